@@ -1,14 +1,34 @@
-use std::{collections::HashSet, fs, path::PathBuf, sync::RwLock};
+use std::{
+    collections::HashSet,
+    fs,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, RwLock},
+    time::Duration,
+};
 
 use tauri::Manager;
 use tauri_plugin_dialog::DialogExt;
 
+use tokio_util::sync::CancellationToken;
+
 use semver::Version;
 
-use crate::restic::{self};
+use crate::{
+    deep_link, diagnostics, diff, logging, presets, preview, protocol,
+    restic::{self},
+    thumbnails,
+};
+
+// -------------------------------------------------------------------------------------------------
+
+/// Max time a password command may take, e.g. while waiting for the user to unlock a vault.
+const PASSWORD_COMMAND_TIMEOUT: Duration = Duration::from_secs(60);
 
 // -------------------------------------------------------------------------------------------------
 
+// Result of mounting a location: failed mounts are remembered to avoid mounting them again.
+type MountSessionResult = Result<Arc<restic::MountSession>, restic::Error>;
+
 /// Internal app state.
 #[derive(Debug, Default, Clone)]
 pub struct AppState {
@@ -16,43 +36,196 @@ pub struct AppState {
     location: restic::Location,
     snapshot_ids: HashSet<String>,
     temp_dir: PathBuf,
+    // cancels all pending async commands of the current location when the location changes
+    cancellation_token: CancellationToken,
+    // opt-in: keep a warm session per opened repository instead of running restic for each
+    // listing. Local repositories use the native reader, all others a `restic mount` process.
+    session_mode: bool,
+    // natively opened repository of the current location, shared by all app state copies
+    native_repository: Arc<Mutex<Option<Arc<restic::NativeRepository>>>>,
+    // mount session of the current location or the error which prevented mounting it
+    mount_session: Arc<Mutex<Option<MountSessionResult>>>,
+    // serializes mounts, so the mount session itself is only locked while it gets accessed
+    mount_lock: Arc<Mutex<()>>,
+    // pending deep link or open request args, until the frontend resolved it
+    open_request: Option<deep_link::OpenRequest>,
 }
 
 impl AppState {
-    pub fn new(restic: restic::Program, location: restic::Location, temp_dir: PathBuf) -> Self {
+    pub fn new(
+        restic: restic::Program,
+        location: restic::Location,
+        temp_dir: PathBuf,
+        session_mode: bool,
+    ) -> Self {
         let snapshot_ids = HashSet::default();
+        let cancellation_token = CancellationToken::new();
+        let native_repository = Arc::default();
+        let mount_session = Arc::default();
+        let mount_lock = Arc::default();
         Self {
             restic,
             location,
             snapshot_ids,
             temp_dir,
+            cancellation_token,
+            session_mode,
+            native_repository,
+            mount_session,
+            mount_lock,
+            open_request: None,
         }
     }
 
+    pub fn restic(&self) -> &restic::Program {
+        &self.restic
+    }
+
+    pub fn location(&self) -> &restic::Location {
+        &self.location
+    }
+
     pub fn temp_dir(&self) -> &PathBuf {
         &self.temp_dir
     }
 
-    pub fn verify_restic_path(&self) -> Result<(), String> {
+    /// Returns true if the current location gets listed with the native reader.
+    pub fn uses_native_reader(&self) -> bool {
+        restic::NativeRepository::supports(&self.location)
+            && (self.location.native_reader || self.session_mode)
+    }
+
+    /// Returns true if the current location gets listed via a mount session.
+    pub fn uses_mount_session(&self) -> bool {
+        self.session_mode && !self.uses_native_reader() && !self.location.path.is_empty()
+    }
+
+    /// The natively opened repository of the current location, when the location uses the
+    /// native reader. Gets opened on first use.
+    pub fn native_repository(
+        &self,
+    ) -> Result<Option<Arc<restic::NativeRepository>>, restic::Error> {
+        if !self.uses_native_reader() {
+            return Ok(None);
+        }
+        let mut native_repository = self
+            .native_repository
+            .lock()
+            .map_err(|err| restic::Error::from(format!("Failed to query app state: {err}")))?;
+        if let Some(repository) = native_repository.as_ref() {
+            return Ok(Some(Arc::clone(repository)));
+        }
+        let password = if self.location.allow_empty_password {
+            String::new()
+        } else if let Some(password_command) = &self.location.password_command {
+            restic::run_password_command(password_command, PASSWORD_COMMAND_TIMEOUT)?
+        } else if let Some(secret_id) = &self.location.password_secret_id {
+            restic::resolve_secret(secret_id)?
+        } else {
+            self.location.password.clone()
+        };
+        let repository = Arc::new(restic::NativeRepository::open(
+            Path::new(&self.location.path),
+            &password,
+        )?);
+        *native_repository = Some(Arc::clone(&repository));
+        Ok(Some(repository))
+    }
+
+    /// The mount session of the current location, when the location uses mount sessions.
+    /// Gets mounted on first use. Locations which failed to mount are not mounted again.
+    pub fn mount_session(&self) -> Result<Option<Arc<restic::MountSession>>, restic::Error> {
+        if !self.uses_mount_session() {
+            return Ok(None);
+        }
+        let lock_error =
+            |err: String| restic::Error::from(format!("Failed to query app state: {err}"));
+        // NB: starting `restic mount` may take up to a minute, so don't block other session
+        // users, e.g. `reset_sessions` or `close_sessions`, while mounting
+        let _mount_lock = self
+            .mount_lock
+            .lock()
+            .map_err(|err| lock_error(err.to_string()))?;
+        if let Some(result) = self
+            .mount_session
+            .lock()
+            .map_err(|err| lock_error(err.to_string()))?
+            .as_ref()
+        {
+            return Ok(result.as_ref().ok().cloned());
+        }
+        let mount_dir = self
+            .temp_dir
+            .join(format!("mount-{}", restic::new_secret_id()));
+        let result = self.restic.mount(&self.location, &mount_dir).map(Arc::new);
+        if let Err(err) = &result {
+            log::warn!("Failed to mount repository, using one-shot commands instead: {err}");
+        }
+        let mut mount_session = self
+            .mount_session
+            .lock()
+            .map_err(|err| lock_error(err.to_string()))?;
+        if mount_session.is_some() {
+            // sessions got closed while mounting: the new session gets unmounted when dropped
+            return Ok(None);
+        }
+        *mount_session = Some(result.clone());
+        Ok(result.ok())
+    }
+
+    /// Forget the native repository and mount session, e.g. after a listing failed, so
+    /// they get reopened on next use.
+    pub fn reset_sessions(&self) {
+        if let Ok(mut native_repository) = self.native_repository.lock() {
+            native_repository.take();
+        }
+        if let Ok(mut mount_session) = self.mount_session.lock() {
+            if mount_session.as_ref().is_some_and(|result| result.is_ok()) {
+                mount_session.take();
+            }
+        }
+    }
+
+    /// Unmount the mount session, if any, e.g. when the app closes. Pending mounts get
+    /// unmounted as soon as they finished.
+    pub fn close_sessions(&self) {
+        if let Ok(mut mount_session) = self.mount_session.lock() {
+            mount_session.replace(Err(restic::Error::cancelled()));
+        }
+    }
+
+    pub fn verify_restic_path(&self) -> Result<(), restic::Error> {
         if self.restic.restic_path().as_os_str().is_empty() {
-            return Err("No restic executable set".to_string());
+            return Err(restic::Error::new(
+                restic::ErrorKind::ResticNotFound,
+                "No restic executable set",
+            ));
         } else if !self.restic.restic_path().exists() {
-            return Err(format!(
-                "Restic executable '{}' does not exist or can not be accessed.",
-                self.restic.restic_path().to_string_lossy()
+            return Err(restic::Error::new(
+                restic::ErrorKind::ResticNotFound,
+                format!(
+                    "Restic executable '{}' does not exist or can not be accessed.",
+                    self.restic.restic_path().to_string_lossy()
+                ),
             ));
         } else if self.restic.restic_version().is_none() {
-            return Err(format!(
-                "Failed to query restic version. Is '{}' a valid restic application?",
-                self.restic.restic_path().to_string_lossy()
+            return Err(restic::Error::new(
+                restic::ErrorKind::ResticNotFound,
+                format!(
+                    "Failed to query restic version. Is '{}' a valid restic application?",
+                    self.restic.restic_path().to_string_lossy()
+                ),
             ));
         }
         Ok(())
     }
 
-    pub fn verify_location(&self) -> Result<(), String> {
+    pub fn verify_location(&self) -> Result<(), restic::Error> {
         if self.location.path.is_empty() {
-            return Err("No repository set".to_string());
+            return Err(restic::Error::new(
+                restic::ErrorKind::InvalidLocation,
+                "No repository set",
+            ));
         }
         if self.location.allow_empty_password
             && self
@@ -61,22 +234,38 @@ impl AppState {
                 .as_ref()
                 .is_some_and(|v| v < &Version::new(0, 17, 0))
         {
-            return Err(format!(
-                "Empty passwords are only supported in restic >= 0.17.0.
+            return Err(restic::Error::new(
+                restic::ErrorKind::InvalidLocation,
+                format!(
+                    "Empty passwords are only supported in restic >= 0.17.0.
 Your installed binary is restic {}",
-                self.restic
-                    .restic_version()
-                    .clone()
-                    .unwrap_or_else(|| Version::new(0, 0, 0))
+                    self.restic
+                        .restic_version()
+                        .clone()
+                        .unwrap_or_else(|| Version::new(0, 0, 0))
+                ),
             ));
         }
+        if let Some(option) = self.location.options.iter().find(|o| !o.is_valid()) {
+            return Err(restic::Error::new(
+                restic::ErrorKind::InvalidLocation,
+                format!("Invalid extended option name: '{}'", option.name),
+            ));
+        }
+        restic::parse_extra_args(&self.location.extra_args)?;
+        for extra_env in &self.location.extra_envs {
+            restic::verify_extra_env(&extra_env.name)?;
+        }
         Ok(())
     }
 
-    pub fn verify_snapshot(&self, snapshot_id: &str) -> Result<(), String> {
-        self.snapshot_ids
-            .get(snapshot_id)
-            .ok_or(format!("Can't resolve snapshot with id {snapshot_id}"))?;
+    pub fn verify_snapshot(&self, snapshot_id: &str) -> Result<(), restic::Error> {
+        self.snapshot_ids.get(snapshot_id).ok_or_else(|| {
+            restic::Error::new(
+                restic::ErrorKind::NotFound,
+                format!("Can't resolve snapshot with id {snapshot_id}"),
+            )
+        })?;
         Ok(())
     }
 }
@@ -97,37 +286,62 @@ impl SharedAppState {
     }
 
     /// return a copy of the current app state.
-    pub fn get(&self) -> Result<AppState, String> {
+    pub fn get(&self) -> Result<AppState, restic::Error> {
         let state = self
             .state
             .try_read()
-            .map_err(|err| format!("Failed to query app state: {err}"))?;
+            .map_err(|err| restic::Error::from(format!("Failed to query app state: {err}")))?;
         Ok(state.clone())
     }
 
     /// update `restic` property in the shared app state.
-    fn update_restic(&self, restic: restic::Program) -> Result<(), String> {
+    fn update_restic(&self, restic: restic::Program) -> Result<(), restic::Error> {
         self.state
             .try_write()
-            .map_err(|err| format!("Failed to update app state: {err}"))?
+            .map_err(|err| restic::Error::from(format!("Failed to update app state: {err}")))?
             .restic = restic;
         Ok(())
     }
 
-    /// update `location` property in the shared app state.
-    fn update_location(&self, location: restic::Location) -> Result<(), String> {
-        self.state
+    /// update `location` property in the shared app state and cancel all pending async
+    /// commands of the previous location.
+    fn update_location(&self, location: restic::Location) -> Result<(), restic::Error> {
+        let mut state = self
+            .state
             .try_write()
-            .map_err(|err| format!("Failed to update app state: {err}"))?
-            .location = location;
+            .map_err(|err| restic::Error::from(format!("Failed to update app state: {err}")))?;
+        state.cancellation_token.cancel();
+        state.cancellation_token = CancellationToken::new();
+        state.native_repository = Arc::default();
+        state.mount_session = Arc::default();
+        state.location = location;
+        Ok(())
+    }
+
+    /// set a new pending open request, replacing unresolved previous ones.
+    pub fn set_open_request(&self, request: deep_link::OpenRequest) -> Result<(), restic::Error> {
+        self.state
+            .write()
+            .map_err(|err| restic::Error::from(format!("Failed to update app state: {err}")))?
+            .open_request = Some(request);
         Ok(())
     }
 
+    /// take the pending open request from the shared app state, if any.
+    fn take_open_request(&self) -> Result<Option<deep_link::OpenRequest>, restic::Error> {
+        Ok(self
+            .state
+            .write()
+            .map_err(|err| restic::Error::from(format!("Failed to update app state: {err}")))?
+            .open_request
+            .take())
+    }
+
     /// update `snapshot_ids` property in the shared app state.
-    fn update_snapshot_ids(&self, snapshot_ids: HashSet<String>) -> Result<(), String> {
+    fn update_snapshot_ids(&self, snapshot_ids: HashSet<String>) -> Result<(), restic::Error> {
         self.state
             .try_write()
-            .map_err(|err| format!("Failed to update app state: {err}"))?
+            .map_err(|err| restic::Error::from(format!("Failed to update app state: {err}")))?
             .snapshot_ids = snapshot_ids;
         Ok(())
     }
@@ -135,28 +349,201 @@ impl SharedAppState {
 
 // -------------------------------------------------------------------------------------------------
 
+// Create an io error with the given context description.
+fn io_error(context: &str, err: std::io::Error) -> restic::Error {
+    restic::Error::new(restic::ErrorKind::Io, format!("{context}: {err}"))
+}
+
+// Run the given listing with the native repository reader or the mount session on a
+// blocking thread, when the current location uses one of them. Returns None when the listing
+// returned None or failed, so callers fall back to running one-shot restic commands.
+async fn run_in_session<T, F>(state: &AppState, listing: F) -> Option<T>
+where
+    T: Send + 'static,
+    F: FnOnce(&AppState) -> Result<Option<T>, restic::Error> + Send + 'static,
+{
+    if !state.uses_native_reader() && !state.uses_mount_session() {
+        return None;
+    }
+    let state = state.clone();
+    let result = tauri::async_runtime::spawn_blocking(move || {
+        let result = listing(&state);
+        if result.is_err() {
+            state.reset_sessions();
+        }
+        result
+    })
+    .await
+    .map_err(|err| restic::Error::from(err.to_string()))
+    .and_then(|result| result);
+    match result {
+        Ok(value) => value,
+        Err(err) => {
+            log::warn!("Session listing failed, falling back to restic: {err}");
+            None
+        }
+    }
+}
+
+// -------------------------------------------------------------------------------------------------
+
+#[tauri::command]
+pub fn open_file_or_url(path: String) -> Result<(), restic::Error> {
+    Ok(open::that(path)?)
+}
+
+#[tauri::command]
+pub fn get_log_path(app: tauri::AppHandle) -> Result<String, restic::Error> {
+    let log_dir = app
+        .path()
+        .app_log_dir()
+        .map_err(|err| restic::Error::from(format!("Failed to resolve log directory: {err}")))?;
+    Ok(logging::log_file_path(&log_dir)
+        .to_string_lossy()
+        .to_string())
+}
+
 #[tauri::command]
-pub fn open_file_or_url(path: String) -> Result<(), String> {
-    open::that(path).map_err(|err| err.to_string())
+pub fn open_log_folder(app: tauri::AppHandle) -> Result<(), restic::Error> {
+    let log_dir = app
+        .path()
+        .app_log_dir()
+        .map_err(|err| restic::Error::from(format!("Failed to resolve log directory: {err}")))?;
+    Ok(open::that(log_dir)?)
 }
 
 #[tauri::command]
-pub fn supported_repo_location_types() -> Result<Vec<restic::LocationTypeInfo>, String> {
+pub fn supported_repo_location_types() -> Result<Vec<restic::LocationTypeInfo>, restic::Error> {
     Ok(restic::supported_location_types())
 }
 
 #[tauri::command]
 pub fn default_repo_location(
     app_state: tauri::State<SharedAppState>,
-) -> Result<restic::Location, String> {
+) -> Result<restic::Location, restic::Error> {
     Ok(app_state.get()?.location)
 }
 
+#[tauri::command(async)]
+pub fn store_secret(id: Option<String>, secret: String) -> Result<String, restic::Error> {
+    // create a new id for new secrets or update the existing one
+    let id = id
+        .filter(|id| !id.is_empty())
+        .unwrap_or_else(restic::new_secret_id);
+    log::info!("Storing secret '{id}'...");
+    restic::secret_store()?.set(&id, &secret)?;
+    Ok(id)
+}
+
+#[tauri::command(async)]
+pub fn has_secret(id: String) -> Result<bool, restic::Error> {
+    Ok(restic::secret_store()?.get(&id)?.is_some())
+}
+
+#[tauri::command(async)]
+pub fn delete_secret(id: String) -> Result<(), restic::Error> {
+    log::info!("Deleting secret '{id}'...");
+    restic::secret_store()?.delete(&id)
+}
+
+#[tauri::command(async)]
+pub fn load_profile_locations(
+    file: Option<String>,
+) -> Result<Vec<restic::ProfileLocation>, restic::Error> {
+    let file = file
+        .filter(|file| !file.is_empty())
+        .map(PathBuf::from)
+        .or_else(restic::find_profile_file)
+        .ok_or_else(|| restic::Error::new(restic::ErrorKind::NotFound, "No profile file found"))?;
+    log::info!("Loading profiles from '{}'...", file.display());
+    restic::load_profiles(&file)
+}
+
+#[tauri::command(async)]
+pub fn get_location_presets(
+    preset_store: tauri::State<presets::PresetStore>,
+) -> Result<Vec<presets::LocationPreset>, restic::Error> {
+    preset_store.presets()
+}
+
+#[tauri::command(async)]
+pub fn add_location_preset(
+    name: String,
+    location: restic::Location,
+    preset_store: tauri::State<presets::PresetStore>,
+) -> Result<presets::LocationPreset, restic::Error> {
+    log::info!("Adding location preset '{name}'...");
+    preset_store.add(name, location)
+}
+
+#[tauri::command(async)]
+pub fn update_location_preset(
+    preset: presets::LocationPreset,
+    preset_store: tauri::State<presets::PresetStore>,
+) -> Result<(), restic::Error> {
+    preset_store.update(preset)
+}
+
+#[tauri::command(async)]
+pub fn remove_location_preset(
+    id: String,
+    preset_store: tauri::State<presets::PresetStore>,
+) -> Result<(), restic::Error> {
+    log::info!("Removing location preset '{id}'...");
+    preset_store.remove(&id)
+}
+
+#[tauri::command(async)]
+pub fn reorder_location_presets(
+    ids: Vec<String>,
+    preset_store: tauri::State<presets::PresetStore>,
+) -> Result<(), restic::Error> {
+    preset_store.reorder(&ids)
+}
+
+#[tauri::command(async)]
+pub fn location_presets_with_commands(file: String) -> Result<Vec<String>, restic::Error> {
+    presets::presets_with_commands(&PathBuf::from(file))
+}
+
+#[tauri::command(async)]
+pub fn import_location_presets(
+    file: String,
+    allow_commands: bool,
+    preset_store: tauri::State<presets::PresetStore>,
+) -> Result<Vec<presets::LocationPreset>, restic::Error> {
+    log::info!("Importing location presets from '{file}'...");
+    preset_store.import(&PathBuf::from(file), allow_commands)
+}
+
+#[tauri::command(async)]
+pub fn export_location_presets(
+    file: String,
+    ids: Option<Vec<String>>,
+    secrets: presets::ExportSecrets,
+    preset_store: tauri::State<presets::PresetStore>,
+) -> Result<(), restic::Error> {
+    log::info!("Exporting location presets to '{file}'...");
+    preset_store.export(&PathBuf::from(file), ids, secrets)
+}
+
+#[tauri::command(async)]
+pub fn resolve_open_request(
+    app_state: tauri::State<SharedAppState>,
+    preset_store: tauri::State<presets::PresetStore>,
+) -> Result<Option<deep_link::ResolvedOpenRequest>, restic::Error> {
+    let Some(request) = app_state.take_open_request()? else {
+        return Ok(None);
+    };
+    log::info!("Resolving open request {request:?}...");
+    deep_link::resolve_open_request(request, &preset_store).map(Some)
+}
+
 #[tauri::command(async)] // NB: async! not on main thread, else the dialogs may freeze
 pub fn verify_restic_path(
     app_state: tauri::State<SharedAppState>,
     app_window: tauri::Window,
-) -> Result<(), String> {
+) -> Result<(), restic::Error> {
     // verify that restic binary is set
     let state = app_state.get()?;
     if !state.restic.restic_path().exists() {
@@ -189,35 +576,75 @@ Please select your installed restic binary manually in the following dialog.",
     Ok(())
 }
 
-#[tauri::command]
+#[tauri::command(async)]
 pub fn open_repository(
     location: restic::Location,
     app_state: tauri::State<SharedAppState>,
-) -> Result<(), String> {
+) -> Result<(), restic::Error> {
     log::info!("Opening repository: '{}'...", location.path);
     // unwrap app state
     let state = app_state.get()?;
     state.verify_restic_path()?;
-    // update location in app state
-    app_state.update_location(location)
+    // verify that the password command works, before restic runs it for every command
+    if let Some(password_command) = &location.password_command {
+        log::info!("Verifying password command...");
+        restic::run_password_command(password_command, PASSWORD_COMMAND_TIMEOUT)?;
+    }
+    // update location in app state and stop streaming files from the previous location
+    app_state.update_location(location)?;
+    protocol::close_open_streams();
+    Ok(())
 }
 
 #[tauri::command(async)]
-pub fn get_snapshots(
+pub fn test_location(
+    location: restic::Location,
     app_state: tauri::State<SharedAppState>,
-) -> Result<Vec<restic::Snapshot>, String> {
+) -> Result<diagnostics::LocationDiagnostics, restic::Error> {
+    log::info!("Testing repository: '{}'...", location.path);
+    // verify the location to test, without changing the app's current location
+    let mut state = app_state.get()?;
+    state.location = location;
+    state.verify_location()?;
+    Ok(diagnostics::test_location(
+        state.restic(),
+        state.location(),
+        state.verify_restic_path(),
+    ))
+}
+
+#[tauri::command]
+pub async fn get_snapshots(
+    app_state: tauri::State<'_, SharedAppState>,
+) -> Result<Vec<restic::Snapshot>, restic::Error> {
     // unwrap app state
     let state = app_state.get()?;
     state.verify_restic_path()?;
     state.verify_location()?;
     // run command
     log::info!("Fetching snapshots from repository...");
-    let command_output = state
-        .restic
-        .run(&state.location, &["snapshots", "--json"], "fetch_snapshots")
-        .map_err(|err| err.to_string())?;
-    let snapshots = serde_json::from_str::<Vec<restic::Snapshot>>(&command_output)
-        .map_err(|err| err.to_string())?;
+    let native_snapshots = run_in_session(&state, |state| {
+        state
+            .native_repository()?
+            .map(|repository| repository.snapshots())
+            .transpose()
+    })
+    .await;
+    let snapshots = match native_snapshots {
+        Some(snapshots) => snapshots,
+        None => {
+            let command_output = state
+                .restic
+                .run_async(
+                    &state.location,
+                    &["snapshots", "--json"],
+                    "fetch_snapshots",
+                    &state.cancellation_token,
+                )
+                .await?;
+            serde_json::from_str::<Vec<restic::Snapshot>>(&command_output)?
+        }
+    };
     // update snapshot cache
     let mut snapshot_ids = HashSet::new();
     for v in &snapshots {
@@ -228,12 +655,12 @@ pub fn get_snapshots(
     Ok(snapshots)
 }
 
-#[tauri::command(async)]
-pub fn get_files(
+#[tauri::command]
+pub async fn get_files(
     snapshot_id: String,
     path: String,
-    app_state: tauri::State<SharedAppState>,
-) -> Result<Vec<restic::File>, String> {
+    app_state: tauri::State<'_, SharedAppState>,
+) -> Result<Vec<restic::File>, restic::Error> {
     // unwrap app state
     let state = app_state.get()?;
     state.verify_restic_path()?;
@@ -245,24 +672,32 @@ pub fn get_files(
         snapshot_id,
         path
     );
+    let session_listing = {
+        let (snapshot_id, path) = (snapshot_id.clone(), path.clone());
+        run_in_session(&state, move |state| {
+            if let Some(repository) = state.native_repository()? {
+                return repository.ls(&snapshot_id, &path).map(Some);
+            }
+            state
+                .mount_session()?
+                .map(|session| session.ls(&snapshot_id, &path))
+                .transpose()
+        })
+        .await
+    };
+    if let Some(files) = session_listing {
+        return Ok(files);
+    }
     let command_output = state
         .restic
-        .run(
+        .run_async(
             &state.location,
             &["ls", &snapshot_id, "--json", &path],
             "fetch_files",
+            &state.cancellation_token,
         )
-        .map_err(|err| err.to_string())?;
-    let mut files = vec![];
-    for line in command_output.split('\n').skip(1) {
-        if line.is_empty() || !line.starts_with('{') {
-            // Skip first/blank/malformed lines
-            continue;
-        }
-        let file = serde_json::from_str::<restic::File>(line).map_err(|err| err.to_string())?;
-        files.push(file);
-    }
-    Ok(files)
+        .await?;
+    restic::File::parse_ls_output(&command_output)
 }
 
 #[tauri::command(async)]
@@ -271,7 +706,7 @@ pub fn dump_file(
     file: restic::File,
     app_state: tauri::State<SharedAppState>,
     app_window: tauri::Window,
-) -> Result<String, String> {
+) -> Result<String, restic::Error> {
     // unwrap app state
     let state = app_state.get()?;
     state.verify_restic_path()?;
@@ -311,10 +746,11 @@ Are you sure that you want to overwrite the existing file(s)?",
             return Err(format!(
                 "target file '{}' already exists",
                 target_file_name.display()
-            ));
+            )
+            .into());
         }
         fs::remove_file(target_file_name.clone())
-            .map_err(|err| format!("Failed to remove target file: {err}"))?;
+            .map_err(|err| io_error("Failed to remove target file", err))?;
     }
     // run dump command
     log::info!(
@@ -323,16 +759,13 @@ Are you sure that you want to overwrite the existing file(s)?",
         snapshot_id
     );
     let target_file = fs::File::create(target_file_name.clone())
-        .map_err(|err| format!("Failed to create target file: {err}"))?;
-    state
-        .restic
-        .run_redirected(
-            &state.location,
-            &["dump", "-a", "zip", &snapshot_id, &file.path],
-            target_file,
-            None,
-        )
-        .map_err(|err| err.to_string())?;
+        .map_err(|err| io_error("Failed to create target file", err))?;
+    state.restic.run_redirected(
+        &state.location,
+        &["dump", "-a", "zip", &snapshot_id, &file.path],
+        target_file,
+        None,
+    )?;
     Ok(target_file_name.to_string_lossy().to_string())
 }
 
@@ -342,7 +775,7 @@ pub fn dump_file_to_temp(
     file: restic::File,
     app_state: tauri::State<SharedAppState>,
     _app_window: tauri::Window,
-) -> Result<String, String> {
+) -> Result<String, restic::Error> {
     // unwrap app state
     let state = app_state.get()?;
     state.verify_restic_path()?;
@@ -355,32 +788,160 @@ pub fn dump_file_to_temp(
         state.temp_dir().join(file.name.clone())
     };
     let target_file = fs::File::create(target_file_name.clone())
-        .map_err(|err| format!("Failed to create target file: {err}"))?;
+        .map_err(|err| io_error("Failed to create target file", err))?;
     // run dump command
     log::info!(
         "Previewing file '{}' from snapshot '{}'...",
         file.name,
         snapshot_id
     );
-    state
-        .restic
-        .run_redirected(
-            &state.location,
-            &["dump", "-a", "zip", &snapshot_id, &file.path],
-            target_file,
-            None,
-        )
-        .map_err(|err| err.to_string())?;
+    state.restic.run_redirected(
+        &state.location,
+        &["dump", "-a", "zip", &snapshot_id, &file.path],
+        target_file,
+        None,
+    )?;
     Ok(target_file_name.to_string_lossy().to_string())
 }
 
+#[tauri::command(async)]
+pub fn preview_file(
+    snapshot_id: String,
+    file: restic::File,
+    max_bytes: usize,
+    app_state: tauri::State<SharedAppState>,
+) -> Result<preview::FilePreview, restic::Error> {
+    // unwrap app state
+    let state = app_state.get()?;
+    state.verify_restic_path()?;
+    state.verify_location()?;
+    state.verify_snapshot(&snapshot_id)?;
+    // run dump command
+    log::info!(
+        "Previewing {} bytes of file '{}' from snapshot '{}'...",
+        max_bytes,
+        file.name,
+        snapshot_id
+    );
+    preview::preview_file(
+        &state.restic,
+        &state.location,
+        &snapshot_id,
+        &file,
+        max_bytes,
+    )
+}
+
+#[tauri::command]
+pub async fn get_thumbnail(
+    snapshot_id: String,
+    file: restic::File,
+    size: u32,
+    app_state: tauri::State<'_, SharedAppState>,
+    thumbnail_generator: tauri::State<'_, thumbnails::ThumbnailGenerator>,
+) -> Result<String, restic::Error> {
+    // unwrap app state
+    let state = app_state.get()?;
+    state.verify_restic_path()?;
+    state.verify_location()?;
+    state.verify_snapshot(&snapshot_id)?;
+    // generate or fetch cached thumbnail
+    thumbnail_generator
+        .thumbnail(&state.restic, &state.location, &snapshot_id, &file, size)
+        .await
+}
+
+#[tauri::command]
+pub fn cancel_thumbnails(
+    thumbnail_generator: tauri::State<thumbnails::ThumbnailGenerator>,
+) -> Result<(), restic::Error> {
+    log::debug!("Cancelling pending thumbnail requests...");
+    thumbnail_generator.cancel()
+}
+
+#[tauri::command]
+pub fn list_jobs() -> Result<Vec<restic::JobInfo>, restic::Error> {
+    Ok(restic::list_jobs())
+}
+
+#[tauri::command(async)]
+pub fn get_operation_history(
+    limit: Option<usize>,
+) -> Result<Vec<restic::OperationRecord>, restic::Error> {
+    restic::operation_history(limit)
+}
+
+#[tauri::command(async)]
+pub fn export_operation_history(file: String) -> Result<(), restic::Error> {
+    log::info!("Exporting operation history to '{file}'...");
+    restic::export_operation_history(&PathBuf::from(file))
+}
+
+#[tauri::command(async)]
+pub fn compare_with_local(
+    snapshot_id: String,
+    file: restic::File,
+    local_path: Option<String>,
+    app_state: tauri::State<SharedAppState>,
+) -> Result<diff::LocalFileComparison, restic::Error> {
+    // unwrap app state
+    let state = app_state.get()?;
+    state.verify_restic_path()?;
+    state.verify_location()?;
+    state.verify_snapshot(&snapshot_id)?;
+    // compare files
+    log::info!(
+        "Comparing file '{}' from snapshot '{}' with local file...",
+        file.path,
+        snapshot_id
+    );
+    diff::compare_with_local(
+        &state.restic,
+        &state.location,
+        &snapshot_id,
+        &file,
+        local_path
+            .filter(|path| !path.is_empty())
+            .map(PathBuf::from),
+    )
+}
+
+#[tauri::command(async)]
+pub fn diff_file_contents(
+    snapshot_a: String,
+    snapshot_b: String,
+    path: String,
+    app_state: tauri::State<SharedAppState>,
+) -> Result<diff::ContentDiff, restic::Error> {
+    // unwrap app state
+    let state = app_state.get()?;
+    state.verify_restic_path()?;
+    state.verify_location()?;
+    state.verify_snapshot(&snapshot_a)?;
+    state.verify_snapshot(&snapshot_b)?;
+    // compare files
+    log::info!(
+        "Comparing file '{}' from snapshots '{}' and '{}'...",
+        path,
+        snapshot_a,
+        snapshot_b
+    );
+    diff::compare_snapshot_files(
+        &state.restic,
+        &state.location,
+        &snapshot_a,
+        &snapshot_b,
+        &path,
+    )
+}
+
 #[tauri::command(async)]
 pub fn restore_file(
     snapshot_id: String,
     file: restic::File,
     app_state: tauri::State<SharedAppState>,
     app_window: tauri::Window,
-) -> Result<String, String> {
+) -> Result<String, restic::Error> {
     // unwrap app state
     let state = app_state.get()?;
     state.verify_restic_path()?;
@@ -414,7 +975,8 @@ Are you sure that you want to overwrite the existing file(s)?",
             return Err(format!(
                 "target file or directory '{}' already exists",
                 target_file_name.display()
-            ));
+            )
+            .into());
         }
     }
     // run restore command
@@ -423,20 +985,17 @@ Are you sure that you want to overwrite the existing file(s)?",
         file.name,
         snapshot_id
     );
-    state
-        .restic
-        .run(
-            &state.location,
-            &[
-                "restore",
-                &snapshot_id,
-                "--target",
-                &target_file_name.to_string_lossy(),
-                "--include",
-                &file.path,
-            ],
-            None,
-        )
-        .map_err(|err| err.to_string())?;
+    state.restic.run(
+        &state.location,
+        &[
+            "restore",
+            &snapshot_id,
+            "--target",
+            &target_file_name.to_string_lossy(),
+            "--include",
+            &file.path,
+        ],
+        None,
+    )?;
     Ok(target_file_name.to_string_lossy().to_string())
 }
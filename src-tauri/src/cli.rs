@@ -0,0 +1,549 @@
+use std::{collections::BTreeMap, path::PathBuf};
+
+use tauri_plugin_cli::{ArgData, Matches, SubcommandMatches};
+
+use crate::{app, restic};
+
+// -------------------------------------------------------------------------------------------------
+
+/// Output format of headless subcommands.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum OutputFormat {
+    /// Human readable, column aligned tables.
+    Table,
+    /// Pretty printed JSON, for scripts.
+    Json,
+}
+
+/// A single snapshot's matches, as dumped by the restic binary via `restic find --json`.
+#[derive(serde::Serialize, serde::Deserialize, Debug, Default, Clone)]
+struct FindResult {
+    #[serde(default)]
+    snapshot: String,
+    #[serde(default)]
+    hits: u64,
+    #[serde(default)]
+    matches: Vec<FindMatch>,
+}
+
+/// A single file match, as dumped by the restic binary via `restic find --json`.
+#[derive(serde::Serialize, serde::Deserialize, Debug, Default, Clone)]
+struct FindMatch {
+    path: String,
+    #[serde(rename = "type")]
+    type_: String,
+    #[serde(default)]
+    size: i64,
+    #[serde(default)]
+    mtime: String,
+}
+
+// -------------------------------------------------------------------------------------------------
+
+/// The app's command line config, as defined for tauri's cli plugin in `tauri.conf.json`.
+#[derive(serde::Deserialize, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+struct CliConfig {
+    #[serde(default)]
+    description: Option<String>,
+    #[serde(default)]
+    long_description: Option<String>,
+    #[serde(default)]
+    args: Vec<CliArg>,
+    #[serde(default)]
+    subcommands: BTreeMap<String, CliConfig>,
+}
+
+/// A single arg of a [`CliConfig`].
+#[derive(serde::Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct CliArg {
+    name: String,
+    #[serde(default)]
+    short: Option<char>,
+    #[serde(default)]
+    takes_value: bool,
+    #[serde(default)]
+    multiple: bool,
+    #[serde(default)]
+    required: bool,
+    #[serde(default)]
+    index: Option<usize>,
+    #[serde(default)]
+    description: Option<String>,
+    #[serde(default)]
+    long_description: Option<String>,
+}
+
+impl CliConfig {
+    /// Load the cli plugin config from the bundled `tauri.conf.json`.
+    fn load() -> Self {
+        let config: serde_json::Value =
+            serde_json::from_str(include_str!("../tauri.conf.json")).unwrap_or_default();
+        config
+            .pointer("/plugins/cli")
+            .and_then(|cli| serde_json::from_value(cli.clone()).ok())
+            .unwrap_or_default()
+    }
+
+    /// Create a clap command for this config, just like tauri's cli plugin does.
+    fn command(&self, name: &str) -> clap::Command {
+        let mut command = clap::Command::new(name.to_string()).version(env!("CARGO_PKG_VERSION"));
+        if let Some(description) = &self.description {
+            command = command.about(description.clone());
+        }
+        if let Some(long_description) = &self.long_description {
+            command = command.long_about(long_description.clone());
+        }
+        for arg in &self.args {
+            let mut clap_arg = clap::Arg::new(arg.name.clone()).required(arg.required);
+            if let Some(index) = arg.index {
+                clap_arg = clap_arg.index(index);
+            } else {
+                clap_arg = clap_arg.long(arg.name.clone());
+                if let Some(short) = arg.short {
+                    clap_arg = clap_arg.short(short);
+                }
+            }
+            if let Some(description) = &arg.description {
+                clap_arg = clap_arg.help(description.clone());
+            }
+            if let Some(long_description) = &arg.long_description {
+                clap_arg = clap_arg.long_help(long_description.clone());
+            }
+            clap_arg = clap_arg.action(if arg.multiple {
+                clap::ArgAction::Append
+            } else if arg.takes_value {
+                clap::ArgAction::Set
+            } else {
+                clap::ArgAction::Count
+            });
+            command = command.arg(clap_arg);
+        }
+        for (name, subcommand) in &self.subcommands {
+            command = command.subcommand(subcommand.command(name));
+        }
+        command
+    }
+
+    /// Convert parsed clap matches to the cli plugin's matches, so subcommands see the same
+    /// values as the app: string or string arrays for args with values, else bools.
+    // NB: the plugin's match types are non exhaustive, so they can't be created with initializers
+    #[allow(clippy::field_reassign_with_default)]
+    fn matches(&self, arg_matches: &clap::ArgMatches) -> Matches {
+        let mut matches = Matches::default();
+        for arg in &self.args {
+            let mut arg_data = ArgData::default();
+            if arg.takes_value {
+                let values = arg_matches
+                    .get_many::<String>(&arg.name)
+                    .map(|values| values.cloned().collect::<Vec<_>>())
+                    .unwrap_or_default();
+                arg_data.occurrences = values.len().min(u8::MAX as usize) as u8;
+                arg_data.value = if values.is_empty() {
+                    serde_json::Value::Null
+                } else if arg.multiple {
+                    serde_json::Value::from(values)
+                } else {
+                    serde_json::Value::from(values[0].clone())
+                };
+            } else {
+                arg_data.occurrences = arg_matches.get_count(&arg.name);
+                arg_data.value = serde_json::Value::Bool(arg_data.occurrences > 0);
+            }
+            matches.args.insert(arg.name.clone(), arg_data);
+        }
+        if let Some((name, sub_arg_matches)) = arg_matches.subcommand() {
+            if let Some(config) = self.subcommands.get(name) {
+                let mut subcommand = SubcommandMatches::default();
+                subcommand.name = name.to_string();
+                subcommand.matches = config.matches(sub_arg_matches);
+                matches.subcommand = Some(Box::new(subcommand));
+            }
+        }
+        matches
+    }
+}
+
+/// Parse the given process args with the app's cli config, before the app gets created.
+///
+/// Returns the parsed matches when the args contain a headless subcommand, else None, so the
+/// app and its cli plugin handle GUI args as usual. Returns an error for invalid subcommand args
+/// and for help and version requests, which clap prints on exit.
+pub fn parse_subcommand_args(args: &[String]) -> Result<Option<Matches>, clap::Error> {
+    let config = CliConfig::load();
+    let command = config.command(env!("CARGO_PKG_NAME"));
+    match command.try_get_matches_from(args) {
+        Ok(arg_matches) if arg_matches.subcommand().is_some() => {
+            Ok(Some(config.matches(&arg_matches)))
+        }
+        Ok(_) => Ok(None),
+        Err(err) => {
+            let has_subcommand = args
+                .iter()
+                .skip(1)
+                .any(|arg| config.subcommands.contains_key(arg));
+            let is_help_or_version = matches!(
+                err.kind(),
+                clap::error::ErrorKind::DisplayHelp | clap::error::ErrorKind::DisplayVersion
+            );
+            if has_subcommand || is_help_or_version {
+                Err(err)
+            } else {
+                Ok(None)
+            }
+        }
+    }
+}
+
+/// The app's data directory for headless subcommands, which run without creating the app:
+/// the directory tauri's `app_data_dir` resolves for the bundled app identifier.
+pub fn app_data_dir() -> Option<PathBuf> {
+    let config: serde_json::Value =
+        serde_json::from_str(include_str!("../tauri.conf.json")).ok()?;
+    let identifier = config.get("identifier")?.as_str()?;
+    Some(dirs::data_dir()?.join(identifier))
+}
+
+// -------------------------------------------------------------------------------------------------
+
+/// Run the given headless subcommand, e.g. `restic-browser --repo X ls latest /home`, for the
+/// app state's location and print its output to stdout.
+///
+/// Returns the process exit code: 0 on success, else restic's exit code or 1.
+pub fn run_subcommand(state: &app::AppState, subcommand: &SubcommandMatches) -> i32 {
+    let matches = &subcommand.matches;
+    let format = if flag(matches, "json") {
+        OutputFormat::Json
+    } else {
+        OutputFormat::Table
+    };
+    let result = state
+        .verify_restic_path()
+        .and_then(|_| state.verify_location())
+        .and_then(|_| match subcommand.name.as_str() {
+            "snapshots" => snapshots(state, format),
+            "ls" => ls(state, matches, format),
+            "find" => find(state, matches, format),
+            "restore" => restore(state, matches, format),
+            name => Err(restic::Error::new(
+                restic::ErrorKind::Failed,
+                format!("Unknown subcommand '{name}'"),
+            )),
+        });
+    match result {
+        Ok(()) => 0,
+        Err(err) => {
+            eprintln!("Error: {err}");
+            if let Some(stderr) = err.stderr.as_deref().filter(|stderr| !stderr.is_empty()) {
+                eprintln!("{stderr}");
+            }
+            err.exit_code.filter(|code| *code != 0).unwrap_or(1)
+        }
+    }
+}
+
+// -------------------------------------------------------------------------------------------------
+
+// List all snapshots of the repository.
+fn snapshots(state: &app::AppState, format: OutputFormat) -> Result<(), restic::Error> {
+    let output = state
+        .restic()
+        .run(state.location(), &["snapshots", "--json"], None)?;
+    let snapshots = serde_json::from_str::<Vec<restic::Snapshot>>(&output)?;
+    match format {
+        OutputFormat::Json => print_json(&snapshots),
+        OutputFormat::Table => {
+            print_table(
+                &["ID", "Time", "Host", "Tags", "Paths"],
+                snapshots.iter().map(|snapshot| {
+                    vec![
+                        snapshot.short_id.clone(),
+                        format_time(&snapshot.time),
+                        snapshot.hostname.clone(),
+                        snapshot.tags.join(","),
+                        snapshot.paths.join(","),
+                    ]
+                }),
+            );
+            Ok(())
+        }
+    }
+}
+
+// List files at a path in a snapshot.
+fn ls(state: &app::AppState, matches: &Matches, format: OutputFormat) -> Result<(), restic::Error> {
+    let snapshot_id = required_value(matches, "snapshot")?;
+    let path = value(matches, "path").unwrap_or_else(|| "/".to_string());
+    let output = state.restic().run(
+        state.location(),
+        &["ls", &snapshot_id, "--json", &path],
+        None,
+    )?;
+    let files = restic::File::parse_ls_output(&output)?;
+    match format {
+        OutputFormat::Json => print_json(&files),
+        OutputFormat::Table => {
+            print_table(
+                &["Type", "Size", "Modified", "Path"],
+                files.iter().map(|file| {
+                    vec![
+                        file.type_.clone(),
+                        format_size(&file.type_, file.size),
+                        format_time(&file.mtime),
+                        file.path.clone(),
+                    ]
+                }),
+            );
+            Ok(())
+        }
+    }
+}
+
+// Find files matching a pattern in all or a single snapshot.
+fn find(
+    state: &app::AppState,
+    matches: &Matches,
+    format: OutputFormat,
+) -> Result<(), restic::Error> {
+    let pattern = required_value(matches, "pattern")?;
+    let snapshot_id = value(matches, "snapshot");
+    let mut args = vec!["find", "--json"];
+    if let Some(snapshot_id) = &snapshot_id {
+        args.extend(["--snapshot", snapshot_id]);
+    }
+    args.push(&pattern);
+    let output = state.restic().run(state.location(), &args, None)?;
+    // NB: restic prints nothing at all when no snapshot matched
+    let results = if output.trim().is_empty() {
+        vec![]
+    } else {
+        serde_json::from_str::<Vec<FindResult>>(&output)?
+    };
+    match format {
+        OutputFormat::Json => print_json(&results),
+        OutputFormat::Table => {
+            print_table(
+                &["Snapshot", "Type", "Size", "Modified", "Path"],
+                results.iter().flat_map(|result| {
+                    result.matches.iter().map(|found| {
+                        vec![
+                            result.snapshot.chars().take(8).collect(),
+                            found.type_.clone(),
+                            format_size(&found.type_, found.size),
+                            format_time(&found.mtime),
+                            found.path.clone(),
+                        ]
+                    })
+                }),
+            );
+            Ok(())
+        }
+    }
+}
+
+// Restore a file or folder from a snapshot into a target directory.
+fn restore(
+    state: &app::AppState,
+    matches: &Matches,
+    format: OutputFormat,
+) -> Result<(), restic::Error> {
+    let snapshot_id = required_value(matches, "snapshot")?;
+    let path = required_value(matches, "path")?;
+    let target = required_value(matches, "target")?;
+    log::info!("Restoring file '{path}' from snapshot '{snapshot_id}' to '{target}'...");
+    state.restic().run(
+        state.location(),
+        &[
+            "restore",
+            &snapshot_id,
+            "--target",
+            &target,
+            "--include",
+            &path,
+        ],
+        None,
+    )?;
+    match format {
+        OutputFormat::Json => print_json(&serde_json::json!({
+            "snapshot": snapshot_id,
+            "path": path,
+            "target": target,
+        })),
+        OutputFormat::Table => {
+            println!("Restored '{path}' from snapshot '{snapshot_id}' to '{target}'");
+            Ok(())
+        }
+    }
+}
+
+// -------------------------------------------------------------------------------------------------
+
+// String value of the given arg, if it got passed.
+fn value(matches: &Matches, name: &str) -> Option<String> {
+    matches
+        .args
+        .get(name)
+        .and_then(|arg| arg.value.as_str())
+        .map(String::from)
+}
+
+// String value of the given arg or an error when it's missing.
+fn required_value(matches: &Matches, name: &str) -> Result<String, restic::Error> {
+    value(matches, name).ok_or_else(|| {
+        restic::Error::new(
+            restic::ErrorKind::Failed,
+            format!("Missing required argument '{name}'"),
+        )
+    })
+}
+
+// Returns true if the given flag got passed.
+fn flag(matches: &Matches, name: &str) -> bool {
+    matches
+        .args
+        .get(name)
+        .and_then(|arg| arg.value.as_bool())
+        .unwrap_or(false)
+}
+
+// Print the given value as pretty JSON.
+fn print_json<T: serde::Serialize>(value: &T) -> Result<(), restic::Error> {
+    let json = serde_json::to_string_pretty(value)
+        .map_err(|err| restic::Error::from(format!("Failed to serialize output: {err}")))?;
+    println!("{json}");
+    Ok(())
+}
+
+// Print the given rows as column aligned table with a header.
+fn print_table<I: IntoIterator<Item = Vec<String>>>(header: &[&str], rows: I) {
+    let rows = rows.into_iter().collect::<Vec<_>>();
+    let mut widths = header
+        .iter()
+        .map(|column| column.chars().count())
+        .collect::<Vec<_>>();
+    for row in &rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+    let print_row = |cells: &[String]| {
+        let line = cells
+            .iter()
+            .zip(&widths)
+            .map(|(cell, width)| format!("{cell:<width$}"))
+            .collect::<Vec<_>>()
+            .join("  ");
+        println!("{}", line.trim_end());
+    };
+    print_row(
+        &header
+            .iter()
+            .map(|column| column.to_string())
+            .collect::<Vec<_>>(),
+    );
+    print_row(
+        &widths
+            .iter()
+            .map(|width| "-".repeat(*width))
+            .collect::<Vec<_>>(),
+    );
+    for row in &rows {
+        print_row(row);
+    }
+}
+
+// Format a restic RFC 3339 time as date and time in its time zone, without fractions.
+fn format_time(time: &str) -> String {
+    chrono::DateTime::parse_from_rfc3339(time)
+        .map(|time| time.format("%Y-%m-%d %H:%M:%S").to_string())
+        .unwrap_or_else(|_| time.to_string())
+}
+
+// Format a file size for tables. Directories have no size.
+fn format_size(type_: &str, size: i64) -> String {
+    if type_ == "dir" {
+        String::new()
+    } else {
+        size.to_string()
+    }
+}
+
+// -------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn parse_subcommands() {
+        let matches = parse_subcommand_args(&args(&[
+            "restic-browser",
+            "--repo",
+            "/backups",
+            "--host",
+            "a",
+            "--host",
+            "b",
+            "ls",
+            "latest",
+            "/home",
+            "--json",
+        ]))
+        .unwrap()
+        .unwrap();
+        assert_eq!(value(&matches, "repo").as_deref(), Some("/backups"));
+        assert_eq!(matches.args["host"].value, serde_json::json!(["a", "b"]));
+        assert!(!flag(&matches, "session"));
+
+        let subcommand = matches.subcommand.as_deref().unwrap();
+        assert_eq!(subcommand.name, "ls");
+        assert_eq!(
+            value(&subcommand.matches, "snapshot").as_deref(),
+            Some("latest")
+        );
+        assert_eq!(value(&subcommand.matches, "path").as_deref(), Some("/home"));
+        assert!(flag(&subcommand.matches, "json"));
+    }
+
+    #[test]
+    fn ignore_gui_args() {
+        for gui_args in [
+            args(&["restic-browser"]),
+            args(&["restic-browser", "--repo", "/backups", "--session"]),
+            args(&["restic-browser", "--unknown-arg"]),
+        ] {
+            assert!(parse_subcommand_args(&gui_args).unwrap().is_none());
+        }
+    }
+
+    #[test]
+    fn reject_invalid_subcommand_args() {
+        assert!(parse_subcommand_args(&args(&["restic-browser", "ls"])).is_err());
+        assert!(parse_subcommand_args(&args(&["restic-browser", "ls", "latest", "--x"])).is_err());
+    }
+
+    #[test]
+    fn reject_help_and_version_args() {
+        for (args, kind) in [
+            (
+                args(&["restic-browser", "--help"]),
+                clap::error::ErrorKind::DisplayHelp,
+            ),
+            (
+                args(&["restic-browser", "ls", "--help"]),
+                clap::error::ErrorKind::DisplayHelp,
+            ),
+            (
+                args(&["restic-browser", "--version"]),
+                clap::error::ErrorKind::DisplayVersion,
+            ),
+        ] {
+            assert_eq!(parse_subcommand_args(&args).unwrap_err().kind(), kind);
+        }
+    }
+}
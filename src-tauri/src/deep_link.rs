@@ -0,0 +1,352 @@
+use percent_encoding::percent_decode_str;
+
+use crate::{presets, restic};
+
+// -------------------------------------------------------------------------------------------------
+
+/// URL scheme of deep links, as registered with the OS by the deep-link plugin.
+pub const DEEP_LINK_SCHEME: &str = "restic-browser";
+
+/// Event which notifies the frontend about a new pending open request.
+pub const OPEN_REQUEST_EVENT: &str = "open-request";
+
+// -------------------------------------------------------------------------------------------------
+
+/// A request to open the app at a specific preset, snapshot and path, as passed via deep links
+/// such as `restic-browser://open?preset=nas&snapshot=abc123&path=/etc/nginx` or via the
+/// `--preset`, `--snapshot`, `--path`, `--host` and `--tag` args.
+#[derive(serde::Serialize, serde::Deserialize, Debug, Default, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct OpenRequest {
+    /// Id or name of a location preset. When not set, the current location is used.
+    pub preset: Option<String>,
+    /// Snapshot id, short id or "latest", optionally followed by a subfolder, as in restic's
+    /// `snapshot:subfolder` syntax.
+    pub snapshot: Option<String>,
+    /// Absolute path in the snapshot, or a path relative to the snapshot's subfolder.
+    pub path: Option<String>,
+    /// Hosts which filter the "latest" snapshot. Any of them must match.
+    #[serde(default)]
+    pub hosts: Vec<String>,
+    /// Comma separated tag lists which filter the "latest" snapshot. All tags of any of the
+    /// lists must match.
+    #[serde(default)]
+    pub tags: Vec<String>,
+}
+
+impl OpenRequest {
+    /// Returns true if the request does not specify anything to open.
+    pub fn is_empty(&self) -> bool {
+        self.preset.is_none()
+            && self.snapshot.is_none()
+            && self.path.is_none()
+            && self.hosts.is_empty()
+            && self.tags.is_empty()
+    }
+
+    /// Parse a `restic-browser://open?...` deep link.
+    pub fn from_url(url: &str) -> Result<Self, restic::Error> {
+        let invalid_link = |reason: &str| {
+            restic::Error::new(
+                restic::ErrorKind::Failed,
+                format!("Invalid link '{url}': {reason}"),
+            )
+        };
+        let link = url
+            .strip_prefix(DEEP_LINK_SCHEME)
+            .and_then(|link| link.strip_prefix("://"))
+            .ok_or_else(|| invalid_link("unexpected scheme"))?;
+        let (action, query) = link.split_once('?').unwrap_or((link, ""));
+        if action.trim_end_matches('/') != "open" {
+            return Err(invalid_link("unsupported action"));
+        }
+        let mut request = Self::default();
+        for param in query.split('&').filter(|param| !param.is_empty()) {
+            let (name, value) = param.split_once('=').unwrap_or((param, ""));
+            // NB: query values may encode spaces as '+'
+            let value = percent_decode_str(&value.replace('+', " "))
+                .decode_utf8()
+                .map_err(|_| invalid_link("invalid encoding"))?
+                .to_string();
+            if value.is_empty() {
+                continue;
+            }
+            match name {
+                "preset" => request.preset = Some(value),
+                "snapshot" => request.snapshot = Some(value),
+                "path" => request.path = Some(value),
+                "host" => request.hosts.push(value),
+                "tag" => request.tags.push(value),
+                _ => log::warn!("Ignoring unknown link parameter '{name}'"),
+            }
+        }
+        Ok(request)
+    }
+
+    /// Find the first deep link in the given process args, e.g. as passed by the OS on Linux
+    /// and Windows when opening a link, or by a second app instance.
+    pub fn from_args(args: &[String]) -> Option<Self> {
+        let prefix = format!("{DEEP_LINK_SCHEME}://");
+        let url = args.iter().find(|arg| arg.starts_with(&prefix))?;
+        Self::from_url(url)
+            .inspect_err(|err| log::warn!("{err}"))
+            .ok()
+    }
+}
+
+/// An open request with a resolved location, as returned to the frontend.
+#[derive(serde::Serialize, Debug, Default, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ResolvedOpenRequest {
+    /// Id of the preset to select, if the request referred to a preset.
+    pub preset_id: Option<String>,
+    /// Location to open. When not set, the current location is used.
+    pub location: Option<restic::Location>,
+    /// Snapshot id, short id or "latest", without subfolder.
+    pub snapshot: Option<String>,
+    /// Normalized absolute path in the snapshot, including the snapshot's subfolder.
+    pub path: Option<String>,
+    /// Hosts which filter the "latest" snapshot.
+    pub hosts: Vec<String>,
+    /// Comma separated tag lists which filter the "latest" snapshot.
+    pub tags: Vec<String>,
+}
+
+/// Resolve the given request's preset into a location, split its snapshot's subfolder and
+/// normalize its path. Like restic, a given path is relative to the snapshot's subfolder.
+pub fn resolve_open_request(
+    request: OpenRequest,
+    preset_store: &presets::PresetStore,
+) -> Result<ResolvedOpenRequest, restic::Error> {
+    let (snapshot, subfolder) = match request.snapshot.as_deref().map(split_snapshot) {
+        Some((snapshot, subfolder)) => (snapshot, subfolder),
+        None => (None, None),
+    };
+    let path = match (subfolder, request.path) {
+        (Some(subfolder), Some(path)) => Some(format!("{subfolder}/{path}")),
+        (subfolder, path) => subfolder.or(path),
+    };
+    let mut resolved = ResolvedOpenRequest {
+        // NB: host and tag filters select the latest matching snapshot, as in restic
+        snapshot: snapshot.or_else(|| {
+            (!request.hosts.is_empty() || !request.tags.is_empty()).then(|| "latest".to_string())
+        }),
+        path: path.map(|path| normalize_path(&path)),
+        hosts: request.hosts,
+        tags: request.tags,
+        ..Default::default()
+    };
+    if let Some(preset) = request.preset {
+        let presets = preset_store.presets()?;
+        let preset = presets
+            .iter()
+            .find(|candidate| candidate.id == preset)
+            .or_else(|| {
+                presets
+                    .iter()
+                    .find(|candidate| candidate.name.eq_ignore_ascii_case(&preset))
+            })
+            .ok_or_else(|| {
+                restic::Error::new(
+                    restic::ErrorKind::NotFound,
+                    format!("Can't find location preset '{preset}'"),
+                )
+            })?;
+        resolved.preset_id = Some(preset.id.clone());
+        resolved.location = Some(preset.location.clone());
+    }
+    Ok(resolved)
+}
+
+// Split a restic `snapshot:subfolder` snapshot spec into the snapshot and its subfolder.
+// Empty snapshots or subfolders are None.
+fn split_snapshot(snapshot: &str) -> (Option<String>, Option<String>) {
+    let (snapshot, subfolder) = snapshot.split_once(':').unwrap_or((snapshot, ""));
+    let non_empty = |value: &str| (!value.is_empty()).then(|| value.to_string());
+    (non_empty(snapshot.trim()), non_empty(subfolder))
+}
+
+// Convert the given path into an absolute, '/' separated path without trailing separators.
+// `..` segments get resolved, but never leave the snapshot's root.
+fn normalize_path(path: &str) -> String {
+    let path = path.replace('\\', "/");
+    let mut components = vec![];
+    for component in path.split('/') {
+        match component {
+            "" | "." => (),
+            ".." => {
+                components.pop();
+            }
+            component => components.push(component),
+        }
+    }
+    format!("/{}", components.join("/"))
+}
+
+// -------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::PathBuf};
+
+    use super::*;
+
+    fn request(snapshot: Option<&str>, path: Option<&str>) -> OpenRequest {
+        OpenRequest {
+            snapshot: snapshot.map(str::to_string),
+            path: path.map(str::to_string),
+            ..Default::default()
+        }
+    }
+
+    // Create a preset store with a single "NAS" preset in a new temp file.
+    fn preset_store() -> (presets::PresetStore, PathBuf) {
+        let file = std::env::temp_dir().join(format!(
+            "restic-browser-deep-link-test-{}.json",
+            restic::new_secret_id()
+        ));
+        let store = presets::PresetStore::new(file.clone());
+        let location = restic::Location {
+            path: "/backups".to_string(),
+            ..Default::default()
+        };
+        store.add("NAS".to_string(), location).unwrap();
+        (store, file)
+    }
+
+    #[test]
+    fn parse_links() {
+        let parsed = [
+            ("restic-browser://open", Some(OpenRequest::default())),
+            ("restic-browser://open/?", Some(OpenRequest::default())),
+            (
+                "restic-browser://open?preset=my%20nas&snapshot=abc123&path=/etc/nginx+conf",
+                Some(OpenRequest {
+                    preset: Some("my nas".to_string()),
+                    snapshot: Some("abc123".to_string()),
+                    path: Some("/etc/nginx conf".to_string()),
+                    ..Default::default()
+                }),
+            ),
+            (
+                "restic-browser://open?host=a&host=b&tag=x%2Cy&snapshot=&unknown=1",
+                Some(OpenRequest {
+                    hosts: vec!["a".to_string(), "b".to_string()],
+                    tags: vec!["x,y".to_string()],
+                    ..Default::default()
+                }),
+            ),
+            (
+                "restic-browser://open?path=%2Fa%26b%3Dc",
+                Some(request(None, Some("/a&b=c"))),
+            ),
+            // malformed schemes, actions and encodings
+            ("restic-browser:open?path=/etc", None),
+            ("https://open?path=/etc", None),
+            ("RESTIC-BROWSER://open", None),
+            ("restic-browser://delete?path=/etc", None),
+            ("restic-browser://open?path=%FF", None),
+        ];
+        for (url, expected) in parsed {
+            assert_eq!(OpenRequest::from_url(url).ok(), expected, "{url}");
+        }
+    }
+
+    #[test]
+    fn find_links_in_args() {
+        let args = [
+            "restic-browser".to_string(),
+            "--preset".to_string(),
+            "restic-browser://open?snapshot=latest".to_string(),
+        ];
+        assert_eq!(
+            OpenRequest::from_args(&args),
+            Some(request(Some("latest"), None))
+        );
+        assert_eq!(OpenRequest::from_args(&args[..2]), None);
+    }
+
+    #[test]
+    fn split_snapshot_subfolders() {
+        let split = [
+            ("abc123", (Some("abc123"), None)),
+            ("abc123:/home/user", (Some("abc123"), Some("/home/user"))),
+            ("abc123:", (Some("abc123"), None)),
+            (":/home/user", (None, Some("/home/user"))),
+            (":", (None, None)),
+        ];
+        for (snapshot, (expected_snapshot, expected_subfolder)) in split {
+            assert_eq!(
+                split_snapshot(snapshot),
+                (
+                    expected_snapshot.map(str::to_string),
+                    expected_subfolder.map(str::to_string)
+                ),
+                "{snapshot}"
+            );
+        }
+    }
+
+    #[test]
+    fn normalize_paths() {
+        let normalized = [
+            ("", "/"),
+            ("/", "/"),
+            ("etc/nginx/", "/etc/nginx"),
+            ("//etc/./nginx", "/etc/nginx"),
+            ("C:\\Users\\me", "/C:/Users/me"),
+            ("/etc/nginx/../ssh", "/etc/ssh"),
+            ("/etc/../../..", "/"),
+            ("../etc", "/etc"),
+        ];
+        for (path, expected) in normalized {
+            assert_eq!(normalize_path(path), expected, "{path}");
+        }
+    }
+
+    #[test]
+    fn resolve_requests() {
+        let (store, file) = preset_store();
+        let resolve = |request| resolve_open_request(request, &store);
+
+        // paths are relative to the snapshot's subfolder
+        let resolved = resolve(request(Some("abc:/home/me"), Some("docs/../notes"))).unwrap();
+        assert_eq!(resolved.snapshot.as_deref(), Some("abc"));
+        assert_eq!(resolved.path.as_deref(), Some("/home/me/notes"));
+        let resolved = resolve(request(Some(":/home/me"), None)).unwrap();
+        assert_eq!(resolved.snapshot, None);
+        assert_eq!(resolved.path.as_deref(), Some("/home/me"));
+
+        // host and tag filters select the latest snapshot
+        let resolved = resolve(OpenRequest {
+            hosts: vec!["nas".to_string()],
+            ..Default::default()
+        })
+        .unwrap();
+        assert_eq!(resolved.snapshot.as_deref(), Some("latest"));
+        assert_eq!(resolved.location.map(|location| location.path), None);
+
+        // presets resolve by id or case-insensitive name
+        let preset_id = store.presets().unwrap()[0].id.clone();
+        for preset in [preset_id.as_str(), "nas"] {
+            let resolved = resolve(OpenRequest {
+                preset: Some(preset.to_string()),
+                ..Default::default()
+            })
+            .unwrap();
+            assert_eq!(resolved.preset_id.as_deref(), Some(preset_id.as_str()));
+            assert_eq!(
+                resolved.location.map(|location| location.path).as_deref(),
+                Some("/backups")
+            );
+        }
+        let err = resolve(OpenRequest {
+            preset: Some("unknown".to_string()),
+            ..Default::default()
+        })
+        .unwrap_err();
+        assert_eq!(err.kind, restic::ErrorKind::NotFound);
+
+        fs::remove_file(file).unwrap();
+    }
+}
@@ -0,0 +1,463 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use similar::{ChangeTag, TextDiff};
+
+use crate::{preview, restic};
+
+// -------------------------------------------------------------------------------------------------
+
+/// Max size of files which get compared by content: larger files only get a metadata summary.
+pub const MAX_DIFF_FILE_SIZE: u64 = 8 * 1024 * 1024;
+
+/// Number of unchanged context lines around changes in diff hunks.
+const DIFF_CONTEXT_LINES: usize = 3;
+
+/// Max time spent on computing a line diff, before falling back to a less minimal diff.
+const DIFF_TIMEOUT: Duration = Duration::from_secs(5);
+
+// -------------------------------------------------------------------------------------------------
+
+/// Serializable metadata of a compared file.
+#[derive(serde::Serialize, Debug, Default, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct FileMetadata {
+    pub size: u64,
+    /// Unix permission bits, if available.
+    pub mode: Option<u32>,
+    /// Modification time as RFC 3339 string in UTC, if available.
+    pub mtime: Option<String>,
+}
+
+impl FileMetadata {
+    /// Metadata of a file in a restic snapshot.
+    pub fn from_snapshot_file(file: &restic::File) -> Self {
+        let mtime = chrono::DateTime::parse_from_rfc3339(&file.mtime)
+            .ok()
+            .map(|mtime| mtime.with_timezone(&chrono::Utc).to_rfc3339());
+        Self {
+            size: file.size.max(0) as u64,
+            // restic stores Go `os.FileMode` values: the lower bits are the permissions
+            mode: Some((file.mode & 0o7777) as u32),
+            mtime,
+        }
+    }
+
+    /// Metadata of a file in the local file system.
+    pub fn from_local_file(metadata: &fs::Metadata) -> Self {
+        #[cfg(unix)]
+        let mode = {
+            use std::os::unix::fs::PermissionsExt;
+            Some(metadata.permissions().mode() & 0o7777)
+        };
+        #[cfg(not(unix))]
+        let mode = None;
+        let mtime = metadata
+            .modified()
+            .ok()
+            .map(|mtime| chrono::DateTime::<chrono::Utc>::from(mtime).to_rfc3339());
+        Self {
+            size: metadata.len(),
+            mode,
+            mtime,
+        }
+    }
+}
+
+// -------------------------------------------------------------------------------------------------
+
+/// A single line in a diff hunk.
+#[derive(serde::Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct DiffLine {
+    /// "equal", "delete" or "insert".
+    pub tag: &'static str,
+    /// Zero based line index in the old file, unless the line got inserted.
+    pub old_index: Option<usize>,
+    /// Zero based line index in the new file, unless the line got deleted.
+    pub new_index: Option<usize>,
+    pub content: String,
+}
+
+/// A group of changed lines with surrounding context lines.
+#[derive(serde::Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct DiffHunk {
+    /// One based start line and line count in the old file, as in unified diff headers.
+    pub old_start: usize,
+    pub old_lines: usize,
+    /// One based start line and line count in the new file, as in unified diff headers.
+    pub new_start: usize,
+    pub new_lines: usize,
+    pub lines: Vec<DiffLine>,
+}
+
+/// Result of comparing the content of two files.
+#[derive(serde::Serialize, Debug, Clone)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+pub enum ContentDiff {
+    /// Both files have the same content.
+    Identical,
+    /// Line diff of two text files.
+    #[serde(rename_all = "camelCase")]
+    Text {
+        unified_diff: String,
+        hunks: Vec<DiffHunk>,
+        insertions: usize,
+        deletions: usize,
+    },
+    /// Byte level summary of two binary files.
+    #[serde(rename_all = "camelCase")]
+    Binary {
+        /// Offset of the first differing byte.
+        first_difference: u64,
+        /// Number of differing bytes in the range both files have in common.
+        differing_bytes: u64,
+        old_size: u64,
+        new_size: u64,
+    },
+    /// At least one of the files exceeds the size limit.
+    #[serde(rename_all = "camelCase")]
+    TooLarge { old_size: u64, new_size: u64 },
+    /// The file only exists in the new snapshot.
+    #[serde(rename_all = "camelCase")]
+    Added { new_size: u64 },
+    /// The file only exists in the old snapshot.
+    #[serde(rename_all = "camelCase")]
+    Deleted { old_size: u64 },
+    /// The path is not a regular file in at least one of the snapshots, e.g. a directory.
+    /// Node types are restic's "file", "dir", "symlink", ... or None when missing.
+    #[serde(rename_all = "camelCase")]
+    NotAFile {
+        old_type: Option<String>,
+        new_type: Option<String>,
+    },
+}
+
+impl ContentDiff {
+    /// Compare the given file contents. Texts get a line diff, binaries a byte summary.
+    pub fn new(old_name: &str, old_data: &[u8], new_name: &str, new_data: &[u8]) -> Self {
+        if old_data == new_data {
+            return Self::Identical;
+        }
+        let old_text = preview::decode_text(old_data, false);
+        let new_text = preview::decode_text(new_data, false);
+        match (old_text, new_text) {
+            (Some((old_text, _)), Some((new_text, _))) => {
+                Self::text_diff(old_name, &old_text, new_name, &new_text)
+            }
+            _ => Self::binary_diff(old_data, new_data),
+        }
+    }
+
+    fn text_diff(old_name: &str, old_text: &str, new_name: &str, new_text: &str) -> Self {
+        let diff = TextDiff::configure()
+            .timeout(DIFF_TIMEOUT)
+            .diff_lines(old_text, new_text);
+        let mut insertions = 0;
+        let mut deletions = 0;
+        let mut hunks = vec![];
+        for group in diff.grouped_ops(DIFF_CONTEXT_LINES) {
+            let (Some(first), Some(last)) = (group.first(), group.last()) else {
+                continue;
+            };
+            let old_range = first.old_range().start..last.old_range().end;
+            let new_range = first.new_range().start..last.new_range().end;
+            let mut lines = vec![];
+            for op in &group {
+                for change in diff.iter_changes(op) {
+                    let tag = match change.tag() {
+                        ChangeTag::Equal => "equal",
+                        ChangeTag::Delete => {
+                            deletions += 1;
+                            "delete"
+                        }
+                        ChangeTag::Insert => {
+                            insertions += 1;
+                            "insert"
+                        }
+                    };
+                    lines.push(DiffLine {
+                        tag,
+                        old_index: change.old_index(),
+                        new_index: change.new_index(),
+                        content: change.value().trim_end_matches(['\r', '\n']).to_string(),
+                    });
+                }
+            }
+            hunks.push(DiffHunk {
+                old_start: old_range.start + 1,
+                old_lines: old_range.len(),
+                new_start: new_range.start + 1,
+                new_lines: new_range.len(),
+                lines,
+            });
+        }
+        let unified_diff = diff
+            .unified_diff()
+            .context_radius(DIFF_CONTEXT_LINES)
+            .header(old_name, new_name)
+            .to_string();
+        Self::Text {
+            unified_diff,
+            hunks,
+            insertions,
+            deletions,
+        }
+    }
+
+    fn binary_diff(old_data: &[u8], new_data: &[u8]) -> Self {
+        let mut first_difference = None;
+        let mut differing_bytes = 0;
+        for (index, (a, b)) in old_data.iter().zip(new_data.iter()).enumerate() {
+            if a != b {
+                first_difference.get_or_insert(index);
+                differing_bytes += 1;
+            }
+        }
+        // when the common range is equal, the files differ in size only
+        let first_difference =
+            first_difference.unwrap_or(old_data.len().min(new_data.len())) as u64;
+        Self::Binary {
+            first_difference,
+            differing_bytes,
+            old_size: old_data.len() as u64,
+            new_size: new_data.len() as u64,
+        }
+    }
+}
+
+// -------------------------------------------------------------------------------------------------
+
+/// Result of comparing a snapshot file with a file in the local file system.
+#[derive(serde::Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct LocalFileComparison {
+    pub local_path: String,
+    pub snapshot: FileMetadata,
+    /// Metadata of the local file, or None when the local file does not exist.
+    pub local: Option<FileMetadata>,
+    /// Content diff from the snapshot to the local version, when the local file exists.
+    pub content: Option<ContentDiff>,
+}
+
+/// Compare a snapshot file with its local version at `local_path` or, when not set,
+/// at the file's original location.
+pub fn compare_with_local(
+    program: &restic::Program,
+    location: &restic::Location,
+    snapshot_id: &str,
+    file: &restic::File,
+    local_path: Option<PathBuf>,
+) -> Result<LocalFileComparison, restic::Error> {
+    if file.type_ != "file" {
+        return Err(format!("Can't compare '{}': not a file", file.path).into());
+    }
+    let local_path = local_path.unwrap_or_else(|| local_path_from_snapshot_path(&file.path));
+    let snapshot = FileMetadata::from_snapshot_file(file);
+    let Ok(local_metadata) = fs::metadata(&local_path) else {
+        return Ok(LocalFileComparison {
+            local_path: local_path.to_string_lossy().to_string(),
+            snapshot,
+            local: None,
+            content: None,
+        });
+    };
+    if !local_metadata.is_file() {
+        return Err(format!("Local path '{}' is not a file", local_path.display()).into());
+    }
+    let local = FileMetadata::from_local_file(&local_metadata);
+    let too_large = ContentDiff::TooLarge {
+        old_size: snapshot.size,
+        new_size: local.size,
+    };
+    let content = if snapshot.size > MAX_DIFF_FILE_SIZE || local.size > MAX_DIFF_FILE_SIZE {
+        too_large
+    } else if let Some(snapshot_data) =
+        dump_file_contents(program, location, snapshot_id, &file.path)?
+    {
+        let local_data = fs::read(&local_path).map_err(|err| {
+            restic::Error::new(
+                restic::ErrorKind::Io,
+                format!("Failed to read local file: {err}"),
+            )
+        })?;
+        ContentDiff::new(
+            &snapshot_file_name(snapshot_id, &file.path),
+            &snapshot_data,
+            &local_path.to_string_lossy(),
+            &local_data,
+        )
+    } else {
+        too_large
+    };
+    Ok(LocalFileComparison {
+        local_path: local_path.to_string_lossy().to_string(),
+        snapshot,
+        local: Some(local),
+        content: Some(content),
+    })
+}
+
+// -------------------------------------------------------------------------------------------------
+
+/// Compare the content of a file at `path` in two snapshots. Files which exist in one of
+/// the snapshots only are reported as added or deleted, without dumping them.
+pub fn compare_snapshot_files(
+    program: &restic::Program,
+    location: &restic::Location,
+    old_snapshot_id: &str,
+    new_snapshot_id: &str,
+    path: &str,
+) -> Result<ContentDiff, restic::Error> {
+    let old_file = snapshot_file(program, location, old_snapshot_id, path)?;
+    let new_file = snapshot_file(program, location, new_snapshot_id, path)?;
+    let is_file = |file: &Option<restic::File>| file.iter().all(|file| file.type_ == "file");
+    if !is_file(&old_file) || !is_file(&new_file) {
+        return Ok(ContentDiff::NotAFile {
+            old_type: old_file.map(|file| file.type_),
+            new_type: new_file.map(|file| file.type_),
+        });
+    }
+    let size = |file: &restic::File| file.size.max(0) as u64;
+    let (old_file, new_file) = match (old_file, new_file) {
+        (Some(old_file), Some(new_file)) => (old_file, new_file),
+        (None, Some(new_file)) => {
+            return Ok(ContentDiff::Added {
+                new_size: size(&new_file),
+            })
+        }
+        (Some(old_file), None) => {
+            return Ok(ContentDiff::Deleted {
+                old_size: size(&old_file),
+            })
+        }
+        (None, None) => {
+            return Err(restic::Error::new(
+                restic::ErrorKind::NotFound,
+                format!("File '{path}' does not exist in both snapshots"),
+            ))
+        }
+    };
+    let too_large = ContentDiff::TooLarge {
+        old_size: size(&old_file),
+        new_size: size(&new_file),
+    };
+    if size(&old_file) > MAX_DIFF_FILE_SIZE || size(&new_file) > MAX_DIFF_FILE_SIZE {
+        return Ok(too_large);
+    }
+    let old_data = dump_file_contents(program, location, old_snapshot_id, path)?;
+    let new_data = dump_file_contents(program, location, new_snapshot_id, path)?;
+    match (old_data, new_data) {
+        (Some(old_data), Some(new_data)) => Ok(ContentDiff::new(
+            &snapshot_file_name(old_snapshot_id, path),
+            &old_data,
+            &snapshot_file_name(new_snapshot_id, path),
+            &new_data,
+        )),
+        _ => Ok(too_large),
+    }
+}
+
+// -------------------------------------------------------------------------------------------------
+
+// Look up the node at `path` in the given snapshot via `restic ls`. Returns None when the
+// path does not exist in the snapshot.
+fn snapshot_file(
+    program: &restic::Program,
+    location: &restic::Location,
+    snapshot_id: &str,
+    path: &str,
+) -> Result<Option<restic::File>, restic::Error> {
+    let output = program.run(location, &["ls", snapshot_id, "--json", path], None)?;
+    Ok(restic::File::parse_ls_output(&output)?
+        .into_iter()
+        .find(|file| file.path == path))
+}
+
+// Dump the content of a snapshot file into memory.
+// Returns None when the file exceeds the diff size limit.
+fn dump_file_contents(
+    program: &restic::Program,
+    location: &restic::Location,
+    snapshot_id: &str,
+    path: &str,
+) -> Result<Option<Vec<u8>>, restic::Error> {
+    program.run_captured(
+        location,
+        &["dump", snapshot_id, path],
+        MAX_DIFF_FILE_SIZE,
+        None,
+    )
+}
+
+// Name of a snapshot file in diff headers: short snapshot id and path.
+fn snapshot_file_name(snapshot_id: &str, path: &str) -> String {
+    format!("{}:{}", &snapshot_id[..snapshot_id.len().min(8)], path)
+}
+
+// Convert a path in a restic snapshot to a local file system path.
+// On Windows, restic stores paths such as `C:\dir` as `/C/dir`.
+fn local_path_from_snapshot_path(path: &str) -> PathBuf {
+    if cfg!(windows) {
+        let mut components = path.trim_start_matches('/').splitn(2, '/');
+        if let Some(drive) = components.next().filter(|drive| drive.len() == 1) {
+            let rest = components.next().unwrap_or_default();
+            return Path::new(&format!("{drive}:\\")).join(rest.replace('/', "\\"));
+        }
+    }
+    PathBuf::from(path)
+}
+
+// -------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn identical_contents() {
+        assert!(matches!(
+            ContentDiff::new("a", b"same\n", "b", b"same\n"),
+            ContentDiff::Identical
+        ));
+    }
+
+    #[test]
+    fn text_contents_get_line_hunks() {
+        let old = b"one\ntwo\nthree\n";
+        let new = b"one\n2\nthree\nfour\n";
+        let ContentDiff::Text {
+            hunks,
+            insertions,
+            deletions,
+            unified_diff,
+        } = ContentDiff::new("old", old, "new", new)
+        else {
+            panic!("expected a text diff");
+        };
+        assert_eq!((insertions, deletions), (2, 1));
+        assert_eq!(hunks.len(), 1);
+        assert_eq!((hunks[0].old_start, hunks[0].old_lines), (1, 3));
+        assert_eq!((hunks[0].new_start, hunks[0].new_lines), (1, 4));
+        assert!(unified_diff.starts_with("--- old\n+++ new\n"));
+    }
+
+    #[test]
+    fn binary_contents_get_byte_summary() {
+        let ContentDiff::Binary {
+            first_difference,
+            differing_bytes,
+            old_size,
+            new_size,
+        } = ContentDiff::new("old", &[0, 1, 2, 3], "new", &[0, 1, 9, 3, 4])
+        else {
+            panic!("expected a binary diff");
+        };
+        assert_eq!((first_difference, differing_bytes), (2, 1));
+        assert_eq!((old_size, new_size), (4, 5));
+    }
+}
@@ -0,0 +1,225 @@
+use std::{
+    fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use simplelog::{LevelFilter, SharedLogger};
+
+use crate::restic;
+
+// -------------------------------------------------------------------------------------------------
+
+/// Name of the current log file in the app log directory.
+pub const LOG_FILE_NAME: &str = "App.log";
+
+/// Environment variable which sets the log level, when no `--log-level` arg is given.
+pub const LOG_LEVEL_ENV: &str = "RESTIC_BROWSER_LOG";
+
+/// Max size of a log file before it gets rotated.
+const MAX_LOG_FILE_SIZE: u64 = 10 * 1024 * 1024;
+
+/// Number of rotated log files which are kept next to the current log file.
+const MAX_ROTATED_LOG_FILES: usize = 5;
+
+// -------------------------------------------------------------------------------------------------
+
+/// Parse a log level name such as "debug" or "warn". "off" disables logging.
+pub fn parse_log_level(level: &str) -> Option<LevelFilter> {
+    LevelFilter::from_str(level.trim()).ok()
+}
+
+/// Path of the current log file in the given log directory.
+pub fn log_file_path(log_dir: &Path) -> PathBuf {
+    log_dir.join(LOG_FILE_NAME)
+}
+
+// Path of the rotated log file with the given index, e.g. `App.1.log`.
+fn rotated_log_file_path(log_dir: &Path, index: usize) -> PathBuf {
+    let (stem, extension) = LOG_FILE_NAME
+        .rsplit_once('.')
+        .unwrap_or((LOG_FILE_NAME, "log"));
+    log_dir.join(format!("{stem}.{index}.{extension}"))
+}
+
+// Move the current log file to `App.1.log`, shift older rotated files and remove files
+// which exceed the number of retained log files.
+fn rotate_log_files(log_dir: &Path) -> io::Result<()> {
+    let oldest = rotated_log_file_path(log_dir, MAX_ROTATED_LOG_FILES);
+    if oldest.exists() {
+        fs::remove_file(oldest)?;
+    }
+    for index in (1..MAX_ROTATED_LOG_FILES).rev() {
+        let file = rotated_log_file_path(log_dir, index);
+        if file.exists() {
+            fs::rename(file, rotated_log_file_path(log_dir, index + 1))?;
+        }
+    }
+    let current = log_file_path(log_dir);
+    if current.exists() {
+        fs::rename(current, rotated_log_file_path(log_dir, 1))?;
+    }
+    Ok(())
+}
+
+// -------------------------------------------------------------------------------------------------
+
+/// Log file writer which rotates log files on startup and when they exceed a max size,
+/// so logs of previous sessions are retained.
+pub struct RotatingLogFile {
+    log_dir: PathBuf,
+    file: fs::File,
+    size: u64,
+}
+
+impl RotatingLogFile {
+    /// Rotate existing log files in the given directory and create a new log file.
+    pub fn create(log_dir: &Path) -> io::Result<Self> {
+        fs::create_dir_all(log_dir)?;
+        rotate_log_files(log_dir)?;
+        Ok(Self {
+            log_dir: log_dir.to_path_buf(),
+            file: fs::File::create(log_file_path(log_dir))?,
+            size: 0,
+        })
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        self.file.flush()?;
+        rotate_log_files(&self.log_dir)?;
+        self.file = fs::File::create(log_file_path(&self.log_dir))?;
+        self.size = 0;
+        Ok(())
+    }
+}
+
+impl Write for RotatingLogFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.size > 0 && self.size + buf.len() as u64 > MAX_LOG_FILE_SIZE {
+            self.rotate()?;
+        }
+        let bytes_written = self.file.write(buf)?;
+        self.size += bytes_written as u64;
+        Ok(bytes_written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+// -------------------------------------------------------------------------------------------------
+
+/// Logger which redacts secrets, such as passwords in repository URLs, from all log lines
+/// before passing them to the wrapped logger.
+pub struct RedactingLogger<L: SharedLogger> {
+    logger: Box<L>,
+}
+
+impl<L: SharedLogger + 'static> RedactingLogger<L> {
+    /// Install the given logger as global logger, wrapped into a redacting logger.
+    pub fn init(logger: Box<L>) -> Result<(), log::SetLoggerError> {
+        log::set_max_level(logger.level());
+        log::set_boxed_logger(Box::new(Self { logger }))
+    }
+}
+
+impl<L: SharedLogger> log::Log for RedactingLogger<L> {
+    fn enabled(&self, metadata: &log::Metadata) -> bool {
+        self.logger.enabled(metadata)
+    }
+
+    fn log(&self, record: &log::Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        let message = restic::redact(&record.args().to_string());
+        self.logger.log(
+            &log::Record::builder()
+                .metadata(record.metadata().clone())
+                .args(format_args!("{message}"))
+                .module_path(record.module_path())
+                .file(record.file())
+                .line(record.line())
+                .build(),
+        );
+    }
+
+    fn flush(&self) {
+        self.logger.flush();
+    }
+}
+
+// -------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn file_names(log_dir: &Path) -> Vec<String> {
+        let mut names = fs::read_dir(log_dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().to_string())
+            .collect::<Vec<_>>();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn parse_log_levels() {
+        assert_eq!(parse_log_level(" debug "), Some(LevelFilter::Debug));
+        assert_eq!(parse_log_level("WARN"), Some(LevelFilter::Warn));
+        assert_eq!(parse_log_level("off"), Some(LevelFilter::Off));
+        assert_eq!(parse_log_level("verbose"), None);
+    }
+
+    #[test]
+    fn rotate_log_files_on_startup_and_max_size() {
+        let log_dir = std::env::temp_dir().join(format!(
+            "restic-browser-logging-test-{}",
+            restic::new_secret_id()
+        ));
+        fs::create_dir_all(&log_dir).unwrap();
+        fs::write(log_file_path(&log_dir), b"previous session").unwrap();
+
+        // the previous session's log gets rotated on startup
+        let mut log_file = RotatingLogFile::create(&log_dir).unwrap();
+        assert_eq!(file_names(&log_dir), ["App.1.log", "App.log"]);
+        assert_eq!(
+            fs::read(rotated_log_file_path(&log_dir, 1)).unwrap(),
+            b"previous session"
+        );
+
+        // write past the size limit: each log file holds two chunks
+        let chunk = vec![b'x'; (MAX_LOG_FILE_SIZE / 2) as usize];
+        log_file.write_all(b"first").unwrap();
+        for _ in 0..2 * (MAX_ROTATED_LOG_FILES + 2) {
+            log_file.write_all(&chunk).unwrap();
+        }
+        log_file.flush().unwrap();
+        assert_eq!(
+            file_names(&log_dir),
+            [
+                "App.1.log",
+                "App.2.log",
+                "App.3.log",
+                "App.4.log",
+                "App.5.log",
+                "App.log"
+            ]
+        );
+        for index in 1..=MAX_ROTATED_LOG_FILES {
+            let size = fs::metadata(rotated_log_file_path(&log_dir, index))
+                .unwrap()
+                .len();
+            assert!(size <= MAX_LOG_FILE_SIZE);
+        }
+        // the oldest logs got removed
+        let oldest = fs::read(rotated_log_file_path(&log_dir, MAX_ROTATED_LOG_FILES)).unwrap();
+        assert_ne!(oldest, b"previous session");
+        assert!(!oldest.starts_with(b"first"));
+
+        fs::remove_dir_all(log_dir).unwrap();
+    }
+}
@@ -18,15 +18,26 @@ use which::which;
 #[cfg(target_os = "macos")]
 use which::which_in;
 
-use tauri::Manager;
-use tauri_plugin_cli::CliExt;
+use tauri::{Emitter, Manager};
+use tauri_plugin_cli::{CliExt, Matches, SubcommandMatches};
+use tauri_plugin_deep_link::DeepLinkExt;
 use tauri_plugin_dialog::DialogExt;
 use tauri_plugin_window_state::StateFlags;
 
 // -------------------------------------------------------------------------------------------------
 
 mod app;
+mod cli;
+mod deep_link;
+mod diagnostics;
+mod diff;
+mod logging;
+mod mime;
+mod presets;
+mod preview;
+mod protocol;
 mod restic;
+mod thumbnails;
 
 // -------------------------------------------------------------------------------------------------
 
@@ -64,71 +75,54 @@ fn initialize_console() -> bool {
 
 // -------------------------------------------------------------------------------------------------
 
-// Try attaching the tauri GUI app to a running console, so we can print into it.
+// Create a terminal logger and, when a log dir is given, a rotating file logger.
 //
-// Returns true when running in a terminal, else false.
-fn initialize_logger(app: &tauri::App) -> Result<(), Box<dyn std::error::Error>> {
+// Log levels default to Warn for the terminal and Info for the log file and can be overridden
+// with the `--log-level` arg or the `RESTIC_BROWSER_LOG` environment variable.
+fn initialize_logger(arg_matches: &Matches, log_dir: Option<PathBuf>, terminal_mode: TerminalMode) {
+    // get log level from args or env
+    let log_level = arg_matches
+        .args
+        .get("log-level")
+        .and_then(|arg| arg.value.as_str().map(String::from))
+        .or_else(|| env::var(logging::LOG_LEVEL_ENV).ok());
+    let log_level_filter = log_level.as_deref().and_then(|level| {
+        let filter = logging::parse_log_level(level);
+        if filter.is_none() {
+            eprintln!("Ignoring invalid log level '{level}'");
+        }
+        filter
+    });
     // create term logger
     let mut loggers: Vec<Box<dyn SharedLogger>> = vec![TermLogger::new(
-        LevelFilter::Warn,
+        log_level_filter.unwrap_or(LevelFilter::Warn),
         Config::default(),
-        TerminalMode::Mixed,
+        terminal_mode,
         ColorChoice::Auto,
     )];
-    // try creating a file log as well, but don't panic
-    let log_file_result: Result<Box<WriteLogger<std::fs::File>>, Box<dyn std::error::Error>> = {
-        let log_path = app.path().app_log_dir()?;
-        std::fs::create_dir_all(log_path.as_path())?;
-        let mut log_file_path = log_path.clone();
-        log_file_path.push("App.log");
-        Ok(WriteLogger::new(
-            LevelFilter::Info,
-            Config::default(),
-            std::fs::File::create(log_file_path.as_path())?,
-        ))
-    };
-    match log_file_result {
-        Err(err) => eprintln!("Failed to create log file: {err}"),
-        Ok(logger) => loggers.push(logger),
+    // try creating a rotating file log as well, but don't panic
+    if let Some(log_dir) = log_dir {
+        match logging::RotatingLogFile::create(&log_dir) {
+            Err(err) => eprintln!("Failed to create log file: {err}"),
+            Ok(log_file) => loggers.push(WriteLogger::new(
+                log_level_filter.unwrap_or(LevelFilter::Info),
+                Config::default(),
+                log_file,
+            )),
+        }
     }
-    // create combined logger
-    CombinedLogger::init(loggers).unwrap_or_else(|err| eprintln!("Failed to create logger: {err}"));
-    Ok(())
+    // create combined logger, which redacts secrets from all log lines
+    logging::RedactingLogger::init(CombinedLogger::new(loggers))
+        .unwrap_or_else(|err| eprintln!("Failed to create logger: {err}"));
 }
 
 // -------------------------------------------------------------------------------------------------
 
-fn initialize_app(app: &mut tauri::App) -> Result<(), Box<dyn std::error::Error>> {
-    // setup term logger
-    initialize_logger(app)?;
-
-    // handle help/v arguments (early exit)
-    let arg_matches = app.cli().matches()?;
-    if let Some(arg) = arg_matches.args.get("help") {
-        let message = arg.value.as_str().expect("Invalid help string").to_string();
-        log::info!("Dumping arg help and exiting...");
-        show_message_and_exit(app, message, 0);
-    } else if arg_matches.args.contains_key("version") {
-        let message = format!(
-            "{} v{}",
-            app.config()
-                .product_name
-                .clone()
-                .unwrap_or("Restic Browser".to_string()),
-            app.config()
-                .version
-                .clone()
-                .unwrap_or("[Unknown version]".to_string())
-        );
-        log::info!("Dumping version and exiting...");
-        show_message_and_exit(app, message, 0);
-    }
-
-    // set PATH environment from shells in GUI apps on Linux and macOS
-    if let Err(err) = fix_path_env::fix() {
-        log::warn!("Failed to update PATH env: {}", err);
-    }
-
+// Create the app state for the given args: resolves restic and rclone binaries and the default
+// location from a profile, args or env. Used by the app and by headless subcommands.
+//
+// Returns an error message for invalid args, which should be shown to the user.
+fn create_app_state(arg_matches: &Matches, temp_dir_name: &str) -> Result<app::AppState, String> {
     // get common bin directories on macOS
     #[cfg(target_os = "macos")]
     let common_path = format!(
@@ -152,6 +146,16 @@ fn initialize_app(app: &mut tauri::App) -> Result<(), Box<dyn std::error::Error>
             log::info!("Got rclone as arg {}", path.to_string_lossy());
         }
     }
+    // get opt-in session mode from args
+    let session_mode = arg_matches
+        .args
+        .get("session")
+        .and_then(|arg| arg.value.as_bool())
+        .unwrap_or(false);
+    if session_mode {
+        log::info!("Using warm repository sessions");
+    }
+
     if restic_path.is_none() {
         if let Ok(restic) = which(restic::RESTIC_EXECTUABLE_NAME) {
             restic_path = Some(restic.clone());
@@ -195,21 +199,67 @@ fn initialize_app(app: &mut tauri::App) -> Result<(), Box<dyn std::error::Error>
         }
     }
 
-    // get default restic location from args or env
-    let mut location = restic::Location::new_from_args(
-        arg_matches
+    // get default restic location from a profile, args or env
+    let mut location = restic::Location::default();
+    // NB: the password strategy arg also applies to profile and env locations
+    let password_strategy = match arg_matches
+        .args
+        .get("password-strategy")
+        .and_then(|arg| arg.value.as_str())
+        .filter(|strategy| !strategy.is_empty())
+        .map(str::parse::<restic::PasswordStrategy>)
+    {
+        Some(Ok(strategy)) => Some(strategy),
+        Some(Err(err)) => return Err(err.to_string()),
+        None => None,
+    };
+    if let Some(profile) = arg_matches
+        .args
+        .get("profile")
+        .and_then(|arg| arg.value.as_str())
+        .filter(|profile| !profile.is_empty())
+    {
+        let profile_file = arg_matches
             .args
-            .into_iter()
-            .map(|(k, v)| (k, v.value.as_str().map(String::from)))
-            .collect::<HashMap<_, _>>(),
-    );
+            .get("profile-file")
+            .and_then(|arg| arg.value.as_str())
+            .map(PathBuf::from)
+            .or_else(restic::find_profile_file);
+        let Some(profile_file) = profile_file else {
+            return Err(format!(
+                "Failed to load profile '{profile}': no profile file found"
+            ));
+        };
+        match restic::load_profile(&profile_file, profile) {
+            Ok(profile_location) => {
+                log::info!(
+                    "Got location from profile '{profile}' in '{}'",
+                    profile_file.to_string_lossy()
+                );
+                location = profile_location;
+            }
+            Err(err) => return Err(format!("Failed to load profile '{profile}': {err}")),
+        }
+    }
+    if location.path.is_empty() {
+        location = restic::Location::new_from_args(
+            arg_matches
+                .args
+                .iter()
+                .map(|(k, v)| (k.clone(), v.value.as_str().map(String::from)))
+                .collect::<HashMap<_, _>>(),
+        );
+    }
     if location.path.is_empty() {
         location = restic::Location::new_from_env();
     }
+    if let Some(password_strategy) = password_strategy {
+        location.password_strategy = password_strategy;
+    }
 
     // create temp dir for previews
-    let mut temp_dir = path::Path::new(&env::temp_dir())
-        .join(app.package_info().name.clone() + "_" + &process::id().to_string());
+    let mut temp_dir =
+        path::Path::new(&env::temp_dir()).join(format!("{temp_dir_name}_{}", process::id()));
     if !temp_dir.exists() {
         if let Err(err) = fs::create_dir_all(temp_dir.clone()) {
             log::warn!("Failed to create temp app directory: {err}");
@@ -217,12 +267,154 @@ fn initialize_app(app: &mut tauri::App) -> Result<(), Box<dyn std::error::Error>
         }
     }
 
-    // create new app state
-    app.manage(app::SharedAppState::new(app::AppState::new(
+    Ok(app::AppState::new(
         restic::Program::new(restic_path.unwrap_or_default(), rclone_path),
         location,
         temp_dir,
-    )));
+        session_mode,
+    ))
+}
+
+// -------------------------------------------------------------------------------------------------
+
+fn initialize_app(app: &mut tauri::App) -> Result<(), Box<dyn std::error::Error>> {
+    // setup term and file logger
+    let arg_matches = app.cli().matches()?;
+    let log_dir = app
+        .path()
+        .app_log_dir()
+        .inspect_err(|err| eprintln!("Failed to create log file: {err}"))
+        .ok();
+    initialize_logger(&arg_matches, log_dir, TerminalMode::Mixed);
+
+    // handle help/v arguments (early exit)
+    if let Some(arg) = arg_matches.args.get("help") {
+        let message = arg.value.as_str().expect("Invalid help string").to_string();
+        log::info!("Dumping arg help and exiting...");
+        show_message_and_exit(app, message, 0);
+    } else if arg_matches.args.contains_key("version") {
+        let message = format!(
+            "{} v{}",
+            app.config()
+                .product_name
+                .clone()
+                .unwrap_or("Restic Browser".to_string()),
+            app.config()
+                .version
+                .clone()
+                .unwrap_or("[Unknown version]".to_string())
+        );
+        log::info!("Dumping version and exiting...");
+        show_message_and_exit(app, message, 0);
+    }
+
+    // set PATH environment from shells in GUI apps on Linux and macOS
+    if let Err(err) = fix_path_env::fix() {
+        log::warn!("Failed to update PATH env: {}", err);
+    }
+
+    // get initial open request from a deep link or preset/snapshot/path/host/tag args
+    let arg_value = |name: &str| {
+        let arg = arg_matches.args.get(name)?;
+        arg.value.as_str().map(String::from)
+    };
+    let arg_values = |name: &str| -> Vec<String> {
+        let Some(arg) = arg_matches.args.get(name) else {
+            return vec![];
+        };
+        match &arg.value {
+            serde_json::Value::Array(values) => values
+                .iter()
+                .filter_map(|value| value.as_str().map(String::from))
+                .collect(),
+            value => value.as_str().map(String::from).into_iter().collect(),
+        }
+    };
+    let mut open_request = arg_value("link")
+        .and_then(|link| {
+            deep_link::OpenRequest::from_url(&link)
+                .inspect_err(|err| log::warn!("{err}"))
+                .ok()
+        })
+        .unwrap_or_default();
+    open_request.preset = arg_value("preset").or(open_request.preset);
+    open_request.snapshot = arg_value("snapshot").or(open_request.snapshot);
+    open_request.path = arg_value("path").or(open_request.path);
+    let (hosts, tags) = (arg_values("host"), arg_values("tag"));
+    if !hosts.is_empty() {
+        open_request.hosts = hosts;
+    }
+    if !tags.is_empty() {
+        open_request.tags = tags;
+    }
+
+    // create app state for the default location and restic binary
+    let app_state = match create_app_state(&arg_matches, &app.package_info().name) {
+        Ok(app_state) => app_state,
+        Err(message) => show_message_and_exit(app, message, 1),
+    };
+    let temp_dir = app_state.temp_dir().clone();
+
+    // create secret store for saved passwords and credentials
+    let config_dir = match app.path().app_config_dir() {
+        Ok(config_dir) => {
+            match restic::default_secret_store(&config_dir) {
+                Ok(secret_store) => restic::set_secret_store(secret_store),
+                Err(err) => log::warn!("Failed to create secret store: {err}"),
+            }
+            config_dir
+        }
+        Err(err) => {
+            log::warn!("Failed to resolve app config directory: {err}");
+            temp_dir.clone()
+        }
+    };
+
+    // load location presets
+    app.manage(presets::PresetStore::new(
+        config_dir.join(presets::PRESETS_FILE_NAME),
+    ));
+
+    // record executed restic operations in the app data directory
+    match app.path().app_data_dir() {
+        Ok(data_dir) => {
+            restic::set_operation_history_file(data_dir.join(restic::OPERATION_HISTORY_FILE_NAME))
+        }
+        Err(err) => log::warn!("Failed to resolve app data directory: {err}"),
+    }
+
+    // create thumbnail generator with a persistent thumbnail cache
+    let thumbnail_cache_dir = match app.path().app_cache_dir() {
+        Ok(cache_dir) => cache_dir.join("thumbnails"),
+        Err(err) => {
+            log::warn!("Failed to resolve app cache directory: {err}");
+            temp_dir.join("thumbnails")
+        }
+    };
+    app.manage(thumbnails::ThumbnailGenerator::new(thumbnail_cache_dir));
+
+    // create new app state
+    app.manage(app::SharedAppState::new(app_state));
+
+    // pass deep links and open requests to the frontend, which resolves them
+    if !open_request.is_empty() {
+        log::info!("Got open request {open_request:?}");
+        app.state::<app::SharedAppState>()
+            .set_open_request(open_request)?;
+    }
+    #[cfg(any(target_os = "linux", all(debug_assertions, target_os = "windows")))]
+    if let Err(err) = app.deep_link().register_all() {
+        log::warn!("Failed to register deep link scheme: {err}");
+    }
+    let app_handle = app.handle().clone();
+    app.deep_link().on_open_url(move |event| {
+        for url in event.urls() {
+            match deep_link::OpenRequest::from_url(url.as_str()) {
+                Ok(open_request) => handle_open_request(&app_handle, open_request),
+                Err(err) => log::warn!("{err}"),
+            }
+        }
+    });
 
     log::info!("Starting application...");
     Ok(())
@@ -230,10 +422,39 @@ fn initialize_app(app: &mut tauri::App) -> Result<(), Box<dyn std::error::Error>
 
 // -------------------------------------------------------------------------------------------------
 
+// Store the given open request in the app state and notify the frontend about it.
+fn handle_open_request(app: &tauri::AppHandle, open_request: deep_link::OpenRequest) {
+    log::info!("Got open request {open_request:?}");
+    if let Err(err) = app
+        .state::<app::SharedAppState>()
+        .set_open_request(open_request)
+    {
+        log::error!("Failed to handle open request: {err}");
+        return;
+    }
+    if let Err(err) = app.emit(deep_link::OPEN_REQUEST_EVENT, ()) {
+        log::error!("Failed to notify frontend about open request: {err}");
+    }
+}
+
+// Focus the running app when a second instance got launched and pass its deep link, if any.
+fn handle_second_instance(app: &tauri::AppHandle, args: Vec<String>, _cwd: String) {
+    if let Some(window) = app.get_webview_window("main") {
+        let _ = window.unminimize();
+        let _ = window.set_focus();
+    }
+    if let Some(open_request) = deep_link::OpenRequest::from_args(&args) {
+        handle_open_request(app, open_request);
+    }
+}
+
+// -------------------------------------------------------------------------------------------------
+
 fn finalize_app(app: &tauri::AppHandle) -> Result<(), Box<dyn std::error::Error>> {
     log::info!("Closing application...");
-    // remove previews temp dir
+    // unmount sessions and remove previews temp dir
     let state = app.state::<app::SharedAppState>().get()?;
+    state.close_sessions();
     fs::remove_dir_all(state.temp_dir())?;
     Ok(())
 }
@@ -252,10 +473,24 @@ fn show_app_window(app_window: tauri::Window) -> Result<(), String> {
 
 fn create_application() -> Result<tauri::App, Box<dyn std::error::Error>> {
     tauri::Builder::default()
+        // NB: must be the first plugin, so second instances exit before initializing anything.
+        // Headless subcommands never get here, so they run next to an open app window.
+        .plugin(tauri_plugin_single_instance::init(handle_second_instance))
+        .plugin(tauri_plugin_deep_link::init())
         .plugin(tauri_plugin_cli::init())
         .plugin(tauri_plugin_dialog::init())
         .plugin(tauri_plugin_fs::init())
         .setup(initialize_app)
+        .register_asynchronous_uri_scheme_protocol(
+            protocol::URI_SCHEME,
+            |context, request, responder| {
+                // NB: dumping files may take a while, so don't block the main thread
+                let app = context.app_handle().clone();
+                std::thread::spawn(move || {
+                    responder.respond(protocol::handle_request(&app, request));
+                });
+            },
+        )
         .on_window_event(|window, event| {
             if let tauri::WindowEvent::Destroyed = event {
                 let app = window.app_handle();
@@ -275,12 +510,36 @@ fn create_application() -> Result<tauri::App, Box<dyn std::error::Error>> {
             app::supported_repo_location_types,
             app::default_repo_location,
             app::open_file_or_url,
+            app::store_secret,
+            app::has_secret,
+            app::delete_secret,
+            app::load_profile_locations,
+            app::get_location_presets,
+            app::add_location_preset,
+            app::update_location_preset,
+            app::remove_location_preset,
+            app::reorder_location_presets,
+            app::location_presets_with_commands,
+            app::import_location_presets,
+            app::export_location_presets,
             app::verify_restic_path,
             app::open_repository,
+            app::test_location,
             app::get_files,
             app::get_snapshots,
             app::dump_file,
             app::dump_file_to_temp,
+            app::preview_file,
+            app::get_thumbnail,
+            app::cancel_thumbnails,
+            app::list_jobs,
+            app::get_operation_history,
+            app::export_operation_history,
+            app::get_log_path,
+            app::open_log_folder,
+            app::resolve_open_request,
+            app::compare_with_local,
+            app::diff_file_contents,
             app::restore_file
         ])
         .build(tauri::generate_context!())
@@ -289,7 +548,58 @@ fn create_application() -> Result<tauri::App, Box<dyn std::error::Error>> {
 
 // -------------------------------------------------------------------------------------------------
 
+// Run a headless subcommand without creating the app: logs to the terminal only and doesn't
+// touch the app's log, config or data files.
+//
+// Returns the process exit code.
+fn run_headless_subcommand(arg_matches: &Matches, subcommand: &SubcommandMatches) -> i32 {
+    // NB: log to stderr only, so log lines never mix with the subcommand's output on stdout
+    initialize_logger(arg_matches, None, TerminalMode::Stderr);
+    log::info!("Running headless '{}' subcommand...", subcommand.name);
+    // record executed restic operations in the app data directory, just like the app does
+    match cli::app_data_dir() {
+        Some(data_dir) => {
+            restic::set_operation_history_file(data_dir.join(restic::OPERATION_HISTORY_FILE_NAME))
+        }
+        None => log::warn!("Failed to resolve app data directory"),
+    }
+    let state = match create_app_state(arg_matches, env!("CARGO_PKG_NAME")) {
+        Ok(state) => state,
+        Err(message) => {
+            eprintln!("{message}");
+            return 1;
+        }
+    };
+    let exit_code = cli::run_subcommand(&state, subcommand);
+    let _ = fs::remove_dir_all(state.temp_dir());
+    exit_code
+}
+
+// -------------------------------------------------------------------------------------------------
+
 fn main() {
+    let args = env::args().collect::<Vec<_>>();
+    // handle password helper invocations from restic (early exit)
+    if let Some(exit_code) = restic::run_password_helper(&args) {
+        process::exit(exit_code);
+    }
+    // run headless subcommands and print help or version before creating the app, so the
+    // single-instance and deep-link plugins only get registered for GUI runs (early exit)
+    match cli::parse_subcommand_args(&args) {
+        Ok(Some(arg_matches)) => {
+            if let Some(subcommand) = arg_matches.subcommand.as_deref() {
+                initialize_console();
+                process::exit(run_headless_subcommand(&arg_matches, subcommand));
+            }
+        }
+        Ok(None) => (),
+        Err(err) => {
+            // NB: without a terminal, the app shows help and version in a dialog instead
+            if initialize_console() || err.use_stderr() {
+                err.exit();
+            }
+        }
+    }
     match create_application() {
         Ok(app) => {
             app.run(|_app, _event| {});
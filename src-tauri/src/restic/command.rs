@@ -3,15 +3,23 @@ use std::{
     collections::HashMap,
     ffi::{OsStr, OsString},
     fs,
-    path::PathBuf,
-    process::{Command, Output, Stdio},
+    io::{self, Read},
+    path::{Path, PathBuf},
+    process::{Child, Command, Output, Stdio},
+    thread,
+    time::Duration,
 };
 
 use semver::Version;
 
+use tokio_util::sync::CancellationToken;
+
 use scopeguard::defer;
 
-use crate::restic::Location;
+use crate::restic::{
+    is_secret_name, parse_extra_args, register_secret, resolve_secret, verify_extra_env, Error,
+    ErrorKind, Location,
+};
 
 // -------------------------------------------------------------------------------------------------
 
@@ -19,10 +27,58 @@ use crate::restic::Location;
 mod group;
 
 use group::{
-    add_command_to_group, process_was_terminated, remove_command_from_group,
-    terminate_all_commands_in_group,
+    add_command_to_group, command_group_terminations, process_was_terminated,
+    remove_command_from_group,
+};
+
+pub use group::{terminate_all_commands_in_group, CommandGroup};
+
+/// Streamed command output
+mod stream;
+
+pub use stream::OutputStream;
+
+/// Password passing strategies
+mod password;
+
+use password::PasswordHandoff;
+
+pub use password::{run_password_command, run_password_helper, PasswordStrategy};
+
+/// Timeout and retry policies
+mod policy;
+
+use policy::Watchdog;
+
+pub use policy::CommandPolicy;
+
+/// Async process runner
+mod runner;
+
+pub use runner::AsyncProcess;
+
+/// Long-lived mount sessions
+mod mount;
+
+pub use mount::MountSession;
+
+/// Operation history
+mod history;
+
+use history::Operation;
+
+pub use history::{
+    export_operation_history, operation_history, set_operation_history_file, OperationRecord,
+    OPERATION_HISTORY_FILE_NAME,
 };
 
+/// Job scheduling
+mod scheduler;
+
+use scheduler::{start_job, start_job_async, JobGuard};
+
+pub use scheduler::{list_jobs, JobInfo, MAX_JOBS_PER_REPOSITORY};
+
 // -------------------------------------------------------------------------------------------------
 
 /// Create new Command and configure it to hide the CMD window on Windows.
@@ -53,6 +109,39 @@ pub const RCLONE_EXECTUABLE_NAME: &str = "rclone";
 
 // -------------------------------------------------------------------------------------------------
 
+/// A restic process, as spawned by `Program::spawn`, with the resources which must be kept
+/// alive until it finished.
+struct SpawnedProcess {
+    child: Child,
+    args: Vec<OsString>,
+    command_group: Option<&'static str>,
+    // keeps temporary password files alive until the process finished
+    password: PasswordHandoff,
+    // keeps the job's repository slot occupied until the process finished
+    job: JobGuard,
+    operation: Operation,
+}
+
+// Kill all running restic commands of the given group, when it's an exclusive group.
+fn terminate_exclusive_command_group(command_group: CommandGroup) {
+    if let CommandGroup::Exclusive(command_group) = command_group {
+        if let Err(err) = terminate_all_commands_in_group(command_group) {
+            log::error!("Failed to kill process childs: {err}");
+        }
+    }
+}
+
+// Unregister a finished process from its command group, if any.
+fn remove_from_command_group(command_group: Option<&'static str>, child_id: u32) {
+    if let Some(command_group) = command_group {
+        if let Err(err) = remove_command_from_group(command_group, child_id) {
+            log::error!("Failed to remove process child: {err}");
+        }
+    }
+}
+
+// -------------------------------------------------------------------------------------------------
+
 /// Restic command executable wrapper.
 #[derive(Debug, Default, Clone)]
 pub struct Program {
@@ -88,110 +177,371 @@ impl Program {
     }
 
     /// Run a restic command for the given location with the given args.
-    /// when param `command_group` is some, all commands in the same group are
+    /// when param `command_group` is an exclusive group, all commands in the same group are
     /// killed before starting the new command.
-    pub fn run<C: Into<Option<&'static str>>>(
+    ///
+    /// Commands, except dumps and restores, are killed when they exceed the location's command
+    /// policy timeout. Idempotent read commands, which failed with network errors or timed out,
+    /// are retried with backoff.
+    pub fn run<C: Into<CommandGroup>>(
         &self,
         location: &Location,
         args: &[&str],
         command_group: C,
-    ) -> Result<String, String> {
-        // kill all other running restic commands in the same group
+    ) -> Result<String, Error> {
         let command_group = command_group.into();
-        if let Some(command_group) = command_group {
-            if let Err(err) = terminate_all_commands_in_group(command_group) {
-                log::error!("Failed to kill process childs: {err}");
+        let policy = &location.command_policy;
+        let retries = policy.retries_for(args);
+        let mut attempt = 0;
+        loop {
+            let result = self.run_once(location, args, command_group, policy.timeout_for(args));
+            match result {
+                Err(err) if err.kind == ErrorKind::NetworkError && attempt < retries => {
+                    let delay = policy.retry_delay(attempt);
+                    log::warn!(
+                        "Restic '{args:?}' command failed: {err}. Retrying in {} ms...",
+                        delay.as_millis()
+                    );
+                    // don't retry commands which got superseded while waiting
+                    let terminations = command_group.name().map(command_group_terminations);
+                    thread::sleep(delay);
+                    if command_group.name().map(command_group_terminations) != terminations {
+                        return Err(Error::cancelled());
+                    }
+                    attempt += 1;
+                }
+                result => return result,
             }
         }
+    }
+
+    /// Run a restic command for the given location with the given args, like `run`, but
+    /// with the given timeout instead of the location's command policy and without retries.
+    pub fn run_with_timeout<C: Into<CommandGroup>>(
+        &self,
+        location: &Location,
+        args: &[&str],
+        timeout: Duration,
+        command_group: C,
+    ) -> Result<String, Error> {
+        self.run_once(location, args, command_group.into(), Some(timeout))
+    }
+
+    // Run a restic command once and return its stdout. Timed out commands get killed and
+    // are returned as `NetworkError`.
+    fn run_once(
+        &self,
+        location: &Location,
+        args: &[&str],
+        command_group: CommandGroup,
+        timeout: Option<Duration>,
+    ) -> Result<String, Error> {
+        let SpawnedProcess {
+            child,
+            args,
+            command_group,
+            operation,
+            password: _password,
+            job: _job,
+        } = self.spawn(location, args, command_group, Stdio::piped())?;
+        // unregister child id with command group
+        let child_id = child.id();
+        defer! {
+            remove_from_command_group(command_group, child_id);
+        }
+        // wait until command finished or timed out and collect output
+        let watchdog = Watchdog::new(child_id, timeout);
+        let output = child.wait_with_output();
+        let result = if watchdog.finish() {
+            log::warn!("Restic '{args:?}' command timed out");
+            Err(Error::new(
+                ErrorKind::NetworkError,
+                format!(
+                    "Restic command timed out after {} seconds",
+                    timeout.unwrap_or_default().as_secs()
+                ),
+            ))
+        } else {
+            match output {
+                Ok(output) if output.status.success() => {
+                    let stdout = std::str::from_utf8(&output.stdout).unwrap_or("");
+                    Ok(stdout.to_string())
+                }
+                Ok(output) => Err(Self::handle_run_error(&args, &output)),
+                Err(err) => Err(err.into()),
+            }
+        };
+        let bytes_transferred = result.as_ref().ok().map(|stdout| stdout.len() as u64);
+        operation.finish(&result, bytes_transferred);
+        result
+    }
+
+    /// Spawn a restic command for the given location with the given args on the async
+    /// runner, without waiting for it to finish. Waits until the job scheduler lets the
+    /// command run in its repository first.
+    /// when param `command_group` is an exclusive group, all commands in the same group are
+    /// killed before starting the new command.
+    pub async fn spawn_async<C: Into<CommandGroup>>(
+        &self,
+        location: &Location,
+        args: &[&str],
+        command_group: C,
+        cancellation_token: &CancellationToken,
+    ) -> Result<AsyncProcess, Error> {
+        let command_group = command_group.into();
+        terminate_exclusive_command_group(command_group);
+        // wait until the scheduler lets the command run in its repository
+        let job = start_job_async(location, args, command_group, cancellation_token).await?;
         // start a new restic command
-        let args = self.args(args, location);
-        let envs = self.envs(location);
-        let child = new_command(&self.restic_path)
-            .envs(envs)
-            .args(args.clone())
+        let (command, args, mut password) = self.command(location, args)?;
+        let child = tokio::process::Command::from(command)
             .stdout(Stdio::piped())
             .stderr(Stdio::piped())
+            .kill_on_drop(true)
             .spawn()
-            .map_err(|err| err.to_string())?;
-        // register child id with command group
-        let child_id = child.id();
-        if let Some(command_group) = command_group {
-            if let Err(err) = add_command_to_group(command_group, child_id) {
-                log::error!("Failed to add process child: {err}");
-            }
-        }
-        // unregister child id with command group
-        defer! {
-            if let Some(command_group) = command_group {
-                if let Err(err) = remove_command_from_group(command_group, child_id) {
-                    log::error!("Failed to remove process child: {err}");
+            .map_err(Self::handle_spawn_error)?;
+        password.spawned();
+        let operation = Operation::start(location, &args);
+        Ok(AsyncProcess::new(
+            child,
+            args,
+            command_group.name(),
+            cancellation_token.clone(),
+            password,
+            job,
+            operation,
+        ))
+    }
+
+    /// Run a restic command for the given location with the given args on the async runner,
+    /// like `run`, and return its stdout. The command gets terminated when the given token
+    /// gets cancelled.
+    pub async fn run_async<C: Into<CommandGroup>>(
+        &self,
+        location: &Location,
+        args: &[&str],
+        command_group: C,
+        cancellation_token: &CancellationToken,
+    ) -> Result<String, Error> {
+        let command_group = command_group.into();
+        let policy = &location.command_policy;
+        let retries = policy.retries_for(args);
+        let mut attempt = 0;
+        loop {
+            let result = match self
+                .spawn_async(location, args, command_group, cancellation_token)
+                .await
+            {
+                Ok(process) => process
+                    .wait_with_output(policy.timeout_for(args))
+                    .await
+                    .map(|stdout| std::str::from_utf8(&stdout).unwrap_or("").to_string()),
+                Err(err) => Err(err),
+            };
+            match result {
+                Err(err) if err.kind == ErrorKind::NetworkError && attempt < retries => {
+                    let delay = policy.retry_delay(attempt);
+                    log::warn!(
+                        "Restic '{args:?}' command failed: {err}. Retrying in {} ms...",
+                        delay.as_millis()
+                    );
+                    // don't retry commands which got superseded or cancelled while waiting
+                    let terminations = command_group.name().map(command_group_terminations);
+                    tokio::select! {
+                        _ = tokio::time::sleep(delay) => {}
+                        _ = cancellation_token.cancelled() => return Err(Error::cancelled()),
+                    }
+                    if command_group.name().map(command_group_terminations) != terminations {
+                        return Err(Error::cancelled());
+                    }
+                    attempt += 1;
                 }
+                result => return result,
             }
         }
-        // wait until command finished and collect output
-        let output = child.wait_with_output().map_err(|err| err.to_string())?;
-        if output.status.success() {
-            let stdout = std::str::from_utf8(&output.stdout).unwrap_or("");
-            Ok(stdout.to_string())
-        } else {
-            Err(Self::handle_run_error(&args, &output))
-        }
     }
 
     /// Run a restic command for the given location with the given args and redirect
     /// stdout to the given target file.
-    /// when @param `command_group` is some, all commands in the same group are
+    /// when @param `command_group` is an exclusive group, all commands in the same group are
     /// killed before starting the new command.
-    pub fn run_redirected<C: Into<Option<&'static str>>>(
+    pub fn run_redirected<C: Into<CommandGroup>>(
         &self,
         location: &Location,
         args: &[&str],
         file: fs::File,
         command_group: C,
-    ) -> Result<(), String> {
-        // kill all other running restic commands in the same group
-        let command_group = command_group.into();
-        if let Some(command_group) = command_group {
-            if let Err(err) = terminate_all_commands_in_group(command_group) {
-                log::error!("Failed to kill process childs: {err}");
-            }
+    ) -> Result<(), Error> {
+        // keep a handle to the target file to record the number of written bytes
+        let target_file = file.try_clone()?;
+        let SpawnedProcess {
+            child,
+            args,
+            command_group,
+            mut operation,
+            password: _password,
+            job: _job,
+        } = self.spawn(location, args, command_group.into(), Stdio::from(file))?;
+        // redirected outputs are files the user saved
+        operation.mark_user_started();
+        // unregister child id with command group
+        let child_id = child.id();
+        defer! {
+            remove_from_command_group(command_group, child_id);
+        }
+        // wait until command finished and collect output
+        let result = match child.wait_with_output() {
+            Ok(output) if output.status.success() => Ok(()),
+            Ok(output) => Err(Self::handle_run_error(&args, &output)),
+            Err(err) => Err(err.into()),
+        };
+        let bytes_transferred = target_file.metadata().ok().map(|metadata| metadata.len());
+        operation.finish(&result, bytes_transferred);
+        result
+    }
+
+    /// Run a restic command for the given location with the given args and return a reader
+    /// for its stdout, so large outputs can be consumed progressively.
+    /// when @param `command_group` is an exclusive group, all commands in the same group are
+    /// killed before starting the new command.
+    pub fn run_streamed<C: Into<CommandGroup>>(
+        &self,
+        location: &Location,
+        args: &[&str],
+        command_group: C,
+    ) -> Result<OutputStream, Error> {
+        let process = self.spawn(location, args, command_group.into(), Stdio::piped())?;
+        Self::stream(process)
+    }
+
+    // Wrap the given spawned process' stdout into an output stream.
+    fn stream(process: SpawnedProcess) -> Result<OutputStream, Error> {
+        // NB: the stream unregisters the child from its command group when dropped
+        Ok(OutputStream::new(
+            process.child,
+            process.args,
+            process.command_group,
+            process.password,
+            process.job,
+            process.operation,
+        )?)
+    }
+
+    /// Mount the given location's repository in the given empty directory with a long-lived
+    /// `restic mount` process and wait until it serves the repository.
+    /// Requires FUSE, so this is not supported on Windows.
+    pub fn mount(&self, location: &Location, mount_dir: &Path) -> Result<MountSession, Error> {
+        if cfg!(target_os = "windows") {
+            return Err(Error::new(
+                ErrorKind::Failed,
+                "Mounting repositories is not supported on this platform",
+            ));
+        }
+        fs::create_dir_all(mount_dir)?;
+        let mount_dir_arg = mount_dir.to_string_lossy().to_string();
+        let mount_args = ["mount", mount_dir_arg.as_str()];
+        let (mut command, args, mut password) = self.command(location, &mount_args)?;
+        let child = command
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(Self::handle_spawn_error)?;
+        password.spawned();
+        let operation = Operation::start(location, &args);
+        MountSession::new(child, &args, mount_dir.to_path_buf(), password, operation)
+    }
+
+    /// Run a restic command for the given location with the given args and capture its
+    /// stdout in memory instead of redirecting it to a file.
+    /// Returns None when the output exceeds `max_bytes`. The command then gets killed.
+    /// Captured outputs are user requested, e.g. diffed files, so the command gets recorded
+    /// in the operation history.
+    pub fn run_captured<C: Into<CommandGroup>>(
+        &self,
+        location: &Location,
+        args: &[&str],
+        max_bytes: u64,
+        command_group: C,
+    ) -> Result<Option<Vec<u8>>, Error> {
+        let mut process = self.spawn(location, args, command_group.into(), Stdio::piped())?;
+        process.operation.mark_user_started();
+        let mut output = Vec::new();
+        Self::stream(process)?
+            .take(max_bytes.saturating_add(1))
+            .read_to_end(&mut output)?;
+        if output.len() as u64 > max_bytes {
+            Ok(None)
+        } else {
+            Ok(Some(output))
         }
+    }
+
+    // Spawn a restic command for the given location with the given args and stdout target:
+    // kills other commands of exclusive groups, waits until the scheduler lets the command
+    // run in its repository and registers the process with its command group.
+    // The caller must unregister the process from its command group when it finished.
+    fn spawn(
+        &self,
+        location: &Location,
+        args: &[&str],
+        command_group: CommandGroup,
+        stdout: Stdio,
+    ) -> Result<SpawnedProcess, Error> {
+        terminate_exclusive_command_group(command_group);
+        // wait until the scheduler lets the command run in its repository
+        let job = start_job(location, args, command_group)?;
         // start a new restic command
-        let args = self.args(args, location);
-        let envs = self.envs(location);
-        let child = new_command(&self.restic_path)
-            .envs(envs)
-            .args(args.clone())
-            .stdout(std::process::Stdio::from(file))
+        let (mut command, args, mut password) = self.command(location, args)?;
+        let child = command
+            .stdout(stdout)
             .stderr(Stdio::piped())
             .spawn()
-            .map_err(|err| err.to_string())?;
+            .map_err(Self::handle_spawn_error)?;
+        password.spawned();
         // register child id with command group
-        let child_id = child.id();
+        let command_group = command_group.name();
         if let Some(command_group) = command_group {
-            if let Err(err) = add_command_to_group(command_group, child_id) {
+            if let Err(err) = add_command_to_group(command_group, child.id()) {
                 log::error!("Failed to add process child: {err}");
             }
         }
-        // unregister child id with command group
-        defer! {
-            if let Some(command_group) = command_group {
-                if let Err(err) = remove_command_from_group(command_group, child_id) {
-                    log::error!("Failed to remove process child: {err}");
-                }
-            }
-        }
-        // wait until command finished and collect output
-        let output = child.wait_with_output().map_err(|err| err.to_string())?;
-        if output.status.success() {
-            Ok(())
-        } else {
-            Err(Self::handle_run_error(&args, &output))
-        }
+        let operation = Operation::start(location, &args);
+        Ok(SpawnedProcess {
+            child,
+            args,
+            command_group,
+            password,
+            job,
+            operation,
+        })
+    }
+
+    // Create a restic command for the given location with the given args and envs and the
+    // password handoff, which must be kept alive until the process finished.
+    fn command(
+        &self,
+        location: &Location,
+        args: &[&str],
+    ) -> Result<(Command, Vec<OsString>, PasswordHandoff), Error> {
+        let args = self
+            .args(args, location)?
+            .into_iter()
+            .map(Cow::into_owned)
+            .collect::<Vec<_>>();
+        let envs = self.envs(location)?;
+        let password = self.password_handoff(location)?;
+        let mut command = new_command(&self.restic_path);
+        password.configure(command.envs(envs)).args(&args);
+        Ok((command, args, password))
     }
 
     // Create restic specific args for the given base args and location.
-    fn args<'a>(&self, args: &'a [&'a str], location: &Location) -> Vec<Cow<'a, OsStr>> {
+    fn args<'a>(
+        &self,
+        args: &'a [&'a str],
+        location: &Location,
+    ) -> Result<Vec<Cow<'a, OsStr>>, Error> {
         let mut args = args
             .iter()
             .copied()
@@ -215,11 +565,28 @@ impl Program {
         if location.insecure_tls {
             args.push(Cow::Borrowed(OsStr::new("--insecure-tls")));
         }
-        args
+        for option in &location.options {
+            if !option.is_valid() {
+                return Err(Error::new(
+                    ErrorKind::InvalidLocation,
+                    format!("Invalid extended option name: '{}'", option.name),
+                ));
+            }
+            args.push(Cow::Borrowed(OsStr::new("--option")));
+            args.push(Cow::Owned(OsString::from(format!(
+                "{}={}",
+                option.name, option.value
+            ))));
+        }
+        for extra_arg in parse_extra_args(&location.extra_args)? {
+            args.push(Cow::Owned(OsString::from(extra_arg)));
+        }
+        Ok(args)
     }
 
-    // Create restic specific environment variables for the given location
-    fn envs(&self, location: &Location) -> HashMap<String, String> {
+    // Create restic specific environment variables for the given location.
+    // Secrets which are referenced by id get resolved from the secret store here.
+    fn envs(&self, location: &Location) -> Result<HashMap<String, String>, Error> {
         let mut envs = HashMap::new();
         // set repository
         if !location.path.is_empty() {
@@ -234,37 +601,87 @@ impl Program {
             // ensure that only RESTIC_REPOSITORY is set: restic else may use the repo file
             envs.insert("RESTIC_REPOSITORY_FILE".to_string(), "".to_string());
         }
-        // set password
-        if !location.allow_empty_password {
-            envs.insert("RESTIC_PASSWORD".to_string(), location.password.clone());
-        } else {
-            envs.insert("RESTIC_PASSWORD".to_string(), "".to_string());
-        }
-        // ensure that only RESTIC_PASSWORD is set: restic else may use the password file
-        envs.insert("RESTIC_PASSWORD_FILE".to_string(), "".to_string());
         // set all extra credentials for the location
         for credential in location.credentials.clone() {
-            envs.insert(credential.name, credential.value);
+            let secret_id = credential.secret_id.as_deref().filter(|id| !id.is_empty());
+            let value = match secret_id {
+                Some(secret_id) => resolve_secret(secret_id)?,
+                None => credential.value,
+            };
+            // don't override inherited envs with empty optional values, e.g. AWS_DEFAULT_REGION
+            if value.is_empty() {
+                continue;
+            }
+            // only redact real secrets: regions or account names would mangle unrelated text
+            if secret_id.is_some() || is_secret_name(&credential.name) {
+                register_secret(&value);
+            }
+            envs.insert(credential.name, value);
+        }
+        // set all extra envs for the location
+        for extra_env in location.extra_envs.clone() {
+            verify_extra_env(&extra_env.name)?;
+            let secret_id = extra_env.secret_id.as_deref().filter(|id| !id.is_empty());
+            let value = match secret_id {
+                Some(secret_id) => resolve_secret(secret_id)?,
+                None => extra_env.value,
+            };
+            if secret_id.is_some() || is_secret_name(&extra_env.name) {
+                register_secret(&value);
+            }
+            envs.insert(extra_env.name, value);
+        }
+        Ok(envs)
+    }
+
+    // Prepare passing the password of the given location to restic.
+    fn password_handoff(&self, location: &Location) -> Result<PasswordHandoff, Error> {
+        if location.allow_empty_password {
+            return PasswordHandoff::new(PasswordStrategy::Environment, String::new());
+        }
+        // let restic run password commands for each command, so short-lived passwords work
+        if let Some(password_command) = &location.password_command {
+            return Ok(PasswordHandoff::command(password_command.clone()));
         }
-        envs
+        let password = match location
+            .password_secret_id
+            .as_deref()
+            .filter(|id| !id.is_empty())
+        {
+            Some(secret_id) => resolve_secret(secret_id)?,
+            None => location.password.clone(),
+        };
+        register_secret(&password);
+        PasswordHandoff::new(location.password_strategy, password)
     }
 
     /// Log and return error from a restic run command.
-    fn handle_run_error<S: AsRef<OsStr> + std::fmt::Debug>(args: &[S], output: &Output) -> String {
+    fn handle_run_error<S: AsRef<OsStr> + std::fmt::Debug>(args: &[S], output: &Output) -> Error {
         // guess if this is a command which got aborted
         if process_was_terminated(&output.status) {
             log::info!("Restic '{:?}' command got aborted", args);
-            return "Command got aborted".to_string();
+            return Error::cancelled();
         }
-        // else log and return stderr as it is
-        let stderr = std::str::from_utf8(&output.stderr).unwrap_or("");
+        // else log and return classified error
+        let error = Error::from_output(output);
         log::warn!(
-            "Restic '{:?}' command failed with status {}:\n{}",
+            "Restic '{:?}' command failed with status {} ({:?}):\n{}",
             args,
             output.status,
-            stderr
+            error.kind,
+            error.stderr.as_deref().unwrap_or_default()
         );
-        stderr.to_string()
+        error
+    }
+
+    /// Convert errors from launching the restic executable.
+    fn handle_spawn_error(err: io::Error) -> Error {
+        let kind = if err.kind() == io::ErrorKind::NotFound {
+            ErrorKind::ResticNotFound
+        } else {
+            ErrorKind::Io
+        };
+        Error::new(kind, format!("Failed to launch restic: {err}"))
     }
 
     /// Run restic command to query its version number.
@@ -300,3 +717,84 @@ impl Program {
         None
     }
 }
+
+// -------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::restic::{redact, EnvValue, ExtendedOption};
+
+    fn program() -> Program {
+        Program {
+            restic_version: None,
+            restic_path: PathBuf::from("restic"),
+            rclone_path: None,
+        }
+    }
+
+    fn env_value(name: &str, value: &str) -> EnvValue {
+        EnvValue {
+            name: name.to_string(),
+            value: value.to_string(),
+            secret_id: None,
+        }
+    }
+
+    #[test]
+    fn envs_skip_empty_credentials_and_only_register_secrets() {
+        let location = Location {
+            prefix: "s3".to_string(),
+            path: "https://minio.local/bucket".to_string(),
+            credentials: vec![
+                env_value("AWS_ACCESS_KEY_ID", ""),
+                env_value("AWS_SECRET_ACCESS_KEY", "envs-test-secret-key"),
+                env_value("AWS_DEFAULT_REGION", "envs-test-region"),
+            ],
+            ..Location::default()
+        };
+        let envs = program().envs(&location).unwrap();
+        assert!(!envs.contains_key("AWS_ACCESS_KEY_ID"));
+        assert_eq!(envs["AWS_DEFAULT_REGION"], "envs-test-region");
+        assert_eq!(
+            redact("envs-test-secret-key in envs-test-region"),
+            "*** in envs-test-region"
+        );
+    }
+
+    #[test]
+    fn envs_register_secret_extra_envs() {
+        let location = Location {
+            path: "/backups".to_string(),
+            extra_envs: vec![
+                env_value("RESTIC_CACHE_DIR", "/extra-envs-test-cache"),
+                env_value("AWS_SESSION_TOKEN", "extra-envs-test-token"),
+            ],
+            ..Location::default()
+        };
+        let envs = program().envs(&location).unwrap();
+        assert_eq!(envs["AWS_SESSION_TOKEN"], "extra-envs-test-token");
+        assert_eq!(
+            redact("extra-envs-test-token in /extra-envs-test-cache"),
+            "*** in /extra-envs-test-cache"
+        );
+    }
+
+    #[test]
+    fn args_reject_invalid_extended_options() {
+        let mut location = Location {
+            path: "/backups".to_string(),
+            options: vec![ExtendedOption {
+                name: "s3.storage-class".to_string(),
+                value: "STANDARD_IA".to_string(),
+            }],
+            ..Location::default()
+        };
+        let args = program().args(&["snapshots"], &location).unwrap();
+        assert!(args
+            .windows(2)
+            .any(|args| *args[0] == *"--option" && *args[1] == *"s3.storage-class=STANDARD_IA"));
+        location.options[0].name = "storage-class".to_string();
+        assert!(program().args(&["snapshots"], &location).is_err());
+    }
+}
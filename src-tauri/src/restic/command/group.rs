@@ -53,10 +53,48 @@ fn terminate_process_with_id(pid: u32) -> Result<(), String> {
 
 // -------------------------------------------------------------------------------------------------
 
+/// Command group a restic command gets registered with while it's running.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CommandGroup {
+    /// The command does not belong to any group.
+    Ungrouped,
+    /// All other running commands in the group are terminated before the command starts.
+    Exclusive(&'static str),
+    /// The command runs next to other commands in the group, until the group gets terminated.
+    Shared(&'static str),
+}
+
+impl CommandGroup {
+    /// Name of the command group, if any.
+    pub fn name(&self) -> Option<&'static str> {
+        match self {
+            Self::Ungrouped => None,
+            Self::Exclusive(name) | Self::Shared(name) => Some(name),
+        }
+    }
+}
+
+impl From<&'static str> for CommandGroup {
+    fn from(name: &'static str) -> Self {
+        Self::Exclusive(name)
+    }
+}
+
+impl From<Option<&'static str>> for CommandGroup {
+    fn from(name: Option<&'static str>) -> Self {
+        name.map_or(Self::Ungrouped, Self::Exclusive)
+    }
+}
+
+// -------------------------------------------------------------------------------------------------
+
 lazy_static! {
     /// Currently running processes mapped by command group names.
     static ref RUNNING_RESTIC_COMMANDS: RwLock<HashMap<String, Vec<u32>>> =
         RwLock::new(HashMap::new());
+    /// Number of `terminate_all_commands_in_group` calls mapped by command group names.
+    static ref COMMAND_GROUP_TERMINATIONS: RwLock<HashMap<String, usize>> =
+        RwLock::new(HashMap::new());
 }
 
 // test with a command exit code if a command got aborted, probably via `terminate_all_commands_in_group`
@@ -77,6 +115,11 @@ pub fn process_was_terminated(status: &ExitStatus) -> bool {
 
 /// Kill all running commands from the given command group.
 pub fn terminate_all_commands_in_group(command_group: &str) -> Result<(), String> {
+    *COMMAND_GROUP_TERMINATIONS
+        .write()
+        .map_err(|err| err.to_string())?
+        .entry(command_group.to_string())
+        .or_default() += 1;
     let running_child_ids = {
         if let Some(child_ids) = RUNNING_RESTIC_COMMANDS
             .write()
@@ -103,6 +146,21 @@ pub fn terminate_all_commands_in_group(command_group: &str) -> Result<(), String
     Ok(())
 }
 
+/// Number of times the given command group got terminated. Allows detecting if a command
+/// got superseded by another exclusive command, e.g. while waiting to retry it.
+pub fn command_group_terminations(command_group: &str) -> usize {
+    COMMAND_GROUP_TERMINATIONS
+        .read()
+        .ok()
+        .and_then(|terminations| terminations.get(command_group).copied())
+        .unwrap_or(0)
+}
+
+/// Kill a single running command, e.g. because it timed out.
+pub fn terminate_command(child_id: u32) -> Result<(), String> {
+    terminate_process_with_id(child_id)
+}
+
 /// Register the given child it with a command group.
 pub fn add_command_to_group(command_group: &str, child_id: u32) -> Result<(), String> {
     log::debug!("Process in group '{command_group}' with PID '{child_id}' started...");
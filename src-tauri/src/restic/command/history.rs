@@ -0,0 +1,363 @@
+use std::{
+    env,
+    ffi::OsStr,
+    fs,
+    io::{self, BufRead, Write},
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::{Instant, SystemTime},
+};
+
+use lazy_static::lazy_static;
+
+use crate::restic::{redact, redact_repository, Error, ErrorKind, Location};
+
+// -------------------------------------------------------------------------------------------------
+
+/// Name of the operation history file in the app data directory.
+pub const OPERATION_HISTORY_FILE_NAME: &str = "operations.jsonl";
+
+/// Max number of records in the history file. Full history files get archived next to the
+/// history file when the history file gets opened, so no records get lost.
+const MAX_HISTORY_RECORDS: usize = 10000;
+
+/// Restic commands which only run when the user started them and thus always get recorded.
+/// Routine commands, such as listings, previews or thumbnail dumps, only get recorded when
+/// marked as user started.
+const USER_COMMANDS: &[&str] = &["restore"];
+
+// -------------------------------------------------------------------------------------------------
+
+/// Outcome of a recorded restic operation.
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum OperationStatus {
+    Succeeded,
+    Failed,
+    /// Terminated by the app, e.g. when the operation got superseded or the user cancelled it.
+    Cancelled,
+}
+
+/// A single executed restic operation, as stored in the operation history.
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct OperationRecord {
+    /// RFC 3339 start time of the operation.
+    pub timestamp: String,
+    /// Name of the OS user who ran the app.
+    pub user: String,
+    /// Repository the operation ran in, with redacted credentials.
+    pub repository: String,
+    /// Restic command name, e.g. "restore".
+    pub command: String,
+    /// All restic args, including location specific args, with redacted secrets.
+    pub args: Vec<String>,
+    pub duration_ms: u64,
+    pub status: OperationStatus,
+    pub exit_code: Option<i32>,
+    pub error: Option<String>,
+    /// Number of bytes restic wrote to the app, e.g. dumped file contents.
+    pub bytes_transferred: Option<u64>,
+}
+
+// -------------------------------------------------------------------------------------------------
+
+lazy_static! {
+    /// Path to the operation history file. Records get dropped when this is not set.
+    static ref HISTORY_FILE: Mutex<Option<PathBuf>> = Mutex::new(None);
+}
+
+/// Set the global operation history file, which all restic operations get appended to.
+pub fn set_operation_history_file(path: PathBuf) {
+    if let Some(parent) = path.parent() {
+        if let Err(err) = fs::create_dir_all(parent) {
+            log::warn!("Failed to create operation history directory: {err}");
+        }
+    }
+    if let Err(err) = rotate_history_file(&path, MAX_HISTORY_RECORDS) {
+        log::warn!("Failed to archive operation history: {err}");
+    }
+    match HISTORY_FILE.lock() {
+        Ok(mut history_file) => *history_file = Some(path),
+        Err(err) => log::error!("Failed to set operation history file: {err}"),
+    }
+}
+
+/// Recorded restic operations, oldest first. When a limit is given, only the latest
+/// `limit` records are returned.
+pub fn operation_history(limit: Option<usize>) -> Result<Vec<OperationRecord>, Error> {
+    let Some(path) = history_file()? else {
+        return Ok(vec![]);
+    };
+    let records = read_history_file(&path)?;
+    let skip = limit.map_or(0, |limit| records.len().saturating_sub(limit));
+    Ok(records.into_iter().skip(skip).collect())
+}
+
+/// Export all recorded restic operations as JSON lines to the given file.
+pub fn export_operation_history(file: &Path) -> Result<(), Error> {
+    let mut output = fs::File::create(file)?;
+    if let Some(path) = history_file()? {
+        if path.exists() {
+            // NB: lock the history, so no partially written records get exported
+            let _lock = HISTORY_FILE.lock();
+            io::copy(&mut fs::File::open(path)?, &mut output)?;
+        }
+    }
+    output.flush()?;
+    Ok(())
+}
+
+// -------------------------------------------------------------------------------------------------
+
+/// A running restic operation, which gets appended to the operation history when finished,
+/// if the user started it. Operations which get dropped before they got finished are recorded
+/// as cancelled.
+pub(super) struct Operation {
+    record: Option<OperationRecord>,
+    start_time: Instant,
+    user_started: bool,
+}
+
+impl Operation {
+    /// Start recording a restic operation with the given full restic args.
+    pub fn start<S: AsRef<OsStr>>(location: &Location, args: &[S]) -> Self {
+        let args = args
+            .iter()
+            .map(|arg| arg.as_ref().to_string_lossy().to_string())
+            .collect::<Vec<_>>();
+        let record = OperationRecord {
+            timestamp: chrono::DateTime::<chrono::Utc>::from(SystemTime::now()).to_rfc3339(),
+            user: env::var("USER")
+                .or_else(|_| env::var("USERNAME"))
+                .unwrap_or_default(),
+            repository: redact_repository(location),
+            command: args.first().cloned().unwrap_or_default(),
+            args: args.iter().map(|arg| redact(arg)).collect(),
+            duration_ms: 0,
+            status: OperationStatus::Cancelled,
+            exit_code: None,
+            error: None,
+            bytes_transferred: None,
+        };
+        Self {
+            user_started: USER_COMMANDS.contains(&record.command.as_str()),
+            record: Some(record),
+            start_time: Instant::now(),
+        }
+    }
+
+    /// Mark a routine command as started by the user, e.g. a dump of a file the user saved,
+    /// so it gets recorded in the operation history.
+    pub fn mark_user_started(&mut self) {
+        self.user_started = true;
+    }
+
+    /// Record the operation's result and the number of bytes restic wrote to the app.
+    pub fn finish<T>(mut self, result: &Result<T, Error>, bytes_transferred: Option<u64>) {
+        if let Some(record) = self.record.as_mut() {
+            match result {
+                Ok(_) => {
+                    record.status = OperationStatus::Succeeded;
+                    record.exit_code = Some(0);
+                }
+                Err(err) => {
+                    record.status = if err.kind == ErrorKind::Cancelled {
+                        OperationStatus::Cancelled
+                    } else {
+                        OperationStatus::Failed
+                    };
+                    record.exit_code = err.exit_code;
+                    record.error = Some(err.message.clone());
+                }
+            }
+            record.bytes_transferred = bytes_transferred;
+        }
+        self.record();
+    }
+
+    // Append the operation to the history file, if the user started it.
+    fn record(&mut self) {
+        let Some(mut record) = self.record.take() else {
+            return;
+        };
+        if !self.user_started {
+            return;
+        }
+        record.duration_ms = self.start_time.elapsed().as_millis() as u64;
+        if let Err(err) = append_to_history_file(&record) {
+            log::warn!("Failed to record restic operation: {err}");
+        }
+    }
+}
+
+impl Drop for Operation {
+    fn drop(&mut self) {
+        self.record();
+    }
+}
+
+// -------------------------------------------------------------------------------------------------
+
+// Path of the history file, if any.
+fn history_file() -> Result<Option<PathBuf>, Error> {
+    Ok(HISTORY_FILE
+        .lock()
+        .map_err(|err| Error::from(format!("Failed to access operation history: {err}")))?
+        .clone())
+}
+
+// Append a single record as JSON line to the history file.
+fn append_to_history_file(record: &OperationRecord) -> Result<(), Error> {
+    let history_file = HISTORY_FILE
+        .lock()
+        .map_err(|err| Error::from(format!("Failed to access operation history: {err}")))?;
+    let Some(path) = history_file.as_ref() else {
+        return Ok(());
+    };
+    append_record(path, record)
+}
+
+// Append a single record as JSON line to the given history file.
+fn append_record(path: &Path, record: &OperationRecord) -> Result<(), Error> {
+    let mut line = serde_json::to_string(record)
+        .map_err(|err| Error::from(format!("Failed to serialize operation: {err}")))?;
+    line.push('\n');
+    fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)?
+        .write_all(line.as_bytes())?;
+    Ok(())
+}
+
+// Read all valid records from the given history file. Broken lines are skipped.
+fn read_history_file(path: &Path) -> Result<Vec<OperationRecord>, Error> {
+    if !path.exists() {
+        return Ok(vec![]);
+    }
+    let file = io::BufReader::new(fs::File::open(path)?);
+    let mut records = vec![];
+    for line in file.lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        match serde_json::from_str::<OperationRecord>(&line) {
+            Ok(record) => records.push(record),
+            Err(err) => log::warn!("Skipping invalid operation history record: {err}"),
+        }
+    }
+    Ok(records)
+}
+
+// Move the given history file to a new archive file next to it, when it holds more than the
+// given number of records. Archives are named by the time they got archived, e.g.
+// `operations-20250101T120000Z.jsonl`. Returns the archive's path, if any.
+fn rotate_history_file(path: &Path, max_records: usize) -> Result<Option<PathBuf>, Error> {
+    if read_history_file(path)?.len() <= max_records {
+        return Ok(None);
+    }
+    let stem = path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let timestamp =
+        chrono::DateTime::<chrono::Utc>::from(SystemTime::now()).format("%Y%m%dT%H%M%SZ");
+    let mut archive_path = path.with_file_name(format!("{stem}-{timestamp}.jsonl"));
+    let mut index = 1;
+    while archive_path.exists() {
+        archive_path = path.with_file_name(format!("{stem}-{timestamp}-{index}.jsonl"));
+        index += 1;
+    }
+    fs::rename(path, &archive_path)?;
+    Ok(Some(archive_path))
+}
+
+// -------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir() -> PathBuf {
+        let dir = env::temp_dir().join(format!(
+            "restic-browser-history-test-{}",
+            crate::restic::new_secret_id()
+        ));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn record(command: &str) -> OperationRecord {
+        let location = Location {
+            path: "/repo".to_string(),
+            ..Location::default()
+        };
+        let mut operation = Operation::start(&location, &[command, "latest"]);
+        operation.record.take().unwrap()
+    }
+
+    #[test]
+    fn append_and_read_records() {
+        let dir = temp_dir();
+        let path = dir.join(OPERATION_HISTORY_FILE_NAME);
+        assert!(read_history_file(&path).unwrap().is_empty());
+        append_record(&path, &record("restore")).unwrap();
+        // broken lines, e.g. from a crash while writing, get skipped
+        fs::OpenOptions::new()
+            .append(true)
+            .open(&path)
+            .unwrap()
+            .write_all(b"{\"timestamp\":\n\n")
+            .unwrap();
+        append_record(&path, &record("dump")).unwrap();
+        let records = read_history_file(&path).unwrap();
+        assert_eq!(
+            records
+                .iter()
+                .map(|record| (record.command.as_str(), record.repository.as_str()))
+                .collect::<Vec<_>>(),
+            [("restore", "/repo"), ("dump", "/repo")]
+        );
+        assert_eq!(records[0].args, ["restore", "latest"]);
+        assert_eq!(records[0].status, OperationStatus::Cancelled);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn archive_full_history_files() {
+        let dir = temp_dir();
+        let path = dir.join(OPERATION_HISTORY_FILE_NAME);
+        for _ in 0..3 {
+            append_record(&path, &record("restore")).unwrap();
+        }
+        assert_eq!(rotate_history_file(&path, 3).unwrap(), None);
+        append_record(&path, &record("restore")).unwrap();
+        let archive = rotate_history_file(&path, 3).unwrap().unwrap();
+        // no records get lost: the full history moves to the archive
+        assert!(!path.exists());
+        assert_eq!(read_history_file(&archive).unwrap().len(), 4);
+        let archive_name = archive.file_name().unwrap().to_string_lossy().to_string();
+        assert!(archive_name.starts_with("operations-") && archive_name.ends_with(".jsonl"));
+        // archives never get overwritten
+        for _ in 0..4 {
+            append_record(&path, &record("restore")).unwrap();
+        }
+        let next_archive = rotate_history_file(&path, 3).unwrap().unwrap();
+        assert_ne!(next_archive, archive);
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 2);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn only_record_user_started_operations() {
+        let location = Location::default();
+        assert!(Operation::start(&location, &["restore", "latest"]).user_started);
+        assert!(!Operation::start(&location, &["dump", "latest", "/file"]).user_started);
+        assert!(!Operation::start(&location, &["ls", "--json", "latest"]).user_started);
+        let mut operation = Operation::start(&location, &["dump", "latest", "/file"]);
+        operation.mark_user_started();
+        assert!(operation.user_started);
+    }
+}
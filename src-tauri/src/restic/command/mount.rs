@@ -0,0 +1,318 @@
+use std::{
+    ffi::OsString,
+    fs,
+    io::Read,
+    path::{Path, PathBuf},
+    process::{Child, Output},
+    sync::{Arc, Mutex},
+    thread,
+    time::{Duration, Instant, SystemTime},
+};
+
+use super::{group::terminate_command, history::Operation, password::PasswordHandoff, Program};
+use crate::restic::{Error, ErrorKind, File};
+
+// -------------------------------------------------------------------------------------------------
+
+/// Max time a new `restic mount` process may take to serve the repository.
+const MOUNT_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Time a mount process gets to unmount the repository before it gets killed.
+const UNMOUNT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Go `os.FileMode` type bits, as used by restic for file modes.
+const GO_MODE_DIR: i64 = 1 << 31;
+const GO_MODE_SYMLINK: i64 = 1 << 27;
+
+// -------------------------------------------------------------------------------------------------
+
+/// A long-lived `restic mount` process, as started by `Program::mount`.
+///
+/// The process loads the repository index once and then serves all snapshots as file
+/// system, so directories are listed with plain file system reads instead of spawning
+/// restic for each listing. The repository gets unmounted when the session gets dropped.
+pub struct MountSession {
+    child: Child,
+    mount_dir: PathBuf,
+    // recorded when the session gets unmounted
+    operation: Option<Operation>,
+    // keeps temporary password files alive until the process finished
+    _password: PasswordHandoff,
+}
+
+impl MountSession {
+    /// Wait until the given spawned `restic mount` process serves the repository in the
+    /// given directory.
+    pub(super) fn new(
+        mut child: Child,
+        args: &[OsString],
+        mount_dir: PathBuf,
+        password: PasswordHandoff,
+        operation: Operation,
+    ) -> Result<Self, Error> {
+        // drain stderr in a separate thread, so the child never blocks on a full stderr pipe
+        let stderr = Arc::new(Mutex::new(Vec::new()));
+        if let Some(mut child_stderr) = child.stderr.take() {
+            let stderr = Arc::clone(&stderr);
+            thread::spawn(move || {
+                let mut buffer = [0; 4096];
+                while let Ok(len @ 1..) = child_stderr.read(&mut buffer) {
+                    if let Ok(mut stderr) = stderr.lock() {
+                        stderr.extend_from_slice(&buffer[..len]);
+                    }
+                }
+            });
+        }
+        // NB: create the session first, so the process gets terminated on errors
+        let mut session = Self {
+            child,
+            mount_dir,
+            operation: Some(operation),
+            _password: password,
+        };
+        let start_time = Instant::now();
+        while !session.mount_dir.join("ids").exists() {
+            if let Some(status) = session.child.try_wait()? {
+                // give the stderr reader a chance to catch up
+                thread::sleep(Duration::from_millis(100));
+                let output = Output {
+                    status,
+                    stdout: vec![],
+                    stderr: stderr
+                        .lock()
+                        .map(|stderr| stderr.clone())
+                        .unwrap_or_default(),
+                };
+                return Err(session.fail(Program::handle_run_error(args, &output)));
+            }
+            if start_time.elapsed() > MOUNT_TIMEOUT {
+                return Err(session.fail(Error::new(
+                    ErrorKind::NetworkError,
+                    format!(
+                        "Restic mount timed out after {} seconds",
+                        MOUNT_TIMEOUT.as_secs()
+                    ),
+                )));
+            }
+            thread::sleep(Duration::from_millis(100));
+        }
+        log::info!(
+            "Mounted repository at '{}' with PID {}",
+            session.mount_dir.display(),
+            session.child.id()
+        );
+        Ok(session)
+    }
+
+    // Record the given mount error as the session's operation result.
+    fn fail(&mut self, err: Error) -> Error {
+        if let Some(operation) = self.operation.take() {
+            operation.finish(&Err::<(), _>(err.clone()), None);
+        }
+        err
+    }
+
+    /// Files at the given path in the given snapshot, as listed by `restic ls`: the node at
+    /// the given path, followed by its direct children when it's a directory.
+    pub fn ls(&self, snapshot_id: &str, path: &str) -> Result<Vec<File>, Error> {
+        let ids_dir = self.mount_dir.join("ids");
+        // restic mount names snapshot dirs by short ids
+        let snapshot_dir = [snapshot_id, &snapshot_id[..8.min(snapshot_id.len())]]
+            .into_iter()
+            .map(|id| ids_dir.join(id))
+            .find(|dir| dir.exists())
+            .ok_or_else(|| {
+                Error::new(
+                    ErrorKind::NotFound,
+                    format!("Can't resolve snapshot with id {snapshot_id}"),
+                )
+            })?;
+        let path = format!("/{}", path.trim_matches('/'));
+        let target = snapshot_dir.join(path.trim_start_matches('/'));
+        let Ok(metadata) = fs::symlink_metadata(&target) else {
+            // restic lists nothing for missing paths
+            return Ok(vec![]);
+        };
+        let mut files = vec![];
+        if path != "/" {
+            files.push(Self::file(&target, path.clone(), &metadata));
+        }
+        if metadata.is_dir() {
+            for entry in fs::read_dir(&target)? {
+                let entry = entry?;
+                let name = entry.file_name().to_string_lossy().to_string();
+                let file_path = format!("{}/{name}", path.trim_end_matches('/'));
+                let metadata = fs::symlink_metadata(entry.path())?;
+                files.push(Self::file(&entry.path(), file_path, &metadata));
+            }
+        }
+        Ok(files)
+    }
+
+    // Convert mounted file metadata to a restic `ls` file.
+    fn file(file: &Path, path: String, metadata: &fs::Metadata) -> File {
+        let format_time = |time: std::io::Result<SystemTime>| {
+            time.map(|time| chrono::DateTime::<chrono::Utc>::from(time).to_rfc3339())
+                .unwrap_or_default()
+        };
+        let (type_, type_mode) = if metadata.is_dir() {
+            ("dir", GO_MODE_DIR)
+        } else if metadata.is_symlink() {
+            ("symlink", GO_MODE_SYMLINK)
+        } else {
+            ("file", 0)
+        };
+        #[cfg(unix)]
+        let (uid, gid, permissions, ctime) = {
+            use std::os::unix::fs::MetadataExt;
+            (
+                metadata.uid() as i64,
+                metadata.gid() as i64,
+                (metadata.mode() & 0o7777) as i64,
+                chrono::DateTime::from_timestamp(metadata.ctime(), metadata.ctime_nsec() as u32)
+                    .map(|time| time.to_rfc3339())
+                    .unwrap_or_default(),
+            )
+        };
+        #[cfg(not(unix))]
+        let (uid, gid, permissions, ctime) = (0, 0, 0, String::new());
+        File {
+            name: file
+                .file_name()
+                .map(|name| name.to_string_lossy().to_string())
+                .unwrap_or_default(),
+            type_: type_.to_string(),
+            path,
+            uid,
+            gid,
+            size: if metadata.is_file() {
+                metadata.len() as i64
+            } else {
+                0
+            },
+            mode: type_mode | permissions,
+            mtime: format_time(metadata.modified()),
+            atime: format_time(metadata.accessed()),
+            ctime,
+        }
+    }
+}
+
+impl std::fmt::Debug for MountSession {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("MountSession")
+            .field("pid", &self.child.id())
+            .field("mount_dir", &self.mount_dir)
+            .finish()
+    }
+}
+
+impl Drop for MountSession {
+    fn drop(&mut self) {
+        // restic unmounts the repository when it gets terminated
+        log::info!("Unmounting repository at '{}'...", self.mount_dir.display());
+        if let Err(err) = terminate_command(self.child.id()) {
+            log::warn!("Failed to terminate restic mount process: {err}");
+        }
+        let start_time = Instant::now();
+        while let Ok(None) = self.child.try_wait() {
+            if start_time.elapsed() > UNMOUNT_TIMEOUT {
+                log::warn!("Restic mount process did not exit in time: killing it");
+                let _ = self.child.kill();
+                let _ = self.child.wait();
+                break;
+            }
+            thread::sleep(Duration::from_millis(50));
+        }
+        if let Some(operation) = self.operation.take() {
+            operation.finish(&Ok::<(), Error>(()), None);
+        }
+        // NB: only remove the mount dir when it got unmounted, never its content
+        if let Err(err) = fs::remove_dir(&self.mount_dir) {
+            log::warn!("Failed to remove mount directory: {err}");
+        }
+    }
+}
+
+// -------------------------------------------------------------------------------------------------
+
+#[cfg(all(test, unix))]
+mod tests {
+    use std::process::{Command, Stdio};
+
+    use super::*;
+    use crate::restic::{command::password::PasswordStrategy, Location};
+
+    // Create a mount session for the given shell script, as spawned by `Program::mount`.
+    fn mount(script: &str, mount_dir: &Path) -> Result<MountSession, Error> {
+        let location = Location {
+            path: mount_dir.to_string_lossy().to_string(),
+            ..Location::default()
+        };
+        let args = ["sh", "-c", script];
+        let child = Command::new("sh")
+            .args(["-c", script])
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::piped())
+            .spawn()
+            .unwrap();
+        MountSession::new(
+            child,
+            &args.iter().map(OsString::from).collect::<Vec<_>>(),
+            mount_dir.to_path_buf(),
+            PasswordHandoff::new(PasswordStrategy::Environment, String::new()).unwrap(),
+            Operation::start(&location, &args),
+        )
+    }
+
+    fn temp_dir() -> PathBuf {
+        std::env::temp_dir().join(format!(
+            "restic-browser-mount-test-{}",
+            crate::restic::new_secret_id()
+        ))
+    }
+
+    #[test]
+    fn remove_mount_dir_when_mounting_failed() {
+        let mount_dir = temp_dir();
+        fs::create_dir_all(&mount_dir).unwrap();
+        let err = mount("echo 'Fatal: wrong password' >&2; exit 12", &mount_dir).unwrap_err();
+        assert_eq!(err.kind, ErrorKind::WrongPassword);
+        assert!(!mount_dir.exists());
+    }
+
+    #[test]
+    fn list_snapshots_by_full_or_short_id() {
+        let mount_dir = temp_dir();
+        let snapshot_id = "0123456789abcdef0123456789abcdef";
+        let snapshot_dir = mount_dir.join("ids").join(&snapshot_id[..8]);
+        fs::create_dir_all(snapshot_dir.join("dir")).unwrap();
+        fs::write(snapshot_dir.join("dir").join("file.txt"), b"content").unwrap();
+
+        let session = mount("exec sleep 30", &mount_dir).unwrap();
+        // restic mount names snapshot dirs by short ids
+        let root = session.ls(snapshot_id, "/").unwrap();
+        assert_eq!(root.len(), 1);
+        assert_eq!(
+            (root[0].path.as_str(), root[0].type_.as_str()),
+            ("/dir", "dir")
+        );
+        let dir = session.ls(&snapshot_id[..8], "dir/").unwrap();
+        assert_eq!(
+            dir.iter()
+                .map(|file| (file.path.as_str(), file.size))
+                .collect::<Vec<_>>(),
+            [("/dir", 0), ("/dir/file.txt", 7)]
+        );
+        // missing paths list nothing, unknown snapshots fail
+        assert!(session.ls(snapshot_id, "missing").unwrap().is_empty());
+        assert_eq!(
+            session.ls("fedcba98", "/").unwrap_err().kind,
+            ErrorKind::NotFound
+        );
+
+        drop(session);
+        fs::remove_dir_all(&mount_dir).unwrap();
+    }
+}
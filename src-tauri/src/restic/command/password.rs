@@ -0,0 +1,388 @@
+use std::{
+    env, fs,
+    io::{Read, Write},
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+    thread,
+    time::{Duration, Instant},
+};
+
+use shlex::Shlex;
+
+use crate::restic::{new_command, new_secret_id, redact, register_secret, Error, ErrorKind};
+
+// -------------------------------------------------------------------------------------------------
+
+/// Command line argument which runs the app as one-shot password helper for restic.
+const PASSWORD_HELPER_ARG: &str = "--restic-password-helper";
+
+/// File name prefix of temporary helper password files.
+const PASSWORD_FILE_PREFIX: &str = "restic-browser-password-";
+
+// -------------------------------------------------------------------------------------------------
+
+/// How repository passwords get passed to restic.
+#[derive(serde::Serialize, serde::Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum PasswordStrategy {
+    /// Pass the password via `RESTIC_PASSWORD`. Simple, but the environment of the restic
+    /// process can be read by all other processes of the same user.
+    #[default]
+    Environment,
+    /// Write the password into a pipe, which restic inherits and reads as `RESTIC_PASSWORD_FILE`.
+    /// Only supported on Unix systems: falls back to `Helper` on other platforms.
+    Pipe,
+    /// Let restic run this app as one-shot `RESTIC_PASSWORD_COMMAND` helper, which prints
+    /// and removes a temporary password file that only the current user can read.
+    Helper,
+}
+
+impl std::str::FromStr for PasswordStrategy {
+    type Err = Error;
+
+    /// Parse strategy names as used in CLI args and profiles: "environment", "pipe" or "helper".
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name.trim().to_lowercase().as_str() {
+            "environment" | "env" => Ok(Self::Environment),
+            "pipe" => Ok(Self::Pipe),
+            "helper" => Ok(Self::Helper),
+            _ => Err(Error::new(
+                ErrorKind::InvalidLocation,
+                format!("Invalid password strategy '{name}': expected environment, pipe or helper"),
+            )),
+        }
+    }
+}
+
+// -------------------------------------------------------------------------------------------------
+
+/// Hands a repository password to a single restic process with a `PasswordStrategy`.
+///
+/// Must be kept alive until the restic process has read the password. Temporary resources
+/// are released when dropped.
+pub(super) struct PasswordHandoff {
+    envs: Vec<(&'static str, String)>,
+    #[cfg(unix)]
+    pipe: Option<std::os::fd::OwnedFd>,
+    temp_file: Option<PathBuf>,
+}
+
+impl PasswordHandoff {
+    /// Prepare passing the given password with the given strategy.
+    pub fn new(strategy: PasswordStrategy, password: String) -> Result<Self, Error> {
+        let mut handoff = Self {
+            envs: vec![],
+            #[cfg(unix)]
+            pipe: None,
+            temp_file: None,
+        };
+        // NB: always set all password envs, so restic never picks up passwords from our own env
+        let (password, file, command) = match strategy {
+            PasswordStrategy::Environment => (password, String::new(), String::new()),
+            #[cfg(unix)]
+            PasswordStrategy::Pipe => {
+                let file = handoff.create_pipe(&password)?;
+                (String::new(), file, String::new())
+            }
+            #[cfg(not(unix))]
+            PasswordStrategy::Pipe => {
+                let command = handoff.create_helper(&password)?;
+                (String::new(), String::new(), command)
+            }
+            PasswordStrategy::Helper => {
+                let command = handoff.create_helper(&password)?;
+                (String::new(), String::new(), command)
+            }
+        };
+        handoff.envs = vec![
+            ("RESTIC_PASSWORD", password),
+            ("RESTIC_PASSWORD_FILE", file),
+            ("RESTIC_PASSWORD_COMMAND", command),
+        ];
+        Ok(handoff)
+    }
+
+    /// Let restic run the given password command to fetch the password by itself.
+    pub fn command(password_command: String) -> Self {
+        Self {
+            envs: vec![
+                ("RESTIC_PASSWORD", String::new()),
+                ("RESTIC_PASSWORD_FILE", String::new()),
+                ("RESTIC_PASSWORD_COMMAND", password_command),
+            ],
+            #[cfg(unix)]
+            pipe: None,
+            temp_file: None,
+        }
+    }
+
+    /// Set up the given restic command to receive the password: sets the password envs and
+    /// lets the command's process, and only this one, inherit the password pipe.
+    pub fn configure<'a>(&self, command: &'a mut Command) -> &'a mut Command {
+        command.envs(
+            self.envs
+                .iter()
+                .map(|(name, value)| (*name, value.as_str())),
+        );
+        #[cfg(unix)]
+        if let Some(pipe) = &self.pipe {
+            use std::os::{
+                fd::{AsRawFd, BorrowedFd},
+                unix::process::CommandExt,
+            };
+            let fd = pipe.as_raw_fd();
+            // SAFETY: runs in the forked child before exec and only calls the async-signal-safe
+            // fcntl on a file descriptor which is kept open until the process got spawned.
+            unsafe {
+                command.pre_exec(move || {
+                    use nix::fcntl::{fcntl, FcntlArg, FdFlag};
+                    fcntl(
+                        BorrowedFd::borrow_raw(fd),
+                        FcntlArg::F_SETFD(FdFlag::empty()),
+                    )?;
+                    Ok(())
+                });
+            }
+        }
+        command
+    }
+
+    /// Release resources which are no longer needed once the restic process got spawned.
+    pub fn spawned(&mut self) {
+        // close our copy of the pipe's read end: the child has its own one now
+        #[cfg(unix)]
+        self.pipe.take();
+    }
+
+    // Write the password into a new pipe and return the path restic can read it from.
+    // The pipe is close-on-exec, so other processes spawned meanwhile don't inherit it.
+    #[cfg(unix)]
+    fn create_pipe(&mut self, password: &str) -> Result<String, Error> {
+        use std::os::fd::AsRawFd;
+        let (reader, writer) = Self::new_cloexec_pipe()
+            .map_err(|err| Error::new(ErrorKind::Io, format!("Failed to create pipe: {err}")))?;
+        // passwords are way smaller than the pipe buffer, so this won't block
+        let mut writer = fs::File::from(writer);
+        writer.write_all(password.as_bytes())?;
+        drop(writer);
+        let file = format!("/dev/fd/{}", reader.as_raw_fd());
+        self.pipe = Some(reader);
+        Ok(file)
+    }
+
+    #[cfg(all(unix, not(target_vendor = "apple")))]
+    fn new_cloexec_pipe() -> nix::Result<(std::os::fd::OwnedFd, std::os::fd::OwnedFd)> {
+        nix::unistd::pipe2(nix::fcntl::OFlag::O_CLOEXEC)
+    }
+
+    // NB: there's no pipe2 on macOS: set the flag right after creating the pipe instead
+    #[cfg(target_vendor = "apple")]
+    fn new_cloexec_pipe() -> nix::Result<(std::os::fd::OwnedFd, std::os::fd::OwnedFd)> {
+        use nix::fcntl::{fcntl, FcntlArg, FdFlag};
+        let (reader, writer) = nix::unistd::pipe()?;
+        fcntl(&reader, FcntlArg::F_SETFD(FdFlag::FD_CLOEXEC))?;
+        fcntl(&writer, FcntlArg::F_SETFD(FdFlag::FD_CLOEXEC))?;
+        Ok((reader, writer))
+    }
+
+    // Write the password into a private temp file and return the helper command to read it.
+    fn create_helper(&mut self, password: &str) -> Result<String, Error> {
+        let helper = env::current_exe()?;
+        let temp_file =
+            env::temp_dir().join(format!("{PASSWORD_FILE_PREFIX}{}.tmp", new_secret_id()));
+        let mut options = fs::OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600);
+        }
+        options.open(&temp_file)?.write_all(password.as_bytes())?;
+        self.temp_file = Some(temp_file.clone());
+        // restic splits the command like a shell: quote paths, which may contain spaces
+        Ok(format!(
+            "\"{}\" {} \"{}\"",
+            helper.display(),
+            PASSWORD_HELPER_ARG,
+            temp_file.display()
+        ))
+    }
+}
+
+impl Drop for PasswordHandoff {
+    fn drop(&mut self) {
+        // remove unused helper password files, e.g. when restic failed before reading it
+        if let Some(temp_file) = self.temp_file.take() {
+            if temp_file.exists() {
+                if let Err(err) = fs::remove_file(&temp_file) {
+                    log::warn!("Failed to remove temporary password file: {err}");
+                }
+            }
+        }
+    }
+}
+
+// -------------------------------------------------------------------------------------------------
+
+/// Run the given password command, as restic does with `RESTIC_PASSWORD_COMMAND`, and return
+/// the password it printed. Commands which fail, print no password or don't finish within
+/// the given timeout are returned as `PasswordCommandFailed` errors.
+pub fn run_password_command(password_command: &str, timeout: Duration) -> Result<String, Error> {
+    let error = |message: String| Error::new(ErrorKind::PasswordCommandFailed, message);
+    let mut program_and_args = Shlex::new(password_command);
+    let program = program_and_args
+        .next()
+        .ok_or_else(|| error("Password command is empty".to_string()))?;
+    let mut child = new_command(&program.clone().into())
+        .args(program_and_args)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|err| error(format!("Failed to run password command '{program}': {err}")))?;
+    // drain outputs while waiting, so commands with lots of output don't block on full pipes
+    let stdout = read_in_background(child.stdout.take());
+    let stderr = read_in_background(child.stderr.take());
+    // NB: wait_timeout is not available in std, so poll the child
+    let start_time = Instant::now();
+    let status = loop {
+        if let Some(status) = child.try_wait()? {
+            break status;
+        }
+        if start_time.elapsed() > timeout {
+            let _ = child.kill();
+            let _ = child.wait();
+            return Err(error(format!(
+                "Password command '{program}' timed out after {} seconds",
+                timeout.as_secs()
+            )));
+        }
+        thread::sleep(Duration::from_millis(50));
+    };
+    let stdout = stdout.join().unwrap_or_default();
+    let stderr = stderr.join().unwrap_or_default();
+    if !status.success() {
+        let mut err = error(format!(
+            "Password command '{program}' failed with status {status}"
+        ));
+        err.exit_code = status.code();
+        err.stderr = Some(redact(String::from_utf8_lossy(&stderr).trim()));
+        return Err(err);
+    }
+    let password = String::from_utf8_lossy(&stdout)
+        .trim_end_matches(['\r', '\n'])
+        .to_string();
+    if password.is_empty() {
+        return Err(error(format!(
+            "Password command '{program}' returned an empty password"
+        )));
+    }
+    register_secret(&password);
+    Ok(password)
+}
+
+// Read the given child output until EOF in a background thread.
+fn read_in_background<R: Read + Send + 'static>(output: Option<R>) -> thread::JoinHandle<Vec<u8>> {
+    thread::spawn(move || {
+        let mut buffer = vec![];
+        if let Some(mut output) = output {
+            let _ = output.read_to_end(&mut buffer);
+        }
+        buffer
+    })
+}
+
+// -------------------------------------------------------------------------------------------------
+
+/// When the app got launched as password helper by restic, print and remove the password file
+/// passed in the given args and return the process exit code. Else returns None.
+pub fn run_password_helper(args: &[String]) -> Option<i32> {
+    let [_, arg, password_file] = args else {
+        return None;
+    };
+    if arg != PASSWORD_HELPER_ARG {
+        return None;
+    }
+    // only touch our own temp files: the helper removes the file after reading it
+    let password_file = Path::new(password_file);
+    let is_password_file = password_file.parent() == Some(env::temp_dir().as_path())
+        && password_file
+            .file_name()
+            .is_some_and(|name| name.to_string_lossy().starts_with(PASSWORD_FILE_PREFIX));
+    if !is_password_file {
+        eprintln!("Invalid password file: '{}'", password_file.display());
+        return Some(1);
+    }
+    let password = fs::read_to_string(password_file);
+    let _ = fs::remove_file(password_file);
+    match password {
+        Ok(password) => {
+            print!("{password}");
+            Some(0)
+        }
+        Err(err) => {
+            eprintln!("Failed to read password file: {err}");
+            Some(1)
+        }
+    }
+}
+
+// -------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_password_strategy() {
+        assert_eq!(
+            "pipe".parse::<PasswordStrategy>().unwrap(),
+            PasswordStrategy::Pipe
+        );
+        assert_eq!(
+            " Helper ".parse::<PasswordStrategy>().unwrap(),
+            PasswordStrategy::Helper
+        );
+        assert_eq!(
+            "environment".parse::<PasswordStrategy>().unwrap(),
+            PasswordStrategy::Environment
+        );
+        assert!("stdin".parse::<PasswordStrategy>().is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn password_command_with_large_output_does_not_block() {
+        // prints way more than a pipe buffer to stderr before printing the password
+        let password = run_password_command(
+            "sh -c 'head -c 1000000 /dev/zero >&2; echo large-output-test-password'",
+            Duration::from_secs(10),
+        )
+        .unwrap();
+        assert_eq!(password, "large-output-test-password");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn pipe_is_only_inherited_by_the_configured_command() {
+        let mut handoff = PasswordHandoff::new(PasswordStrategy::Pipe, "secret".into()).unwrap();
+        let file = handoff
+            .envs
+            .iter()
+            .find(|(name, _)| *name == "RESTIC_PASSWORD_FILE")
+            .map(|(_, file)| file.clone())
+            .unwrap();
+        // other processes don't inherit the close-on-exec pipe
+        let status = Command::new("sh")
+            .args(["-c", &format!("test -e {file}")])
+            .status()
+            .unwrap();
+        assert!(!status.success());
+        // the configured command can read the password from it
+        let mut command = Command::new("sh");
+        command.args(["-c", "cat \"$RESTIC_PASSWORD_FILE\""]);
+        let output = handoff.configure(&mut command).output().unwrap();
+        handoff.spawned();
+        assert_eq!(String::from_utf8_lossy(&output.stdout), "secret");
+    }
+}
@@ -0,0 +1,179 @@
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc, Arc,
+    },
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+use super::group::terminate_command;
+
+// -------------------------------------------------------------------------------------------------
+
+/// Restic commands which only read from the repository and thus can safely be retried.
+const IDEMPOTENT_COMMANDS: &[&str] = &["cat", "find", "ls", "snapshots"];
+
+/// Restic commands which may legitimately run for hours and thus never time out.
+const LONG_RUNNING_COMMANDS: &[&str] = &["dump", "restore"];
+
+/// Upper limit for retry delays.
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(60);
+
+// -------------------------------------------------------------------------------------------------
+
+/// Timeout and retry policy for restic commands of a location.
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase", default)]
+pub struct CommandPolicy {
+    /// Max number of seconds a restic command may run until it gets killed. 0 disables
+    /// timeouts. Dumps and restores are not affected, as they may legitimately take hours.
+    pub timeout_secs: u64,
+    /// Number of retries for idempotent read commands which failed with network errors
+    /// or timed out.
+    pub retries: u32,
+    /// Delay before the first retry in milliseconds. It's doubled with each further retry.
+    pub retry_delay_ms: u64,
+}
+
+impl Default for CommandPolicy {
+    fn default() -> Self {
+        Self {
+            timeout_secs: 300,
+            retries: 2,
+            retry_delay_ms: 1000,
+        }
+    }
+}
+
+impl CommandPolicy {
+    /// Command timeout for the given restic args, if any.
+    pub fn timeout_for(&self, args: &[&str]) -> Option<Duration> {
+        let is_long_running = args
+            .first()
+            .is_some_and(|command| LONG_RUNNING_COMMANDS.contains(command));
+        (self.timeout_secs > 0 && !is_long_running).then(|| Duration::from_secs(self.timeout_secs))
+    }
+
+    /// Number of retries for the given restic args.
+    pub fn retries_for(&self, args: &[&str]) -> u32 {
+        if args
+            .first()
+            .is_some_and(|command| IDEMPOTENT_COMMANDS.contains(command))
+        {
+            self.retries
+        } else {
+            0
+        }
+    }
+
+    /// Backoff delay before the given zero based retry attempt.
+    pub fn retry_delay(&self, attempt: u32) -> Duration {
+        Duration::from_millis(self.retry_delay_ms.saturating_mul(1 << attempt.min(16)))
+            .min(MAX_RETRY_DELAY)
+    }
+}
+
+// -------------------------------------------------------------------------------------------------
+
+/// Kills a running restic process through the command group termination path, when it did
+/// not finish within a timeout.
+pub(super) struct Watchdog {
+    finished: Option<mpsc::Sender<()>>,
+    thread: Option<JoinHandle<()>>,
+    timed_out: Arc<AtomicBool>,
+}
+
+impl Watchdog {
+    /// Start watching the process with the given id. Without timeout, nothing is watched.
+    pub fn new(child_id: u32, timeout: Option<Duration>) -> Self {
+        let timed_out = Arc::new(AtomicBool::new(false));
+        let Some(timeout) = timeout else {
+            return Self {
+                finished: None,
+                thread: None,
+                timed_out,
+            };
+        };
+        let (finished_sender, finished_receiver) = mpsc::channel::<()>();
+        let thread = {
+            let timed_out = Arc::clone(&timed_out);
+            thread::spawn(move || {
+                if let Err(mpsc::RecvTimeoutError::Timeout) =
+                    finished_receiver.recv_timeout(timeout)
+                {
+                    log::warn!(
+                        "Process with PID {child_id} timed out after {} seconds",
+                        timeout.as_secs()
+                    );
+                    timed_out.store(true, Ordering::SeqCst);
+                    if let Err(err) = terminate_command(child_id) {
+                        log::error!("Failed to kill timed out process: {err}");
+                    }
+                }
+            })
+        };
+        Self {
+            finished: Some(finished_sender),
+            thread: Some(thread),
+            timed_out,
+        }
+    }
+
+    /// Stop watching the process. Returns true when it got killed because it timed out.
+    pub fn finish(mut self) -> bool {
+        self.finished.take();
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+        self.timed_out.load(Ordering::SeqCst)
+    }
+}
+
+// -------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_retry_idempotent_commands() {
+        let policy = CommandPolicy::default();
+        assert_eq!(policy.retries_for(&["snapshots", "--json"]), 2);
+        assert_eq!(policy.retries_for(&["ls", "abc", "/"]), 2);
+        assert_eq!(policy.retries_for(&["restore", "abc"]), 0);
+        assert_eq!(policy.retries_for(&["forget", "abc"]), 0);
+        assert_eq!(policy.retries_for(&[]), 0);
+    }
+
+    #[test]
+    fn double_retry_delays_up_to_the_max_delay() {
+        let policy = CommandPolicy::default();
+        assert_eq!(policy.retry_delay(0), Duration::from_secs(1));
+        assert_eq!(policy.retry_delay(1), Duration::from_secs(2));
+        assert_eq!(policy.retry_delay(3), Duration::from_secs(8));
+        assert_eq!(policy.retry_delay(10), MAX_RETRY_DELAY);
+        assert_eq!(policy.retry_delay(u32::MAX), MAX_RETRY_DELAY);
+        let policy = CommandPolicy {
+            retry_delay_ms: u64::MAX,
+            ..Default::default()
+        };
+        assert_eq!(policy.retry_delay(2), MAX_RETRY_DELAY);
+    }
+
+    #[test]
+    fn restores_and_dumps_never_time_out() {
+        let policy = CommandPolicy::default();
+        assert_eq!(
+            policy.timeout_for(&["ls", "abc"]),
+            Some(Duration::from_secs(300))
+        );
+        assert_eq!(policy.timeout_for(&["restore", "abc"]), None);
+        assert_eq!(policy.timeout_for(&["dump", "abc", "/file"]), None);
+        let policy = CommandPolicy {
+            timeout_secs: 0,
+            ..Default::default()
+        };
+        assert_eq!(policy.timeout_for(&["ls", "abc"]), None);
+    }
+}
@@ -0,0 +1,272 @@
+use std::{ffi::OsString, io, process::Output, time::Duration};
+
+use tokio::{
+    io::{AsyncRead, AsyncReadExt},
+    process::Child,
+};
+use tokio_util::sync::CancellationToken;
+
+use super::{
+    group::{add_command_to_group, remove_command_from_group, terminate_command},
+    history::Operation,
+    password::PasswordHandoff,
+    scheduler::JobGuard,
+    Program,
+};
+use crate::restic::{Error, ErrorKind};
+
+// -------------------------------------------------------------------------------------------------
+
+/// Time a cancelled or timed out process gets to exit gracefully before it gets killed.
+const TERMINATION_GRACE_PERIOD: Duration = Duration::from_secs(5);
+
+// -------------------------------------------------------------------------------------------------
+
+/// A running restic process, as spawned by `Program::spawn_async`.
+///
+/// The process gets terminated when its cancellation token gets cancelled while waiting for
+/// it, and killed when it gets dropped before it finished.
+pub struct AsyncProcess {
+    child: Child,
+    args: Vec<OsString>,
+    command_group: Option<&'static str>,
+    child_id: Option<u32>,
+    cancellation_token: CancellationToken,
+    // recorded when the process finished, or as cancelled when it gets dropped before
+    operation: Option<Operation>,
+    _password: PasswordHandoff,
+    _job: JobGuard,
+}
+
+impl AsyncProcess {
+    /// Wrap a spawned child and register it with the given command group.
+    pub(super) fn new(
+        child: Child,
+        args: Vec<OsString>,
+        command_group: Option<&'static str>,
+        cancellation_token: CancellationToken,
+        password: PasswordHandoff,
+        job: JobGuard,
+        operation: Operation,
+    ) -> Self {
+        let child_id = child.id();
+        if let (Some(command_group), Some(child_id)) = (command_group, child_id) {
+            if let Err(err) = add_command_to_group(command_group, child_id) {
+                log::error!("Failed to add process child: {err}");
+            }
+        }
+        Self {
+            child,
+            args,
+            command_group,
+            child_id,
+            cancellation_token,
+            operation: Some(operation),
+            _password: password,
+            _job: job,
+        }
+    }
+
+    /// Wait until the process finished, got cancelled or timed out and return its stdout.
+    /// Timed out processes are returned as `NetworkError`.
+    pub async fn wait_with_output(mut self, timeout: Option<Duration>) -> Result<Vec<u8>, Error> {
+        enum Outcome {
+            Finished(io::Result<Output>),
+            Cancelled,
+            TimedOut,
+        }
+        let stdout = self.child.stdout.take();
+        let stderr = self.child.stderr.take();
+        let child = &mut self.child;
+        // read stdout and stderr while waiting, so the process never blocks on full pipes
+        let finished = async move {
+            let (status, stdout, stderr) =
+                tokio::join!(child.wait(), read_to_end(stdout), read_to_end(stderr));
+            Ok(Output {
+                status: status?,
+                stdout: stdout?,
+                stderr: stderr?,
+            })
+        };
+        let timed_out = async move {
+            match timeout {
+                Some(timeout) => tokio::time::sleep(timeout).await,
+                None => std::future::pending().await,
+            }
+        };
+        let outcome = tokio::select! {
+            output = finished => Outcome::Finished(output),
+            _ = self.cancellation_token.cancelled() => Outcome::Cancelled,
+            _ = timed_out => Outcome::TimedOut,
+        };
+        let result = match outcome {
+            Outcome::Finished(output) => match output {
+                Ok(output) if output.status.success() => Ok(output.stdout),
+                Ok(output) => Err(Program::handle_run_error(&self.args, &output)),
+                Err(err) => Err(err.into()),
+            },
+            Outcome::Cancelled => {
+                log::info!("Restic '{:?}' command got cancelled", self.args);
+                self.terminate().await;
+                Err(Error::cancelled())
+            }
+            Outcome::TimedOut => {
+                log::warn!("Restic '{:?}' command timed out", self.args);
+                self.terminate().await;
+                Err(Error::new(
+                    ErrorKind::NetworkError,
+                    format!(
+                        "Restic command timed out after {} seconds",
+                        timeout.unwrap_or_default().as_secs()
+                    ),
+                ))
+            }
+        };
+        if let Some(operation) = self.operation.take() {
+            let bytes_transferred = result.as_ref().ok().map(|stdout| stdout.len() as u64);
+            operation.finish(&result, bytes_transferred);
+        }
+        result
+    }
+
+    // Terminate the process gracefully and kill it when it does not exit in time.
+    async fn terminate(&mut self) {
+        if let Some(child_id) = self.child_id {
+            if let Err(err) = terminate_command(child_id) {
+                log::warn!("Failed to terminate process with PID {child_id}: {err}");
+            }
+        }
+        if tokio::time::timeout(TERMINATION_GRACE_PERIOD, self.child.wait())
+            .await
+            .is_err()
+        {
+            if let Err(err) = self.child.kill().await {
+                log::error!("Failed to kill process: {err}");
+            }
+        }
+    }
+}
+
+impl Drop for AsyncProcess {
+    fn drop(&mut self) {
+        // NB: the child itself gets killed via `kill_on_drop` when it's still running
+        if let (Some(command_group), Some(child_id)) = (self.command_group, self.child_id) {
+            if let Err(err) = remove_command_from_group(command_group, child_id) {
+                log::error!("Failed to remove process child: {err}");
+            }
+        }
+    }
+}
+
+// -------------------------------------------------------------------------------------------------
+
+// Read the given optional stream until it's closed.
+async fn read_to_end<R: AsyncRead + Unpin>(reader: Option<R>) -> io::Result<Vec<u8>> {
+    let mut output = vec![];
+    if let Some(mut reader) = reader {
+        reader.read_to_end(&mut output).await?;
+    }
+    Ok(output)
+}
+
+// -------------------------------------------------------------------------------------------------
+
+#[cfg(all(test, unix))]
+mod tests {
+    use std::{process::Stdio, time::Instant};
+
+    use super::*;
+    use crate::restic::{
+        command::{password::PasswordStrategy, scheduler::start_job_async},
+        CommandGroup, Location,
+    };
+
+    // Spawn the given shell script as async process in its own test repository.
+    async fn spawn(script: &str, cancellation_token: &CancellationToken) -> AsyncProcess {
+        let location = Location {
+            path: format!("/runner-test-{}", crate::restic::new_secret_id()),
+            ..Location::default()
+        };
+        let args = ["sh", "-c", script];
+        let job = start_job_async(
+            &location,
+            &args,
+            CommandGroup::Ungrouped,
+            cancellation_token,
+        )
+        .await
+        .unwrap();
+        let child = tokio::process::Command::new("sh")
+            .args(["-c", script])
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()
+            .unwrap();
+        AsyncProcess::new(
+            child,
+            args.iter().map(OsString::from).collect(),
+            None,
+            cancellation_token.clone(),
+            PasswordHandoff::new(PasswordStrategy::Environment, String::new()).unwrap(),
+            job,
+            Operation::start(&location, &args),
+        )
+    }
+
+    async fn run(script: &str) -> Result<Vec<u8>, Error> {
+        spawn(script, &CancellationToken::new())
+            .await
+            .wait_with_output(None)
+            .await
+    }
+
+    #[tokio::test]
+    async fn map_exit_status_to_errors() {
+        assert_eq!(run("echo output; exit 0").await.unwrap(), b"output\n");
+
+        let error = run("exit 12").await.unwrap_err();
+        assert_eq!(error.kind, ErrorKind::WrongPassword);
+        assert_eq!(error.exit_code, Some(12));
+
+        let error = run("echo 'Fatal: repository does not exist' >&2; exit 1")
+            .await
+            .unwrap_err();
+        assert_eq!(error.kind, ErrorKind::RepositoryNotFound);
+
+        let error = run("echo 'something broke' >&2; exit 3").await.unwrap_err();
+        assert_eq!(error.kind, ErrorKind::Failed);
+        assert_eq!(error.exit_code, Some(3));
+        assert_eq!(error.message, "something broke");
+    }
+
+    #[tokio::test]
+    async fn terminate_cancelled_processes() {
+        let cancellation_token = CancellationToken::new();
+        let process = spawn("exec sleep 30", &cancellation_token).await;
+        let start_time = Instant::now();
+        let canceller = {
+            let cancellation_token = cancellation_token.clone();
+            tokio::spawn(async move {
+                tokio::time::sleep(Duration::from_millis(100)).await;
+                cancellation_token.cancel();
+            })
+        };
+        let error = process.wait_with_output(None).await.unwrap_err();
+        canceller.await.unwrap();
+        assert_eq!(error.kind, ErrorKind::Cancelled);
+        assert!(start_time.elapsed() < TERMINATION_GRACE_PERIOD);
+    }
+
+    #[tokio::test]
+    async fn terminate_timed_out_processes() {
+        let process = spawn("exec sleep 30", &CancellationToken::new()).await;
+        let start_time = Instant::now();
+        let error = process
+            .wait_with_output(Some(Duration::from_millis(100)))
+            .await
+            .unwrap_err();
+        assert_eq!(error.kind, ErrorKind::NetworkError);
+        assert!(start_time.elapsed() < TERMINATION_GRACE_PERIOD);
+    }
+}
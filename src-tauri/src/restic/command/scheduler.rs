@@ -0,0 +1,430 @@
+use std::{
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Condvar, Mutex, MutexGuard, PoisonError,
+    },
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use lazy_static::lazy_static;
+use tokio::sync::Notify;
+use tokio_util::sync::CancellationToken;
+
+use super::{group::command_group_terminations, CommandGroup};
+use crate::restic::{redact_repository, Error, Location};
+
+// -------------------------------------------------------------------------------------------------
+
+/// Max number of restic processes which run at the same time for a single repository.
+/// Parked jobs are not counted.
+pub const MAX_JOBS_PER_REPOSITORY: usize = 3;
+
+/// Restic commands which list repository contents in the UI and thus get scheduled first.
+const INTERACTIVE_COMMANDS: &[&str] = &["cat", "find", "ls", "snapshots"];
+
+/// Restic commands which gather repository statistics in the background.
+const BACKGROUND_COMMANDS: &[&str] = &["stats"];
+
+// -------------------------------------------------------------------------------------------------
+
+/// Scheduling priority of a restic job.
+#[derive(serde::Serialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "camelCase")]
+pub enum JobPriority {
+    /// Listings the user is waiting for, e.g. snapshots or files.
+    Interactive,
+    /// Dumps, previews and restores.
+    Normal,
+    /// Batch work such as thumbnails or stats. Never occupies the last slot of a repository,
+    /// so listings are not blocked by background activity.
+    Background,
+}
+
+impl JobPriority {
+    /// Priority of a restic command with the given args and command group. Commands in
+    /// shared command groups are batch jobs and thus run in the background.
+    pub fn for_command(args: &[&str], command_group: CommandGroup) -> Self {
+        let command = args.first().copied().unwrap_or_default();
+        if INTERACTIVE_COMMANDS.contains(&command) {
+            Self::Interactive
+        } else if BACKGROUND_COMMANDS.contains(&command)
+            || matches!(command_group, CommandGroup::Shared(_))
+        {
+            Self::Background
+        } else {
+            Self::Normal
+        }
+    }
+
+    // Max number of running jobs in a repository, up to which a job with this priority starts.
+    fn max_running_jobs(self) -> usize {
+        match self {
+            Self::Interactive | Self::Normal => MAX_JOBS_PER_REPOSITORY,
+            Self::Background => MAX_JOBS_PER_REPOSITORY - 1,
+        }
+    }
+}
+
+/// Scheduling state of a restic job.
+#[derive(serde::Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum JobStatus {
+    /// Waiting for a free slot in its repository.
+    Queued,
+    Running,
+    /// Paused by its owner, e.g. an idle dump stream, without occupying a slot.
+    Parked,
+}
+
+/// Serializable restic job description, as returned by `list_jobs`.
+#[derive(serde::Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct JobInfo {
+    pub id: u64,
+    /// Repository the job runs in, with redacted credentials.
+    pub repository: String,
+    /// Restic command and args, without any location specific args.
+    pub command: String,
+    pub command_group: Option<String>,
+    pub priority: JobPriority,
+    pub status: JobStatus,
+    /// Unix timestamps in milliseconds.
+    pub queued_at: u64,
+    pub started_at: Option<u64>,
+}
+
+// -------------------------------------------------------------------------------------------------
+
+// Start signal of a queued job, which can be awaited from blocking and async code.
+#[derive(Default)]
+struct JobTicket {
+    started: Mutex<bool>,
+    condvar: Condvar,
+    notify: Notify,
+}
+
+impl JobTicket {
+    fn start(&self) {
+        *lock(&self.started) = true;
+        self.condvar.notify_all();
+        // NB: stores a permit when no one is waiting yet, so the start can't get lost
+        self.notify.notify_one();
+    }
+
+    fn is_started(&self) -> bool {
+        *lock(&self.started)
+    }
+}
+
+struct ScheduledJob {
+    // Unredacted `prefix:path` of the job's repository. Redacted names change when secrets
+    // get registered, so they can't be used to group jobs by repository.
+    repository: String,
+    info: JobInfo,
+    ticket: Arc<JobTicket>,
+}
+
+lazy_static! {
+    /// All queued and running jobs, in scheduling order.
+    static ref JOBS: Mutex<Vec<ScheduledJob>> = Mutex::new(vec![]);
+    static ref NEXT_JOB_ID: AtomicU64 = AtomicU64::new(1);
+}
+
+// -------------------------------------------------------------------------------------------------
+
+/// A queued or running restic job. The job gets removed from the scheduler when dropped,
+/// which starts the next queued jobs of its repository.
+pub struct JobGuard {
+    id: u64,
+    ticket: Arc<JobTicket>,
+}
+
+impl JobGuard {
+    // Block until the scheduler started the job.
+    fn wait(&self) {
+        let mut started = lock(&self.ticket.started);
+        while !*started {
+            started = self
+                .ticket
+                .condvar
+                .wait(started)
+                .unwrap_or_else(PoisonError::into_inner);
+        }
+    }
+
+    // Wait until the scheduler started the job or the given token got cancelled.
+    async fn wait_async(&self, cancellation_token: &CancellationToken) -> Result<(), Error> {
+        while !self.ticket.is_started() {
+            tokio::select! {
+                _ = self.ticket.notify.notified() => {}
+                _ = cancellation_token.cancelled() => return Err(Error::cancelled()),
+            }
+        }
+        Ok(())
+    }
+}
+
+impl JobGuard {
+    /// Release the job's repository slot while its process is idle, so parked jobs can't
+    /// block other jobs of the repository.
+    pub(super) fn park(&self) {
+        let mut jobs = lock(&JOBS);
+        if let Some(job) = jobs.iter_mut().find(|job| job.info.id == self.id) {
+            job.info.status = JobStatus::Parked;
+        }
+        dispatch(&mut jobs);
+    }
+
+    /// Queue a parked job again and block until the scheduler restarted it.
+    pub(super) fn resume(&self) {
+        {
+            let mut jobs = lock(&JOBS);
+            if let Some(job) = jobs
+                .iter_mut()
+                .find(|job| job.info.id == self.id && job.info.status == JobStatus::Parked)
+            {
+                job.info.status = JobStatus::Queued;
+                *lock(&self.ticket.started) = false;
+            }
+            dispatch(&mut jobs);
+        }
+        self.wait();
+    }
+}
+
+impl Drop for JobGuard {
+    fn drop(&mut self) {
+        let mut jobs = lock(&JOBS);
+        jobs.retain(|job| job.info.id != self.id);
+        dispatch(&mut jobs);
+    }
+}
+
+// -------------------------------------------------------------------------------------------------
+
+/// All queued and running restic jobs.
+pub fn list_jobs() -> Vec<JobInfo> {
+    lock(&JOBS).iter().map(|job| job.info.clone()).collect()
+}
+
+/// Queue a job for the given restic command and block until the scheduler starts it.
+/// Commands which got superseded by another command of their group while they were queued
+/// are returned as cancelled.
+pub(super) fn start_job(
+    location: &Location,
+    args: &[&str],
+    command_group: CommandGroup,
+) -> Result<JobGuard, Error> {
+    let terminations = command_group.name().map(command_group_terminations);
+    let job = queue_job(location, args, command_group);
+    job.wait();
+    if command_group.name().map(command_group_terminations) != terminations {
+        return Err(Error::cancelled());
+    }
+    Ok(job)
+}
+
+/// Queue a job for the given restic command and wait until the scheduler starts it, like
+/// `start_job`. Queued jobs get removed when the given token gets cancelled.
+pub(super) async fn start_job_async(
+    location: &Location,
+    args: &[&str],
+    command_group: CommandGroup,
+    cancellation_token: &CancellationToken,
+) -> Result<JobGuard, Error> {
+    let terminations = command_group.name().map(command_group_terminations);
+    let job = queue_job(location, args, command_group);
+    job.wait_async(cancellation_token).await?;
+    if command_group.name().map(command_group_terminations) != terminations {
+        return Err(Error::cancelled());
+    }
+    Ok(job)
+}
+
+// Add a new job to the queue and start it right away when its repository has a free slot.
+fn queue_job(location: &Location, args: &[&str], command_group: CommandGroup) -> JobGuard {
+    let id = NEXT_JOB_ID.fetch_add(1, Ordering::Relaxed);
+    let ticket = Arc::new(JobTicket::default());
+    let info = JobInfo {
+        id,
+        repository: redact_repository(location),
+        command: args.join(" "),
+        command_group: command_group.name().map(str::to_string),
+        priority: JobPriority::for_command(args, command_group),
+        status: JobStatus::Queued,
+        queued_at: now(),
+        started_at: None,
+    };
+    let mut jobs = lock(&JOBS);
+    jobs.push(ScheduledJob {
+        repository: format!("{}:{}", location.prefix, location.path),
+        info,
+        ticket: Arc::clone(&ticket),
+    });
+    dispatch(&mut jobs);
+    JobGuard { id, ticket }
+}
+
+// Start queued jobs by priority and age, as long as their repositories have free slots.
+fn dispatch(jobs: &mut [ScheduledJob]) {
+    let mut queued = jobs
+        .iter()
+        .enumerate()
+        .filter(|(_, job)| job.info.status == JobStatus::Queued)
+        .map(|(index, job)| (job.info.priority, job.info.id, index))
+        .collect::<Vec<_>>();
+    queued.sort();
+    for (priority, _, index) in queued {
+        let repository = &jobs[index].repository;
+        let running_jobs = jobs
+            .iter()
+            .filter(|job| job.info.status == JobStatus::Running && job.repository == *repository)
+            .count();
+        if running_jobs < priority.max_running_jobs() {
+            let job = &mut jobs[index];
+            job.info.status = JobStatus::Running;
+            job.info.started_at = Some(now());
+            job.ticket.start();
+        }
+    }
+}
+
+// Lock a scheduler mutex. Jobs never panic while holding a lock, so poisoning is ignored.
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+// Current unix timestamp in milliseconds.
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_millis() as u64)
+        .unwrap_or_default()
+}
+
+// -------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn job(id: u64, repository: &str, priority: JobPriority, status: JobStatus) -> ScheduledJob {
+        ScheduledJob {
+            repository: repository.to_string(),
+            info: JobInfo {
+                id,
+                repository: repository.to_string(),
+                command: String::new(),
+                command_group: None,
+                priority,
+                status,
+                queued_at: 0,
+                started_at: None,
+            },
+            ticket: Arc::new(JobTicket::default()),
+        }
+    }
+
+    fn statuses(jobs: &[ScheduledJob]) -> Vec<JobStatus> {
+        jobs.iter().map(|job| job.info.status).collect()
+    }
+
+    fn location(path: &str) -> Location {
+        Location {
+            path: path.to_string(),
+            ..Location::default()
+        }
+    }
+
+    #[test]
+    fn dispatch_limits_running_jobs_per_repository() {
+        use JobPriority::*;
+        use JobStatus::*;
+        let mut jobs = vec![
+            job(1, "a", Normal, Queued),
+            job(2, "a", Normal, Queued),
+            job(3, "b", Normal, Queued),
+            job(4, "a", Normal, Queued),
+            job(5, "a", Normal, Queued),
+        ];
+        dispatch(&mut jobs);
+        assert_eq!(
+            statuses(&jobs),
+            [Running, Running, Running, Running, Queued]
+        );
+        assert!(jobs[0].ticket.is_started() && !jobs[4].ticket.is_started());
+    }
+
+    #[test]
+    fn dispatch_by_priority_and_keep_a_slot_free_for_listings() {
+        use JobPriority::*;
+        use JobStatus::*;
+        let mut jobs = vec![
+            job(1, "a", Background, Queued),
+            job(2, "a", Background, Queued),
+            job(3, "a", Background, Queued),
+            job(4, "a", Interactive, Queued),
+        ];
+        dispatch(&mut jobs);
+        // the interactive job starts first and background jobs never take the last slot
+        assert_eq!(statuses(&jobs), [Running, Queued, Queued, Running]);
+        jobs.remove(3);
+        dispatch(&mut jobs);
+        assert_eq!(statuses(&jobs), [Running, Running, Queued]);
+    }
+
+    #[test]
+    fn parked_jobs_release_their_slot() {
+        use JobPriority::*;
+        use JobStatus::*;
+        let mut jobs = vec![
+            job(1, "a", Normal, Parked),
+            job(2, "a", Normal, Parked),
+            job(3, "a", Normal, Parked),
+            job(4, "a", Normal, Queued),
+        ];
+        dispatch(&mut jobs);
+        assert_eq!(statuses(&jobs), [Parked, Parked, Parked, Running]);
+    }
+
+    #[test]
+    fn resumed_jobs_wait_for_a_free_slot() {
+        let location = location("/scheduler-test-resume");
+        let parked = start_job(&location, &["dump"], CommandGroup::Ungrouped).unwrap();
+        parked.park();
+        let running = (0..MAX_JOBS_PER_REPOSITORY)
+            .map(|_| start_job(&location, &["dump"], CommandGroup::Ungrouped).unwrap())
+            .collect::<Vec<_>>();
+        let resumed = std::thread::spawn(move || {
+            parked.resume();
+            parked
+        });
+        std::thread::sleep(std::time::Duration::from_millis(50));
+        assert!(!resumed.is_finished());
+        drop(running);
+        let parked = resumed.join().unwrap();
+        assert!(list_jobs()
+            .iter()
+            .any(|job| job.id == parked.id && job.status == JobStatus::Running));
+    }
+
+    #[test]
+    fn registered_secrets_dont_split_repository_slots() {
+        let secret = format!("scheduler-test-secret-{}", crate::restic::new_secret_id());
+        let location = location(&format!("/scheduler-test-{secret}"));
+        let running = (0..MAX_JOBS_PER_REPOSITORY - 1)
+            .map(|_| start_job(&location, &["dump"], CommandGroup::Ungrouped).unwrap())
+            .collect::<Vec<_>>();
+        crate::restic::register_secret(&secret);
+        let last = start_job(&location, &["dump"], CommandGroup::Ungrouped).unwrap();
+        let queued = queue_job(&location, &["dump"], CommandGroup::Ungrouped);
+        // the job info is redacted, but all jobs still share the repository's slots
+        let jobs = list_jobs();
+        let info = jobs.iter().find(|job| job.id == queued.id).unwrap();
+        assert!(!info.repository.contains(&secret));
+        assert_eq!(info.status, JobStatus::Queued);
+        drop(last);
+        assert!(queued.ticket.is_started());
+        drop(running);
+    }
+}
@@ -0,0 +1,139 @@
+use std::{
+    ffi::OsString,
+    io::{self, Read},
+    process::{Child, ChildStdout, Output},
+    thread::JoinHandle,
+};
+
+use super::{
+    group::remove_command_from_group, history::Operation, password::PasswordHandoff,
+    scheduler::JobGuard, Program,
+};
+
+// -------------------------------------------------------------------------------------------------
+
+/// Reader for the stdout of a running restic command, as created by `Program::run_streamed`.
+///
+/// When the stream got read till its end, the command's exit status is checked and failures
+/// are returned as read error. When the stream gets dropped before it got fully read, the
+/// running restic process is killed.
+pub struct OutputStream {
+    child: Child,
+    stdout: ChildStdout,
+    stderr_reader: Option<JoinHandle<Vec<u8>>>,
+    args: Vec<OsString>,
+    command_group: Option<&'static str>,
+    finished: bool,
+    bytes_read: u64,
+    // recorded when the stream got fully read, or as cancelled when it gets dropped before
+    operation: Option<Operation>,
+    // keeps temporary password files alive until the process finished
+    _password: PasswordHandoff,
+    // keeps the job's repository slot occupied until the process finished or got parked
+    job: JobGuard,
+}
+
+impl OutputStream {
+    pub(super) fn new(
+        mut child: Child,
+        args: Vec<OsString>,
+        command_group: Option<&'static str>,
+        password: PasswordHandoff,
+        job: JobGuard,
+        operation: Operation,
+    ) -> io::Result<Self> {
+        let stdout = child
+            .stdout
+            .take()
+            .ok_or_else(|| io::Error::other("Command has no piped stdout"))?;
+        // drain stderr in a separate thread, so the child never blocks on a full stderr pipe
+        let stderr_reader = child.stderr.take().map(|mut stderr| {
+            std::thread::spawn(move || {
+                let mut buffer = Vec::new();
+                let _ = stderr.read_to_end(&mut buffer);
+                buffer
+            })
+        });
+        Ok(Self {
+            child,
+            stdout,
+            stderr_reader,
+            args,
+            command_group,
+            finished: false,
+            bytes_read: 0,
+            operation: Some(operation),
+            _password: password,
+            job,
+        })
+    }
+
+    /// Release the stream's repository slot in the scheduler while it's not read, e.g. when
+    /// keeping the process alive for following reads. Must be resumed before reading again.
+    pub fn park(&self) {
+        self.job.park();
+    }
+
+    /// Block until the scheduler gave a parked stream a repository slot again.
+    pub fn resume(&self) {
+        self.job.resume();
+    }
+
+    // Wait for the child to exit and convert a failed exit status into an io error.
+    fn finish(&mut self) -> io::Result<()> {
+        self.finished = true;
+        let status = self.child.wait()?;
+        let stderr = self
+            .stderr_reader
+            .take()
+            .and_then(|reader| reader.join().ok())
+            .unwrap_or_default();
+        let result = if status.success() {
+            Ok(())
+        } else {
+            let output = Output {
+                status,
+                stdout: vec![],
+                stderr,
+            };
+            Err(Program::handle_run_error(&self.args, &output))
+        };
+        if let Some(operation) = self.operation.take() {
+            operation.finish(&result, Some(self.bytes_read));
+        }
+        // NB: the restic error gets unwrapped again in `From<io::Error> for restic::Error`
+        result.map_err(io::Error::other)
+    }
+}
+
+impl Read for OutputStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.finished {
+            return Ok(0);
+        }
+        let bytes_read = self.stdout.read(buf)?;
+        self.bytes_read += bytes_read as u64;
+        if bytes_read == 0 && !buf.is_empty() {
+            self.finish()?;
+        }
+        Ok(bytes_read)
+    }
+}
+
+impl Drop for OutputStream {
+    fn drop(&mut self) {
+        if !self.finished {
+            log::debug!(
+                "Killing unfinished streamed process with PID '{}'",
+                self.child.id()
+            );
+            let _ = self.child.kill();
+            let _ = self.child.wait();
+        }
+        if let Some(command_group) = self.command_group {
+            if let Err(err) = remove_command_from_group(command_group, self.child.id()) {
+                log::error!("Failed to remove process child: {err}");
+            }
+        }
+    }
+}
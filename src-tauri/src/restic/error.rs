@@ -0,0 +1,266 @@
+use std::{fmt, io, process::Output};
+
+use crate::restic::redact;
+
+// -------------------------------------------------------------------------------------------------
+
+/// Max number of chars from a command's stderr output that are included in errors.
+const MAX_STDERR_EXCERPT_LENGTH: usize = 4096;
+
+// -------------------------------------------------------------------------------------------------
+
+/// A serializable error kind, which allows the frontend to react to specific failures.
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum ErrorKind {
+    /// Unspecified application or restic failure.
+    Failed,
+    /// The restic executable is missing or is not a valid restic program.
+    ResticNotFound,
+    /// The repository location is missing or is not valid.
+    InvalidLocation,
+    /// The repository does not exist. Restic exit code 10.
+    RepositoryNotFound,
+    /// The repository could not be locked. Restic exit code 11.
+    RepositoryLocked,
+    /// The repository password is wrong. Restic exit code 12.
+    WrongPassword,
+    /// The password command failed, timed out or printed no password.
+    PasswordCommandFailed,
+    /// The repository backend could not be reached or timed out.
+    NetworkError,
+    /// The command got terminated by the app or got interrupted.
+    Cancelled,
+    /// A snapshot, file or secret could not be found.
+    NotFound,
+    /// The secret store could not be accessed.
+    SecretStore,
+    /// Local file system errors.
+    Io,
+}
+
+// -------------------------------------------------------------------------------------------------
+
+/// A serializable restic or application error, as returned by the restic program wrapper
+/// and all tauri commands.
+#[derive(serde::Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Error {
+    pub kind: ErrorKind,
+    /// Human readable error description.
+    pub message: String,
+    /// Exit code of the restic process, if the error originates from a restic command.
+    pub exit_code: Option<i32>,
+    /// Tail of the restic process' stderr output, if the error originates from a restic command.
+    pub stderr: Option<String>,
+}
+
+impl Error {
+    /// Create a new error of the given kind without process info.
+    /// Secrets in the message get redacted.
+    pub fn new<S: Into<String>>(kind: ErrorKind, message: S) -> Self {
+        Self {
+            kind,
+            message: redact(&message.into()),
+            exit_code: None,
+            stderr: None,
+        }
+    }
+
+    /// Create a new error for commands which got terminated via their command group.
+    pub fn cancelled() -> Self {
+        Self::new(ErrorKind::Cancelled, "Command got aborted")
+    }
+
+    /// Create a new error from the output of a failed restic command, classifying the
+    /// error by restic's documented exit codes and, for older restic versions, by stderr.
+    /// Secrets in the stderr output get redacted.
+    pub fn from_output(output: &Output) -> Self {
+        let stderr = redact(String::from_utf8_lossy(&output.stderr).trim());
+        let exit_code = output.status.code();
+        let kind = Self::classify(exit_code, &stderr);
+        let message = match kind {
+            ErrorKind::RepositoryNotFound => "Repository does not exist".to_string(),
+            ErrorKind::RepositoryLocked => "Failed to lock the repository".to_string(),
+            ErrorKind::WrongPassword => "Wrong password or no key found".to_string(),
+            ErrorKind::Cancelled => "Command got interrupted".to_string(),
+            _ if !stderr.is_empty() => stderr.clone(),
+            _ => format!("Restic command failed with status {}", output.status),
+        };
+        Self {
+            kind,
+            message,
+            exit_code,
+            stderr: Some(Self::excerpt(&stderr)),
+        }
+    }
+
+    fn classify(exit_code: Option<i32>, stderr: &str) -> ErrorKind {
+        // see https://restic.readthedocs.io/en/stable/075_scripting.html#exit-codes
+        match exit_code {
+            Some(10) => return ErrorKind::RepositoryNotFound,
+            Some(11) => return ErrorKind::RepositoryLocked,
+            Some(12) => return ErrorKind::WrongPassword,
+            Some(130) => return ErrorKind::Cancelled,
+            _ => {}
+        }
+        // restic < 0.17 only uses exit code 1 for all fatal errors
+        let stderr = stderr.to_lowercase();
+        let contains_any = |patterns: &[&str]| patterns.iter().any(|p| stderr.contains(p));
+        if contains_any(&["password command"]) {
+            ErrorKind::PasswordCommandFailed
+        } else if contains_any(&["wrong password", "no key found", "empty password"]) {
+            ErrorKind::WrongPassword
+        } else if contains_any(&[
+            "repository does not exist",
+            "unable to open config file",
+            "is there a repository at the following location",
+        ]) {
+            ErrorKind::RepositoryNotFound
+        } else if contains_any(&["unable to create lock", "repository is already locked"]) {
+            ErrorKind::RepositoryLocked
+        } else if contains_any(&[
+            "connection refused",
+            "connection reset",
+            "no such host",
+            "network is unreachable",
+            "i/o timeout",
+            "timed out",
+            "tls handshake",
+        ]) {
+            ErrorKind::NetworkError
+        } else {
+            ErrorKind::Failed
+        }
+    }
+
+    // The last `MAX_STDERR_EXCERPT_LENGTH` chars of the given stderr output.
+    fn excerpt(stderr: &str) -> String {
+        let char_count = stderr.chars().count();
+        if char_count <= MAX_STDERR_EXCERPT_LENGTH {
+            stderr.to_string()
+        } else {
+            let skipped: String = stderr
+                .chars()
+                .skip(char_count - MAX_STDERR_EXCERPT_LENGTH)
+                .collect();
+            format!("...{skipped}")
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<String> for Error {
+    fn from(message: String) -> Self {
+        Self::new(ErrorKind::Failed, message)
+    }
+}
+
+impl From<&str> for Error {
+    fn from(message: &str) -> Self {
+        Self::new(ErrorKind::Failed, message)
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Self {
+        // unwrap restic errors which got passed through io readers, e.g. `OutputStream`
+        if let Some(err) = err.get_ref().and_then(|err| err.downcast_ref::<Error>()) {
+            return err.clone();
+        }
+        Self::new(ErrorKind::Io, err.to_string())
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(err: serde_json::Error) -> Self {
+        Self::new(
+            ErrorKind::Failed,
+            format!("Failed to parse restic output: {err}"),
+        )
+    }
+}
+
+// -------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn classify_by_exit_code() {
+        assert_eq!(Error::classify(Some(10), ""), ErrorKind::RepositoryNotFound);
+        assert_eq!(Error::classify(Some(11), ""), ErrorKind::RepositoryLocked);
+        assert_eq!(Error::classify(Some(12), ""), ErrorKind::WrongPassword);
+        assert_eq!(Error::classify(Some(130), ""), ErrorKind::Cancelled);
+        assert_eq!(Error::classify(Some(1), ""), ErrorKind::Failed);
+        assert_eq!(Error::classify(None, ""), ErrorKind::Failed);
+    }
+
+    #[test]
+    fn classify_by_stderr_of_old_restic_versions() {
+        let classify = |stderr| Error::classify(Some(1), stderr);
+        assert_eq!(
+            classify("Fatal: wrong password or no key found"),
+            ErrorKind::WrongPassword
+        );
+        assert_eq!(
+            classify("Fatal: unable to open config file: Stat: stat /repo/config: no such file"),
+            ErrorKind::RepositoryNotFound
+        );
+        assert_eq!(
+            classify("unable to create lock in backend: repository is already locked by PID 42"),
+            ErrorKind::RepositoryLocked
+        );
+        assert_eq!(
+            classify("Get \"https://host/config\": dial tcp: connection refused"),
+            ErrorKind::NetworkError
+        );
+        assert_eq!(
+            classify("Fatal: password command failed: exit status 1"),
+            ErrorKind::PasswordCommandFailed
+        );
+        assert_eq!(classify("Fatal: invalid id \"xyz\""), ErrorKind::Failed);
+        // exit codes take precedence over stderr
+        assert_eq!(
+            Error::classify(Some(11), "wrong password"),
+            ErrorKind::RepositoryLocked
+        );
+    }
+
+    #[test]
+    fn excerpt_keeps_the_tail_of_long_output() {
+        assert_eq!(Error::excerpt("short"), "short");
+        let long = "a".repeat(MAX_STDERR_EXCERPT_LENGTH) + "tail";
+        let excerpt = Error::excerpt(&long);
+        assert!(excerpt.starts_with("...") && excerpt.ends_with("tail"));
+        assert_eq!(excerpt.chars().count(), MAX_STDERR_EXCERPT_LENGTH + 3);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn from_output_sets_kind_message_and_exit_code() {
+        use std::os::unix::process::ExitStatusExt;
+        let output = Output {
+            // NB: raw wait status: the exit code is stored in the second byte
+            status: std::process::ExitStatus::from_raw(12 << 8),
+            stdout: vec![],
+            stderr: b"Fatal: wrong password or no key found\n".to_vec(),
+        };
+        let err = Error::from_output(&output);
+        assert_eq!(err.kind, ErrorKind::WrongPassword);
+        assert_eq!(err.exit_code, Some(12));
+        assert_eq!(err.message, "Wrong password or no key found");
+        assert_eq!(
+            err.stderr.as_deref(),
+            Some("Fatal: wrong password or no key found")
+        );
+    }
+}
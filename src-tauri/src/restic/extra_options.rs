@@ -0,0 +1,175 @@
+use crate::restic::{Error, ErrorKind};
+
+// -------------------------------------------------------------------------------------------------
+
+/// Restic global flags which may be passed as extra location args and whether they expect a
+/// value. Flags which are set by the app itself or which change restic's output format, such
+/// as `--repo`, `--password-file` or `--json`, are not allowed. So is `--retry-lock`, which
+/// conflicts with the `--no-lock` flag the app passes to all commands.
+const ALLOWED_GLOBAL_FLAGS: &[(&str, bool)] = &[
+    ("--cacert", true),
+    ("--cache-dir", true),
+    ("--cleanup-cache", false),
+    ("--compression", true),
+    ("--http-user-agent", true),
+    ("--key-hint", true),
+    ("--limit-download", true),
+    ("--limit-upload", true),
+    ("--no-cache", false),
+    ("--no-extra-verify", false),
+    ("--option", true),
+    ("--pack-size", true),
+    ("--stuck-request-timeout", true),
+    ("--tls-client-cert", true),
+];
+
+/// Short restic global flags which may be passed as extra location args.
+const ALLOWED_SHORT_FLAGS: &[(&str, &str)] = &[("-o", "--option")];
+
+/// Prefixes of env variables which may be set as extra location envs.
+const ALLOWED_ENV_PREFIXES: &[&str] = &[
+    "RESTIC_", "AWS_", "AZURE_", "B2_", "GOOGLE_", "OS_", "ST_", "RCLONE_",
+];
+
+/// Other env variables which may be set as extra location envs.
+const ALLOWED_ENVS: &[&str] = &[
+    "HTTP_PROXY",
+    "HTTPS_PROXY",
+    "NO_PROXY",
+    "SSL_CERT_FILE",
+    "SSL_CERT_DIR",
+    "TMPDIR",
+    "XDG_CACHE_HOME",
+];
+
+/// Env variables which are set by the app itself and thus can't be overridden.
+const RESERVED_ENVS: &[&str] = &[
+    "RESTIC_REPOSITORY",
+    "RESTIC_REPOSITORY_FILE",
+    "RESTIC_PASSWORD",
+    "RESTIC_PASSWORD_FILE",
+    "RESTIC_PASSWORD_COMMAND",
+];
+
+// -------------------------------------------------------------------------------------------------
+
+/// Parse a shell-like string of extra restic global flags, e.g. `--cacert "/my certs/ca.pem"`.
+/// Returns the normalized `--flag[=value]` args or an error for unknown or malformed flags.
+pub fn parse_extra_args(extra_args: &str) -> Result<Vec<String>, Error> {
+    let error = |message: String| Error::new(ErrorKind::InvalidLocation, message);
+    let tokens = shlex::split(extra_args).ok_or_else(|| {
+        error(format!(
+            "Failed to parse extra arguments '{extra_args}': unbalanced quotes"
+        ))
+    })?;
+    let mut args = vec![];
+    let mut tokens = tokens.into_iter();
+    while let Some(token) = tokens.next() {
+        let (flag, inline_value) = match token.split_once('=') {
+            Some((flag, value)) => (flag.to_string(), Some(value.to_string())),
+            None => (token.clone(), None),
+        };
+        let flag = ALLOWED_SHORT_FLAGS
+            .iter()
+            .find(|(short, _)| *short == flag)
+            .map_or(flag, |(_, long)| long.to_string());
+        let Some(takes_value) = extra_arg_takes_value(&flag) else {
+            return Err(error(format!("Unsupported extra argument: '{token}'")));
+        };
+        match (takes_value, inline_value) {
+            (true, Some(value)) => args.push(format!("{flag}={value}")),
+            (true, None) => {
+                let value = tokens
+                    .next()
+                    .ok_or_else(|| error(format!("Missing value for argument '{flag}'")))?;
+                args.push(format!("{flag}={value}"));
+            }
+            (false, None) => args.push(flag),
+            (false, Some(_)) => {
+                return Err(error(format!("Argument '{flag}' does not expect a value")));
+            }
+        }
+    }
+    Ok(args)
+}
+
+/// Returns whether the given long restic global flag, e.g. `--cacert`, may be passed as extra
+/// location arg and if so, whether it expects a value.
+pub fn extra_arg_takes_value(flag: &str) -> Option<bool> {
+    ALLOWED_GLOBAL_FLAGS
+        .iter()
+        .find(|(f, _)| *f == flag)
+        .map(|(_, takes_value)| *takes_value)
+}
+
+/// Verify that the given env variable name may be set as extra location env.
+pub fn verify_extra_env(name: &str) -> Result<(), Error> {
+    let is_valid_name = !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_uppercase() || c.is_ascii_digit() || c == '_');
+    let is_allowed = !RESERVED_ENVS.contains(&name)
+        && (ALLOWED_ENVS.contains(&name)
+            || ALLOWED_ENV_PREFIXES
+                .iter()
+                .any(|prefix| name.starts_with(prefix)));
+    if is_valid_name && is_allowed {
+        Ok(())
+    } else {
+        Err(Error::new(
+            ErrorKind::InvalidLocation,
+            format!("Unsupported extra environment variable: '{name}'"),
+        ))
+    }
+}
+
+// -------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_allowed_extra_args() {
+        assert_eq!(
+            parse_extra_args(r#"--cacert "/my certs/ca.pem" --no-cache -o s3.region=eu"#).unwrap(),
+            [
+                "--cacert=/my certs/ca.pem",
+                "--no-cache",
+                "--option=s3.region=eu"
+            ]
+        );
+        assert_eq!(
+            parse_extra_args("--limit-download=1024").unwrap(),
+            ["--limit-download=1024"]
+        );
+        assert!(parse_extra_args("").unwrap().is_empty());
+    }
+
+    #[test]
+    fn reject_unsupported_or_malformed_extra_args() {
+        for extra_args in [
+            "--repo /other",
+            "--password-file /tmp/pass",
+            "--json",
+            "--retry-lock 1m",
+            "--no-cache=true",
+            "--cacert",
+            "--cacert \"/unbalanced",
+            "rm -rf /",
+        ] {
+            assert!(parse_extra_args(extra_args).is_err(), "{extra_args}");
+        }
+    }
+
+    #[test]
+    fn verify_extra_env_names() {
+        assert!(verify_extra_env("AWS_PROFILE").is_ok());
+        assert!(verify_extra_env("HTTPS_PROXY").is_ok());
+        assert!(verify_extra_env("RESTIC_CACHE_DIR").is_ok());
+        assert!(verify_extra_env("RESTIC_PASSWORD").is_err());
+        assert!(verify_extra_env("LD_PRELOAD").is_err());
+        assert!(verify_extra_env("aws_profile").is_err());
+        assert!(verify_extra_env("").is_err());
+    }
+}
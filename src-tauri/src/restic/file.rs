@@ -1,3 +1,5 @@
+use crate::restic::Error;
+
 // -------------------------------------------------------------------------------------------------
 
 /// A serializable restic file, as dumped by the restic binary via `restic ls --json`
@@ -23,3 +25,18 @@ pub struct File {
     pub ctime: String,
 }
 
+impl File {
+    /// Parse the output of `restic ls --json`: the first line describes the snapshot,
+    /// all following lines are files.
+    pub fn parse_ls_output(output: &str) -> Result<Vec<Self>, Error> {
+        let mut files = vec![];
+        for line in output.split('\n').skip(1) {
+            if line.is_empty() || !line.starts_with('{') {
+                // Skip first/blank/malformed lines
+                continue;
+            }
+            files.push(serde_json::from_str::<Self>(line)?);
+        }
+        Ok(files)
+    }
+}
@@ -1,16 +1,42 @@
 use std::{collections::HashMap, env, fs};
 
-use shlex::Shlex;
-
-use crate::restic::{new_command, supported_location_types};
+use crate::restic::{supported_location_types, CommandPolicy, PasswordStrategy};
 
 // -------------------------------------------------------------------------------------------------
 
 /// A serializable restic location env variable.
 #[derive(serde::Serialize, serde::Deserialize, Debug, Default, Clone)]
+#[serde(rename_all = "camelCase")]
 pub struct EnvValue {
     pub name: String,
     pub value: String,
+    /// Id of a secret in the secret store: when set, it's used instead of `value`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub secret_id: Option<String>,
+}
+
+// -------------------------------------------------------------------------------------------------
+
+/// A serializable restic extended option, as passed via `-o name=value`,
+/// e.g. `s3.storage-class`, `sftp.command` or `azure.endpoint-suffix`.
+#[derive(serde::Serialize, serde::Deserialize, Debug, Default, Clone)]
+pub struct ExtendedOption {
+    pub name: String,
+    pub value: String,
+}
+
+impl ExtendedOption {
+    /// Returns true if the option name is a valid `<backend>.<option>` name.
+    pub fn is_valid(&self) -> bool {
+        self.name.split_once('.').is_some_and(|(backend, option)| {
+            !backend.is_empty()
+                && !option.is_empty()
+                && self
+                    .name
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | '_'))
+        })
+    }
 }
 
 // -------------------------------------------------------------------------------------------------
@@ -24,7 +50,32 @@ pub struct Location {
     pub credentials: Vec<EnvValue>,
     pub allow_empty_password: bool,
     pub password: String,
+    /// Id of a secret in the secret store: when set, it's used instead of `password`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub password_secret_id: Option<String>,
+    /// How the password gets passed to restic.
+    #[serde(default)]
+    pub password_strategy: PasswordStrategy,
+    /// Command which prints the password: when set, it's passed to restic as
+    /// `RESTIC_PASSWORD_COMMAND` instead of a password, so it runs for each restic command.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub password_command: Option<String>,
     pub insecure_tls: bool,
+    /// Extended backend options.
+    #[serde(default)]
+    pub options: Vec<ExtendedOption>,
+    /// Extra restic global flags as shell-like string, see `parse_extra_args`.
+    #[serde(default)]
+    pub extra_args: String,
+    /// Extra env variables, see `verify_extra_env`.
+    #[serde(default)]
+    pub extra_envs: Vec<EnvValue>,
+    /// Timeout and retry policy for restic commands.
+    #[serde(default)]
+    pub command_policy: CommandPolicy,
+    /// List snapshots and files of local repositories in process, without running restic.
+    #[serde(default)]
+    pub native_reader: bool,
 }
 
 impl Location {
@@ -49,24 +100,16 @@ impl Location {
                 .cloned()
                 .unwrap_or(String::new())
         };
-        // get password from file, command or directly
+        // get password from file or directly, or keep the password command
+        let password_command = args
+            .get("password-command")
+            .filter(|_| !args.contains_key("password-file"))
+            .cloned();
         let password = if let Some(password_file) = args.get("password-file") {
             fs::read_to_string(password_file)
                 .unwrap_or(String::new())
                 .trim()
                 .to_string()
-        } else if let Some(password_command) = args.get("password-command") {
-            let mut program_and_args = Shlex::new(password_command);
-            let program = program_and_args.next().unwrap_or_default();
-            let args = program_and_args.collect::<Vec<_>>();
-            if let Ok(output) = new_command(&program.into()).args(args).output() {
-                std::str::from_utf8(&output.stdout)
-                    .unwrap_or("")
-                    .trim_end()
-                    .to_string()
-            } else {
-                String::new()
-            }
         } else {
             args.get("password")
                 .or(args.get("pass"))
@@ -74,6 +117,10 @@ impl Location {
                 .unwrap_or(String::new())
         };
         let allow_empty_password = false;
+        let password_strategy = args
+            .get("password-strategy")
+            .and_then(|strategy| strategy.parse().inspect_err(|err| log::warn!("{err}")).ok())
+            .unwrap_or_default();
         // get insecure_tls option, when set
         let insecure_tls = args.contains_key("insecure-tls");
         // build basic location
@@ -83,7 +130,15 @@ impl Location {
             prefix: String::new(),
             allow_empty_password,
             password,
+            password_secret_id: None,
+            password_strategy,
+            password_command,
             insecure_tls,
+            options: vec![],
+            extra_args: String::new(),
+            extra_envs: vec![],
+            command_policy: CommandPolicy::default(),
+            native_reader: false,
         };
         // set prefix from path, when there's a path set
         if !location.path.is_empty() {
@@ -121,6 +176,7 @@ impl Location {
                     self.credentials.push(EnvValue {
                         name: credential.to_string(),
                         value: env::var(credential).unwrap_or_default(),
+                        secret_id: None,
                     });
                 }
                 break;
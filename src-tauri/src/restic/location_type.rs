@@ -9,9 +9,11 @@ pub enum LocationType {
     Rest,
     RClone,
     AmazonS3,
+    S3Compatible,
     MSAzure,
     Backblaze,
     GoogleCloudStorage,
+    Swift,
 }
 
 // -------------------------------------------------------------------------------------------------
@@ -0,0 +1,607 @@
+use std::{
+    collections::HashMap,
+    fs,
+    io::{Read, Seek, SeekFrom},
+    path::{Path, PathBuf},
+};
+
+use aes::cipher::{BlockEncrypt, KeyInit, KeyIvInit, StreamCipher};
+use base64::Engine;
+use poly1305::Poly1305;
+
+use crate::restic::{Error, ErrorKind, File, Location, Snapshot};
+
+// -------------------------------------------------------------------------------------------------
+
+/// Length of the IV which prefixes all encrypted repository data.
+const IV_LENGTH: usize = 16;
+
+/// Length of the MAC which suffixes all encrypted repository data.
+const MAC_LENGTH: usize = 16;
+
+/// Repository format version which added compression.
+const COMPRESSED_REPOSITORY_VERSION: u32 = 2;
+
+/// Plaintext prefix of compressed unpacked files in compressed repositories.
+const COMPRESSED_FILE_VERSION: u8 = 2;
+
+type Aes256Ctr = ctr::Ctr128BE<aes::Aes256>;
+
+// -------------------------------------------------------------------------------------------------
+
+/// Encrypted key file, as stored in a repository's `keys` directory.
+#[derive(serde::Deserialize)]
+struct KeyFile {
+    kdf: String,
+    #[serde(rename = "N")]
+    n: u64,
+    r: u32,
+    p: u32,
+    salt: String,
+    data: String,
+}
+
+/// Decrypted master key data of a key file.
+#[derive(serde::Deserialize)]
+struct MasterKeyData {
+    mac: MacKeyData,
+    encrypt: String,
+}
+
+#[derive(serde::Deserialize)]
+struct MacKeyData {
+    k: String,
+    r: String,
+}
+
+/// Decrypted repository config.
+#[derive(serde::Deserialize)]
+struct Config {
+    version: u32,
+}
+
+/// Decrypted snapshot file, as stored in a repository's `snapshots` directory.
+#[derive(serde::Deserialize)]
+struct SnapshotFile {
+    time: String,
+    tree: String,
+    #[serde(default)]
+    paths: Vec<String>,
+    #[serde(default)]
+    tags: Option<Vec<String>>,
+    #[serde(default)]
+    hostname: String,
+    #[serde(default)]
+    username: String,
+}
+
+/// Decrypted index file, as stored in a repository's `index` directory.
+#[derive(serde::Deserialize)]
+struct IndexFile {
+    packs: Vec<IndexPack>,
+}
+
+#[derive(serde::Deserialize)]
+struct IndexPack {
+    id: String,
+    blobs: Vec<IndexBlob>,
+}
+
+#[derive(serde::Deserialize)]
+struct IndexBlob {
+    id: String,
+    #[serde(rename = "type")]
+    type_: String,
+    offset: u64,
+    length: u64,
+    #[serde(default)]
+    uncompressed_length: Option<u64>,
+}
+
+/// Decrypted tree blob.
+#[derive(serde::Deserialize)]
+struct Tree {
+    nodes: Option<Vec<Node>>,
+}
+
+#[derive(serde::Deserialize)]
+struct Node {
+    name: String,
+    #[serde(rename = "type")]
+    type_: String,
+    #[serde(default)]
+    mode: i64,
+    #[serde(default)]
+    mtime: String,
+    #[serde(default)]
+    atime: String,
+    #[serde(default)]
+    ctime: String,
+    #[serde(default)]
+    uid: i64,
+    #[serde(default)]
+    gid: i64,
+    #[serde(default)]
+    size: i64,
+    #[serde(default)]
+    subtree: Option<String>,
+}
+
+// -------------------------------------------------------------------------------------------------
+
+/// Location of a tree blob in a pack file.
+struct BlobLocation {
+    pack: String,
+    offset: u64,
+    length: u64,
+    uncompressed_length: Option<u64>,
+}
+
+/// Keys to decrypt and authenticate repository data.
+struct MasterKey {
+    encrypt: [u8; 32],
+    mac_k: [u8; 16],
+    mac_r: [u8; 16],
+}
+
+impl MasterKey {
+    // Create a key from raw key bytes: 32 bytes encryption key, 16 bytes mac k and r.
+    fn from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        if bytes.len() != 64 {
+            return Err(invalid_data("invalid key length"));
+        }
+        let mut key = Self {
+            encrypt: [0; 32],
+            mac_k: [0; 16],
+            mac_r: [0; 16],
+        };
+        key.encrypt.copy_from_slice(&bytes[..32]);
+        key.mac_k.copy_from_slice(&bytes[32..48]);
+        key.mac_r.copy_from_slice(&bytes[48..]);
+        Ok(key)
+    }
+
+    // Verify and decrypt the given IV, ciphertext and MAC. Returns None when the MAC is
+    // wrong, e.g. because the data was encrypted with another key.
+    fn decrypt(&self, data: &[u8]) -> Option<Vec<u8>> {
+        if data.len() < IV_LENGTH + MAC_LENGTH {
+            return None;
+        }
+        let (iv, rest) = data.split_at(IV_LENGTH);
+        let (ciphertext, mac) = rest.split_at(rest.len() - MAC_LENGTH);
+        // Poly1305-AES: the MAC's s part is the AES-128 encrypted IV
+        let mut s = aes::Block::clone_from_slice(iv);
+        aes::Aes128::new(&self.mac_k.into()).encrypt_block(&mut s);
+        let mut poly1305_key = [0; 32];
+        poly1305_key[..16].copy_from_slice(&self.mac_r);
+        poly1305_key[16..].copy_from_slice(&s);
+        let tag = Poly1305::new(&poly1305_key.into()).compute_unpadded(ciphertext);
+        if tag.as_slice() != mac {
+            return None;
+        }
+        let mut plaintext = ciphertext.to_vec();
+        Aes256Ctr::new(&self.encrypt.into(), iv.into()).apply_keystream(&mut plaintext);
+        Some(plaintext)
+    }
+}
+
+// -------------------------------------------------------------------------------------------------
+
+/// Native, read-only access to local restic repositories, which lists snapshots and files
+/// in process instead of spawning restic for each listing.
+///
+/// The repository key gets decrypted and the tree index gets loaded once when opening the
+/// repository, so blobs which got added later on are only found after reopening it.
+pub struct NativeRepository {
+    path: PathBuf,
+    key: MasterKey,
+    version: u32,
+    index: HashMap<String, BlobLocation>,
+}
+
+impl std::fmt::Debug for NativeRepository {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // NB: never print keys
+        f.debug_struct("NativeRepository")
+            .field("path", &self.path)
+            .field("version", &self.version)
+            .field("index", &self.index.len())
+            .finish()
+    }
+}
+
+impl NativeRepository {
+    /// Returns true if the given location is a local repository, which can be read natively.
+    pub fn supports(location: &Location) -> bool {
+        location.prefix.is_empty() && !location.path.is_empty()
+    }
+
+    /// Open the local repository at the given path with the given password.
+    pub fn open(path: &Path, password: &str) -> Result<Self, Error> {
+        log::info!("Opening repository '{}' natively...", path.display());
+        if !path.join("config").exists() {
+            return Err(Error::new(
+                ErrorKind::RepositoryNotFound,
+                format!("No restic repository found at '{}'", path.display()),
+            ));
+        }
+        let key = Self::decrypt_master_key(path, password)?;
+        let config = key
+            .decrypt(&fs::read(path.join("config"))?)
+            .ok_or_else(|| invalid_data("failed to decrypt config"))?;
+        let config = serde_json::from_slice::<Config>(&config)?;
+        let mut repository = Self {
+            path: path.to_path_buf(),
+            key,
+            version: config.version,
+            index: HashMap::new(),
+        };
+        repository.load_index()?;
+        Ok(repository)
+    }
+
+    /// All snapshots in the repository, sorted by time, as listed by `restic snapshots`.
+    pub fn snapshots(&self) -> Result<Vec<Snapshot>, Error> {
+        let mut snapshots = self
+            .snapshot_files()?
+            .into_iter()
+            .map(|(id, snapshot)| Snapshot {
+                short_id: id[..8.min(id.len())].to_string(),
+                id,
+                time: snapshot.time,
+                paths: snapshot.paths,
+                tags: snapshot.tags.unwrap_or_default(),
+                hostname: snapshot.hostname,
+                username: snapshot.username,
+            })
+            .collect::<Vec<_>>();
+        snapshots.sort_by_key(|snapshot| chrono::DateTime::parse_from_rfc3339(&snapshot.time).ok());
+        Ok(snapshots)
+    }
+
+    /// Files at the given path in the given snapshot, as listed by `restic ls`: the node at
+    /// the given path, followed by its direct children when it's a directory.
+    pub fn ls(&self, snapshot_id: &str, path: &str) -> Result<Vec<File>, Error> {
+        let mut snapshots = self
+            .snapshot_files()?
+            .into_iter()
+            .filter(|(id, _)| id.starts_with(snapshot_id))
+            .collect::<Vec<_>>();
+        if snapshots.len() > 1 {
+            return Err(Error::new(
+                ErrorKind::Failed,
+                format!("Ambiguous snapshot id {snapshot_id}: multiple snapshots match"),
+            ));
+        }
+        let (_, snapshot) = snapshots.pop().ok_or_else(|| {
+            Error::new(
+                ErrorKind::NotFound,
+                format!("Can't resolve snapshot with id {snapshot_id}"),
+            )
+        })?;
+        // walk down to the parent tree of the requested path
+        let components = path
+            .split('/')
+            .filter(|component| !component.is_empty())
+            .collect::<Vec<_>>();
+        let mut files = vec![];
+        let mut tree_id = snapshot.tree;
+        let mut node_path = String::new();
+        for (index, component) in components.iter().enumerate() {
+            let Some(node) = self
+                .load_tree(&tree_id)?
+                .into_iter()
+                .find(|node| node.name == *component)
+            else {
+                // restic lists nothing for missing paths
+                return Ok(files);
+            };
+            node_path = format!("{node_path}/{component}");
+            let is_target = index == components.len() - 1;
+            if is_target {
+                files.push(Self::file(&node, node_path.clone()));
+            }
+            match node.subtree {
+                Some(subtree) if node.type_ == "dir" => tree_id = subtree,
+                _ => return Ok(files),
+            }
+        }
+        // list the requested directory's content
+        for node in self.load_tree(&tree_id)? {
+            let path = format!("{node_path}/{}", node.name);
+            files.push(Self::file(&node, path));
+        }
+        Ok(files)
+    }
+
+    // Try decrypting the master key from all key files with the given password. Key files
+    // which can't be read or parsed are skipped, as restic does.
+    fn decrypt_master_key(path: &Path, password: &str) -> Result<MasterKey, Error> {
+        for entry in fs::read_dir(path.join("keys"))? {
+            let key_path = entry?.path();
+            match Self::decrypt_key_file(&key_path, password) {
+                Ok(Some(master_key)) => return Ok(master_key),
+                Ok(None) => (),
+                Err(err) => log::warn!(
+                    "Skipping unreadable key file '{}': {err}",
+                    key_path.display()
+                ),
+            }
+        }
+        Err(Error::new(
+            ErrorKind::WrongPassword,
+            "Wrong password or no key found",
+        ))
+    }
+
+    // Decrypt the master key from the given key file. Returns None for wrong passwords.
+    fn decrypt_key_file(key_path: &Path, password: &str) -> Result<Option<MasterKey>, Error> {
+        let key_file = serde_json::from_slice::<KeyFile>(&fs::read(key_path)?)?;
+        if key_file.kdf != "scrypt" || !key_file.n.is_power_of_two() {
+            return Err(invalid_data(&format!("unsupported kdf '{}'", key_file.kdf)));
+        }
+        let params = scrypt::Params::new(
+            key_file.n.trailing_zeros() as u8,
+            key_file.r,
+            key_file.p,
+            64,
+        )
+        .map_err(|err| invalid_data(&format!("invalid key parameters: {err}")))?;
+        let mut user_key = [0; 64];
+        scrypt::scrypt(
+            password.as_bytes(),
+            &decode_base64(&key_file.salt)?,
+            &params,
+            &mut user_key,
+        )
+        .map_err(|err| invalid_data(&format!("failed to derive key: {err}")))?;
+        let user_key = MasterKey::from_bytes(&user_key)?;
+        let Some(data) = user_key.decrypt(&decode_base64(&key_file.data)?) else {
+            return Ok(None);
+        };
+        let data = serde_json::from_slice::<MasterKeyData>(&data)?;
+        let mut master_key = decode_base64(&data.encrypt)?;
+        master_key.extend(decode_base64(&data.mac.k)?);
+        master_key.extend(decode_base64(&data.mac.r)?);
+        MasterKey::from_bytes(&master_key).map(Some)
+    }
+
+    // Load tree blob locations from all index files.
+    fn load_index(&mut self) -> Result<(), Error> {
+        for entry in fs::read_dir(self.path.join("index"))? {
+            let index_file = serde_json::from_slice::<IndexFile>(&self.load_file(&entry?.path())?)?;
+            for pack in index_file.packs {
+                for blob in pack.blobs {
+                    if blob.type_ == "tree" {
+                        self.index.insert(
+                            blob.id,
+                            BlobLocation {
+                                pack: pack.id.clone(),
+                                offset: blob.offset,
+                                length: blob.length,
+                                uncompressed_length: blob.uncompressed_length,
+                            },
+                        );
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // Load all snapshot files, mapped by their ids.
+    fn snapshot_files(&self) -> Result<Vec<(String, SnapshotFile)>, Error> {
+        let mut snapshots = vec![];
+        for entry in fs::read_dir(self.path.join("snapshots"))? {
+            let entry = entry?;
+            let id = entry.file_name().to_string_lossy().to_string();
+            let snapshot = serde_json::from_slice::<SnapshotFile>(&self.load_file(&entry.path())?)?;
+            snapshots.push((id, snapshot));
+        }
+        Ok(snapshots)
+    }
+
+    // Load, decrypt and decompress the nodes of the tree blob with the given id.
+    fn load_tree(&self, id: &str) -> Result<Vec<Node>, Error> {
+        let location = self
+            .index
+            .get(id)
+            .ok_or_else(|| Error::new(ErrorKind::NotFound, format!("Tree {id} is not indexed")))?;
+        let pack_file = self
+            .path
+            .join("data")
+            .join(&location.pack[..2.min(location.pack.len())])
+            .join(&location.pack);
+        let mut data = vec![0; location.length as usize];
+        let mut file = fs::File::open(pack_file)?;
+        file.seek(SeekFrom::Start(location.offset))?;
+        file.read_exact(&mut data)?;
+        let data = self
+            .key
+            .decrypt(&data)
+            .ok_or_else(|| invalid_data(&format!("failed to decrypt tree {id}")))?;
+        let data = match location.uncompressed_length {
+            Some(length) => decompress(&data, length as usize)?,
+            None => data,
+        };
+        Ok(serde_json::from_slice::<Tree>(&data)?
+            .nodes
+            .unwrap_or_default())
+    }
+
+    // Load, decrypt and decompress an unpacked repository file, e.g. a snapshot or index.
+    fn load_file(&self, path: &Path) -> Result<Vec<u8>, Error> {
+        let data = self
+            .key
+            .decrypt(&fs::read(path)?)
+            .ok_or_else(|| invalid_data(&format!("failed to decrypt file '{}'", path.display())))?;
+        // compressed repositories prefix compressed files with a version byte. JSON content
+        // which starts with `{` or `[` is not compressed.
+        if self.version >= COMPRESSED_REPOSITORY_VERSION
+            && data.first() == Some(&COMPRESSED_FILE_VERSION)
+        {
+            decompress(&data[1..], 0)
+        } else {
+            Ok(data)
+        }
+    }
+
+    // Convert a tree node to a restic `ls` file.
+    fn file(node: &Node, path: String) -> File {
+        File {
+            name: node.name.clone(),
+            type_: node.type_.clone(),
+            path,
+            uid: node.uid,
+            gid: node.gid,
+            size: node.size,
+            mode: node.mode,
+            mtime: node.mtime.clone(),
+            atime: node.atime.clone(),
+            ctime: node.ctime.clone(),
+        }
+    }
+}
+
+// -------------------------------------------------------------------------------------------------
+
+// Create an error for malformed or unexpected repository data.
+fn invalid_data(message: &str) -> Error {
+    Error::new(
+        ErrorKind::Failed,
+        format!("Invalid repository data: {message}"),
+    )
+}
+
+// Decode the given base64 encoded repository data.
+fn decode_base64(data: &str) -> Result<Vec<u8>, Error> {
+    base64::engine::general_purpose::STANDARD
+        .decode(data)
+        .map_err(|err| invalid_data(&format!("invalid base64 data: {err}")))
+}
+
+// Decompress a zstd frame with the given, optional, expected uncompressed length.
+fn decompress(data: &[u8], uncompressed_length: usize) -> Result<Vec<u8>, Error> {
+    let mut output = Vec::with_capacity(uncompressed_length);
+    ruzstd::decoding::StreamingDecoder::new(data)
+        .map_err(|err| invalid_data(&format!("failed to decompress data: {err}")))?
+        .read_to_end(&mut output)?;
+    Ok(output)
+}
+
+// -------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use std::sync::OnceLock;
+
+    use super::*;
+
+    // Test repository fixture, which got created with `restic init` and two `restic backup`s.
+    fn test_repository_path() -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR")).join("test/test-repo")
+    }
+
+    fn test_repository_password() -> String {
+        let password_file = Path::new(env!("CARGO_MANIFEST_DIR")).join("test/test-repo.pass");
+        fs::read_to_string(password_file)
+            .unwrap()
+            .trim()
+            .to_string()
+    }
+
+    // NB: deriving keys with scrypt is slow in debug builds, so open the repository once
+    fn test_repository() -> &'static NativeRepository {
+        static REPOSITORY: OnceLock<NativeRepository> = OnceLock::new();
+        REPOSITORY.get_or_init(|| {
+            NativeRepository::open(&test_repository_path(), &test_repository_password()).unwrap()
+        })
+    }
+
+    #[test]
+    fn list_snapshots() {
+        let snapshots = test_repository().snapshots().unwrap();
+        let ids = snapshots
+            .iter()
+            .map(|snapshot| snapshot.short_id.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(ids, ["0458d5a9", "e9a745cc"]);
+        assert_eq!(
+            snapshots[1].id,
+            "e9a745ccac4e773685e5284c79a868fb70c7c70022256b4ee3fad8d63fee533d"
+        );
+        assert_eq!(snapshots[1].time, "2022-07-20T16:32:43.4518797+02:00");
+        assert!(snapshots[0].tags.is_empty());
+        assert_eq!(snapshots[1].tags, ["SomeTag"]);
+        assert_eq!(snapshots[1].hostname, "X1-EDU");
+        assert_eq!(snapshots[1].paths.len(), 1);
+    }
+
+    #[test]
+    fn list_files() {
+        let repository = test_repository();
+        let files = repository.ls("e9a745cc", "/").unwrap();
+        let names = files
+            .iter()
+            .map(|file| file.name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            names,
+            [
+                "file.go",
+                "location.go",
+                "repository.go",
+                "restic.go",
+                "restic_test.go",
+                "snapshot.go",
+                "test-repo",
+            ]
+        );
+        assert_eq!(files[0].path, "/file.go");
+        assert_eq!(files[0].type_, "file");
+        assert_eq!(files[0].size, 439);
+        assert_eq!(files[6].type_, "dir");
+        // directories are listed with their own node first, as restic does
+        let files = repository.ls("e9a745cc", "/test-repo").unwrap();
+        assert_eq!(files[0].path, "/test-repo");
+        assert!(files.len() > 1);
+        assert!(files[1..]
+            .iter()
+            .all(|file| file.path.starts_with("/test-repo/")));
+        // single files list the file only and missing paths nothing
+        let files = repository.ls("e9a745cc", "/file.go").unwrap();
+        assert_eq!(files.len(), 1);
+        assert!(repository.ls("e9a745cc", "/missing").unwrap().is_empty());
+    }
+
+    #[test]
+    fn resolve_snapshot_id_prefixes() {
+        let repository = test_repository();
+        assert!(repository.ls("0458d5a9164a", "/").is_ok());
+        let err = repository.ls("", "/").unwrap_err();
+        assert!(err.message.contains("Ambiguous"), "{}", err.message);
+        let err = repository.ls("ffffffff", "/").unwrap_err();
+        assert_eq!(err.kind, ErrorKind::NotFound);
+    }
+
+    #[test]
+    fn skip_unreadable_key_files() {
+        let repository = std::env::temp_dir().join(format!(
+            "restic-browser-native-test-{}",
+            crate::restic::new_secret_id()
+        ));
+        let keys = repository.join("keys");
+        fs::create_dir_all(&keys).unwrap();
+        fs::write(keys.join("00-broken"), "not a key file").unwrap();
+        for entry in fs::read_dir(test_repository_path().join("keys")).unwrap() {
+            let entry = entry.unwrap();
+            fs::copy(entry.path(), keys.join(entry.file_name())).unwrap();
+        }
+        let result = NativeRepository::decrypt_master_key(&repository, "wrong password");
+        fs::remove_dir_all(&repository).unwrap();
+        assert_eq!(
+            result.err().map(|err| err.kind),
+            Some(ErrorKind::WrongPassword)
+        );
+    }
+}
@@ -0,0 +1,692 @@
+use std::{
+    collections::HashMap,
+    env, fs,
+    path::{Path, PathBuf},
+};
+
+use serde_json::{Map, Value};
+
+use crate::restic::{
+    extra_arg_takes_value, verify_extra_env, EnvValue, Error, ErrorKind, ExtendedOption, Location,
+};
+
+// -------------------------------------------------------------------------------------------------
+
+/// Default resticprofile and autorestic config file names, in lookup order.
+const DEFAULT_PROFILE_FILES: &[&str] = &[
+    "profiles.yaml",
+    "profiles.yml",
+    "profiles.toml",
+    "profiles.json",
+    ".autorestic.yaml",
+    ".autorestic.yml",
+];
+
+/// Top-level resticprofile keys which are no profiles.
+const RESTICPROFILE_RESERVED_KEYS: &[&str] = &[
+    "version", "global", "groups", "includes", "mixins", "profiles",
+];
+
+/// Restic env variables which are applied as location args instead of env variables.
+const LOCATION_ENVS: &[(&str, &str)] = &[
+    ("RESTIC_REPOSITORY", "repository"),
+    ("RESTIC_REPOSITORY_FILE", "repository-file"),
+    ("RESTIC_PASSWORD", "password"),
+    ("RESTIC_PASSWORD_FILE", "password-file"),
+    ("RESTIC_PASSWORD_COMMAND", "password-command"),
+];
+
+/// Max depth of resticprofile `inherit` chains.
+const MAX_INHERITANCE_DEPTH: usize = 16;
+
+// -------------------------------------------------------------------------------------------------
+
+/// A location which got imported from a resticprofile or autorestic profile.
+#[derive(serde::Serialize, Debug, Clone)]
+pub struct ProfileLocation {
+    pub name: String,
+    pub location: Location,
+}
+
+// -------------------------------------------------------------------------------------------------
+
+/// Find a resticprofile or autorestic config file in the current directory or in the default
+/// config directories of both tools.
+pub fn find_profile_file() -> Option<PathBuf> {
+    let home_dir = env::var_os("HOME")
+        .or_else(|| env::var_os("USERPROFILE"))
+        .map(PathBuf::from);
+    let config_dir = env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| home_dir.as_ref().map(|home| home.join(".config")));
+    let search_dirs = [
+        env::current_dir().ok(),
+        config_dir.map(|dir| dir.join("resticprofile")),
+        home_dir,
+    ];
+    search_dirs
+        .into_iter()
+        .flatten()
+        .flat_map(|dir| DEFAULT_PROFILE_FILES.iter().map(move |name| dir.join(name)))
+        .find(|file| file.is_file())
+}
+
+/// Load all profiles with a repository from the given resticprofile or autorestic config file.
+/// YAML, TOML and JSON files are supported.
+pub fn load_profiles(file: &Path) -> Result<Vec<ProfileLocation>, Error> {
+    let config = read_profile_file(file)?;
+    // relative paths in profiles are relative to the config file
+    let base_dir = file.parent().unwrap_or(Path::new("."));
+    if config.contains_key("backends") {
+        autorestic_locations(&config, base_dir)
+    } else {
+        resticprofile_locations(&config, base_dir)
+    }
+}
+
+/// Load a single profile with the given name from the given config file.
+pub fn load_profile(file: &Path, name: &str) -> Result<Location, Error> {
+    load_profiles(file)?
+        .into_iter()
+        .find(|profile| profile.name == name)
+        .map(|profile| profile.location)
+        .ok_or_else(|| {
+            Error::new(
+                ErrorKind::NotFound,
+                format!(
+                    "No profile named '{name}' with a repository in '{}'",
+                    file.display()
+                ),
+            )
+        })
+}
+
+// -------------------------------------------------------------------------------------------------
+
+fn read_profile_file(file: &Path) -> Result<Map<String, Value>, Error> {
+    let error = |message: String| {
+        Error::new(
+            ErrorKind::InvalidLocation,
+            format!(
+                "Failed to parse profile file '{}': {message}",
+                file.display()
+            ),
+        )
+    };
+    let content =
+        fs::read_to_string(file).map_err(|err| error(format!("Failed to read file: {err}")))?;
+    let extension = file
+        .extension()
+        .map(|ext| ext.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    let config = match extension.as_str() {
+        "toml" => toml::from_str::<Value>(&content).map_err(|err| error(err.to_string()))?,
+        "json" => serde_json::from_str::<Value>(&content).map_err(|err| error(err.to_string()))?,
+        _ => serde_yaml::from_str::<Value>(&content).map_err(|err| error(err.to_string()))?,
+    };
+    match config {
+        Value::Object(config) => Ok(config),
+        _ => Err(error("Expected a map of profiles".to_string())),
+    }
+}
+
+// -------------------------------------------------------------------------------------------------
+
+// Convert resticprofile profiles, see https://creativeprojects.github.io/resticprofile/
+fn resticprofile_locations(
+    config: &Map<String, Value>,
+    base_dir: &Path,
+) -> Result<Vec<ProfileLocation>, Error> {
+    let is_v2 = config
+        .get("version")
+        .and_then(value_to_string)
+        .is_some_and(|version| version.starts_with('2'));
+    let profiles = if is_v2 {
+        match config.get("profiles").and_then(Value::as_object) {
+            Some(profiles) => profiles,
+            None => return Ok(vec![]),
+        }
+    } else {
+        config
+    };
+    let mut locations = vec![];
+    for (name, profile) in profiles {
+        if (!is_v2 && RESTICPROFILE_RESERVED_KEYS.contains(&name.as_str())) || !profile.is_object()
+        {
+            continue;
+        }
+        let profile = resolve_inheritance(profiles, name, 0)?;
+        let mut args = HashMap::new();
+        for key in [
+            "repository",
+            "repository-file",
+            "password-file",
+            "password-command",
+            "password-strategy",
+        ] {
+            if let Some(value) = profile.get(key).and_then(value_to_string) {
+                args.insert(key.to_string(), value);
+            }
+        }
+        let envs = profile_envs(&profile);
+        // skip profiles without repository, e.g. abstract base profiles
+        let has_repository = ["repository", "repository-file"]
+            .iter()
+            .any(|key| args.contains_key(*key))
+            || envs
+                .iter()
+                .any(|(name, _)| name.starts_with("RESTIC_REPOSITORY"));
+        if !has_repository {
+            continue;
+        }
+        let mut location = new_location(args, &envs, base_dir);
+        apply_flags(&mut location, &profile)?;
+        locations.push(ProfileLocation {
+            name: name.clone(),
+            location,
+        });
+    }
+    Ok(locations)
+}
+
+// Merge the given resticprofile profile with the profiles it inherits from.
+fn resolve_inheritance(
+    profiles: &Map<String, Value>,
+    name: &str,
+    depth: usize,
+) -> Result<Map<String, Value>, Error> {
+    let profile = profiles
+        .get(name)
+        .and_then(Value::as_object)
+        .ok_or_else(|| {
+            Error::new(
+                ErrorKind::InvalidLocation,
+                format!("Can't find inherited profile '{name}'"),
+            )
+        })?;
+    let Some(parent_name) = profile.get("inherit").and_then(value_to_string) else {
+        return Ok(profile.clone());
+    };
+    if depth >= MAX_INHERITANCE_DEPTH {
+        return Err(Error::new(
+            ErrorKind::InvalidLocation,
+            format!("Profile '{name}' has a cyclic or too deep inheritance chain"),
+        ));
+    }
+    let mut merged = resolve_inheritance(profiles, &parent_name, depth + 1)?;
+    merge_maps(&mut merged, profile);
+    Ok(merged)
+}
+
+// Recursively merge the given overrides into the given map.
+fn merge_maps(map: &mut Map<String, Value>, overrides: &Map<String, Value>) {
+    for (key, value) in overrides {
+        match (map.get_mut(key), value) {
+            (Some(Value::Object(existing)), Value::Object(value)) => merge_maps(existing, value),
+            _ => {
+                map.insert(key.clone(), value.clone());
+            }
+        }
+    }
+}
+
+// -------------------------------------------------------------------------------------------------
+
+// Convert autorestic backends, see https://autorestic.vercel.app/
+fn autorestic_locations(
+    config: &Map<String, Value>,
+    base_dir: &Path,
+) -> Result<Vec<ProfileLocation>, Error> {
+    let Some(backends) = config.get("backends").and_then(Value::as_object) else {
+        return Ok(vec![]);
+    };
+    let mut locations = vec![];
+    for (name, backend) in backends {
+        let Some(backend) = backend.as_object() else {
+            continue;
+        };
+        let Some(path) = backend.get("path").and_then(value_to_string) else {
+            continue;
+        };
+        let backend_type = backend
+            .get("type")
+            .and_then(value_to_string)
+            .unwrap_or("local".to_string());
+        let mut args = HashMap::new();
+        if backend_type == "local" {
+            args.insert("repository".to_string(), path);
+        } else {
+            args.insert("repository".to_string(), format!("{backend_type}:{path}"));
+        }
+        // the key may also be passed via env, as autorestic does
+        let password = backend.get("key").and_then(value_to_string).or_else(|| {
+            let name = name.to_uppercase().replace('-', "_");
+            env::var(format!("AUTORESTIC_{name}_RESTIC_PASSWORD")).ok()
+        });
+        if let Some(password) = password {
+            args.insert("password".to_string(), password);
+        }
+        let mut envs = profile_envs(backend);
+        if let Some(rest) = backend.get("rest").and_then(Value::as_object) {
+            for (key, env_name) in [
+                ("user", "RESTIC_REST_USERNAME"),
+                ("password", "RESTIC_REST_PASSWORD"),
+            ] {
+                if let Some(value) = rest.get(key).and_then(value_to_string) {
+                    envs.push((env_name.to_string(), value));
+                }
+            }
+        }
+        let mut location = new_location(args, &envs, base_dir);
+        // only options which apply to all restic commands are relevant here
+        if let Some(options) = backend
+            .get("options")
+            .and_then(|options| options.get("all"))
+            .and_then(Value::as_object)
+        {
+            apply_flags(&mut location, options)?;
+        }
+        locations.push(ProfileLocation {
+            name: name.clone(),
+            location,
+        });
+    }
+    Ok(locations)
+}
+
+// -------------------------------------------------------------------------------------------------
+
+// Create a new location from the given location args and profile env variables.
+fn new_location(
+    mut args: HashMap<String, String>,
+    envs: &[(String, String)],
+    base_dir: &Path,
+) -> Location {
+    for (env_name, arg_name) in LOCATION_ENVS {
+        if let Some((_, value)) = envs.iter().find(|(name, _)| name == env_name) {
+            args.entry(arg_name.to_string())
+                .or_insert_with(|| value.clone());
+        }
+    }
+    for key in ["repository-file", "password-file"] {
+        if let Some(file) = args.get_mut(key) {
+            *file = resolve_path(base_dir, file);
+        }
+    }
+    // NB: only local repositories have no backend prefix
+    if let Some(repository) = args.get_mut("repository") {
+        if !repository.contains(':') {
+            *repository = resolve_path(base_dir, repository);
+        }
+    }
+    let mut location =
+        Location::new_from_args(args.into_iter().map(|(k, v)| (k, Some(v))).collect());
+    for (name, value) in envs {
+        if LOCATION_ENVS.iter().any(|(env_name, _)| env_name == name) {
+            continue;
+        }
+        if let Some(credential) = location
+            .credentials
+            .iter_mut()
+            .find(|credential| credential.name == *name)
+        {
+            credential.value = value.clone();
+        } else if verify_extra_env(name).is_ok() {
+            location.extra_envs.push(EnvValue {
+                name: name.clone(),
+                value: value.clone(),
+                secret_id: None,
+            });
+        } else {
+            log::warn!("Ignoring unsupported profile env variable '{name}'");
+        }
+    }
+    location
+}
+
+// Apply restic options and global flags from the given profile or options section.
+fn apply_flags(location: &mut Location, flags: &Map<String, Value>) -> Result<(), Error> {
+    let mut extra_args = vec![];
+    for (key, value) in flags {
+        if key == "insecure-tls" {
+            location.insecure_tls = value.as_bool().unwrap_or(false);
+        } else if key == "option" {
+            let options = match value {
+                Value::Array(values) => values.iter().filter_map(value_to_string).collect(),
+                _ => value_to_string(value).into_iter().collect::<Vec<_>>(),
+            };
+            for option in options {
+                let (name, value) = option.split_once('=').unwrap_or((option.as_str(), ""));
+                let option = ExtendedOption {
+                    name: name.trim().to_string(),
+                    value: value.trim().to_string(),
+                };
+                if !option.is_valid() {
+                    return Err(Error::new(
+                        ErrorKind::InvalidLocation,
+                        format!("Invalid extended option '{}' in profile", option.name),
+                    ));
+                }
+                location.options.push(option);
+            }
+        } else {
+            let flag = format!("--{key}");
+            match extra_arg_takes_value(&flag) {
+                Some(true) => {
+                    if let Some(value) = value_to_string(value) {
+                        let value = shlex::try_quote(&value).map_err(|err| {
+                            Error::new(
+                                ErrorKind::InvalidLocation,
+                                format!("Invalid value for '{key}' in profile: {err}"),
+                            )
+                        })?;
+                        extra_args.push(format!("{flag}={value}"));
+                    }
+                }
+                Some(false) if value.as_bool() == Some(true) => extra_args.push(flag),
+                _ => (),
+            }
+        }
+    }
+    location.extra_args = extra_args.join(" ");
+    Ok(())
+}
+
+// Get the env variables of the given profile or backend, with upper case names.
+fn profile_envs(profile: &Map<String, Value>) -> Vec<(String, String)> {
+    profile
+        .get("env")
+        .and_then(Value::as_object)
+        .map(|envs| {
+            envs.iter()
+                .filter_map(|(name, value)| Some((name.to_uppercase(), value_to_string(value)?)))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+// Resolve `~` and relative paths in profiles.
+fn resolve_path(base_dir: &Path, path: &str) -> String {
+    let path = match path.strip_prefix("~/") {
+        Some(stripped) => match env::var_os("HOME").or_else(|| env::var_os("USERPROFILE")) {
+            Some(home) => PathBuf::from(home).join(stripped),
+            None => PathBuf::from(path),
+        },
+        None => PathBuf::from(path),
+    };
+    if path.is_relative() {
+        let path = path.strip_prefix(".").unwrap_or(&path);
+        base_dir.join(path).to_string_lossy().to_string()
+    } else {
+        path.to_string_lossy().to_string()
+    }
+}
+
+// Convert scalar profile values to strings.
+fn value_to_string(value: &Value) -> Option<String> {
+    match value {
+        Value::String(string) => Some(string.clone()),
+        Value::Number(number) => Some(number.to_string()),
+        Value::Bool(bool) => Some(bool.to_string()),
+        _ => None,
+    }
+}
+
+// -------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Write the given profile file content into a new temp dir and load its profiles.
+    fn load_fixture(
+        file_name: &str,
+        content: &str,
+    ) -> (PathBuf, Result<Vec<ProfileLocation>, Error>) {
+        let dir = env::temp_dir().join(format!(
+            "restic-browser-profile-test-{}",
+            crate::restic::new_secret_id()
+        ));
+        fs::create_dir_all(&dir).unwrap();
+        let file = dir.join(file_name);
+        fs::write(&file, content).unwrap();
+        let result = load_profiles(&file);
+        fs::remove_dir_all(&dir).unwrap();
+        (dir, result)
+    }
+
+    fn find<'a>(profiles: &'a [ProfileLocation], name: &str) -> &'a Location {
+        &profiles
+            .iter()
+            .find(|profile| profile.name == name)
+            .unwrap_or_else(|| panic!("missing profile '{name}'"))
+            .location
+    }
+
+    #[test]
+    fn load_resticprofile_v1_profiles() {
+        let (dir, profiles) = load_fixture(
+            "profiles.yaml",
+            r#"
+version: "1"
+global:
+  repository: "/global-is-no-profile"
+groups:
+  all: [home]
+base:
+  password-command: "pass show restic"
+  insecure-tls: true
+home:
+  inherit: base
+  repository: "repos/home"
+  env:
+    aws_access_key_id: "key-id"
+s3:
+  repository: "s3:s3.amazonaws.com/bucket"
+  password-file: "~/.restic-pass"
+"#,
+        );
+        let profiles = profiles.unwrap();
+        let mut names = profiles
+            .iter()
+            .map(|profile| profile.name.as_str())
+            .collect::<Vec<_>>();
+        names.sort();
+        // reserved keys and base profiles without repository are skipped
+        assert_eq!(names, ["home", "s3"]);
+
+        let home = find(&profiles, "home");
+        assert_eq!(home.prefix, "");
+        assert_eq!(home.path, dir.join("repos/home").to_string_lossy());
+        assert_eq!(home.password_command.as_deref(), Some("pass show restic"));
+        assert!(home.insecure_tls);
+        assert!(home
+            .extra_envs
+            .iter()
+            .any(|env| env.name == "AWS_ACCESS_KEY_ID"));
+
+        let s3 = find(&profiles, "s3");
+        assert_eq!(s3.prefix, "s3");
+        assert_eq!(s3.path, "s3.amazonaws.com/bucket");
+        let s3_key = s3
+            .credentials
+            .iter()
+            .find(|credential| credential.name == "AWS_ACCESS_KEY_ID");
+        assert!(s3_key.is_some());
+    }
+
+    #[test]
+    fn load_resticprofile_v2_profiles() {
+        let (_, profiles) = load_fixture(
+            "profiles.toml",
+            r#"
+version = "2"
+
+[global]
+priority = "low"
+
+[profiles.version]
+repository = "/a-profile-named-like-a-reserved-key"
+
+[profiles.nas]
+repository = "rest:http://nas:8000/repo"
+password-strategy = "pipe"
+"#,
+        );
+        let profiles = profiles.unwrap();
+        assert_eq!(profiles.len(), 2);
+        assert_eq!(
+            find(&profiles, "version").path,
+            "/a-profile-named-like-a-reserved-key"
+        );
+        let nas = find(&profiles, "nas");
+        assert_eq!(nas.prefix, "rest");
+        assert_eq!(nas.path, "http://nas:8000/repo");
+        assert_eq!(
+            nas.password_strategy,
+            "pipe".parse::<crate::restic::PasswordStrategy>().unwrap()
+        );
+    }
+
+    #[test]
+    fn merge_inherited_profiles() {
+        let profiles = serde_json::json!({
+            "base": { "repository": "/base", "env": { "A": "1", "B": "1" } },
+            "child": { "inherit": "base", "env": { "B": "2" } },
+            "grandchild": { "inherit": "child", "repository": "/grandchild" },
+        });
+        let profiles = profiles.as_object().unwrap();
+        let grandchild = resolve_inheritance(profiles, "grandchild", 0).unwrap();
+        assert_eq!(grandchild["repository"], "/grandchild");
+        assert_eq!(grandchild["env"], serde_json::json!({ "A": "1", "B": "2" }));
+
+        let error = resolve_inheritance(profiles, "missing", 0).unwrap_err();
+        assert_eq!(error.kind, ErrorKind::InvalidLocation);
+    }
+
+    #[test]
+    fn reject_cyclic_inheritance() {
+        let (_, profiles) = load_fixture(
+            "profiles.json",
+            r#"{
+                "a": { "inherit": "b", "repository": "/a" },
+                "b": { "inherit": "a", "repository": "/b" }
+            }"#,
+        );
+        let error = profiles.unwrap_err();
+        assert_eq!(error.kind, ErrorKind::InvalidLocation);
+        assert!(error.message.contains("cyclic"), "{}", error.message);
+    }
+
+    #[test]
+    fn load_autorestic_backends() {
+        env::set_var(
+            "AUTORESTIC_ENV_BACKEND_RESTIC_PASSWORD",
+            "password-from-env",
+        );
+        let (dir, profiles) = load_fixture(
+            ".autorestic.yml",
+            r#"
+version: 2
+backends:
+  local:
+    type: local
+    path: ./backups
+    key: local-key
+  env-backend:
+    type: b2
+    path: bucket:/repo
+  rest:
+    type: rest
+    path: http://nas:8000/repo
+    key: rest-key
+    rest:
+      user: rest-user
+      password: rest-password
+    options:
+      all:
+        limit-download: 100
+      backup:
+        tag: ignored
+"#,
+        );
+        let profiles = profiles.unwrap();
+        assert_eq!(profiles.len(), 3);
+
+        let local = find(&profiles, "local");
+        assert_eq!(local.path, dir.join("backups").to_string_lossy());
+        assert_eq!(local.password, "local-key");
+
+        let env_backend = find(&profiles, "env-backend");
+        assert_eq!(env_backend.prefix, "b2");
+        assert_eq!(env_backend.path, "bucket:/repo");
+        assert_eq!(env_backend.password, "password-from-env");
+
+        let rest = find(&profiles, "rest");
+        assert_eq!(rest.prefix, "rest");
+        assert_eq!(rest.password, "rest-key");
+        let credential = |name: &str| {
+            rest.credentials
+                .iter()
+                .find(|credential| credential.name == name)
+                .map(|credential| credential.value.clone())
+        };
+        assert_eq!(
+            credential("RESTIC_REST_USERNAME").as_deref(),
+            Some("rest-user")
+        );
+        assert_eq!(
+            credential("RESTIC_REST_PASSWORD").as_deref(),
+            Some("rest-password")
+        );
+        assert_eq!(rest.extra_args, "--limit-download=100");
+    }
+
+    #[test]
+    fn resolve_relative_and_home_paths() {
+        let base_dir = Path::new("/profiles");
+        let expected = base_dir.join("repo").to_string_lossy().to_string();
+        assert_eq!(resolve_path(base_dir, "repo"), expected);
+        assert_eq!(resolve_path(base_dir, "./repo"), expected);
+        assert_eq!(resolve_path(base_dir, "/abs/repo"), "/abs/repo");
+        if let Some(home) = env::var_os("HOME") {
+            assert_eq!(
+                resolve_path(base_dir, "~/repo"),
+                PathBuf::from(home).join("repo").to_string_lossy()
+            );
+        }
+    }
+
+    #[test]
+    fn apply_profile_flags() {
+        let flags = serde_json::json!({
+            "cache-dir": "/my cache",
+            "cleanup-cache": false,
+            "insecure-tls": true,
+            "limit-download": 100,
+            "no-cache": true,
+            "option": ["s3.region=eu-west-1", " sftp.command = ssh host "],
+            "verbose": 2,
+        });
+        let mut location = Location::default();
+        apply_flags(&mut location, flags.as_object().unwrap()).unwrap();
+        // values get quoted, disabled and unsupported flags are skipped
+        assert_eq!(
+            location.extra_args,
+            "--cache-dir='/my cache' --limit-download=100 --no-cache"
+        );
+        assert!(location.insecure_tls);
+        assert_eq!(
+            location
+                .options
+                .iter()
+                .map(|option| (option.name.as_str(), option.value.as_str()))
+                .collect::<Vec<_>>(),
+            [("s3.region", "eu-west-1"), ("sftp.command", "ssh host")]
+        );
+
+        let flags = serde_json::json!({ "option": "no-backend-name=1" });
+        let error = apply_flags(&mut Location::default(), flags.as_object().unwrap()).unwrap_err();
+        assert_eq!(error.kind, ErrorKind::InvalidLocation);
+    }
+}
@@ -0,0 +1,92 @@
+use std::{
+    path::Path,
+    sync::{Arc, RwLock},
+};
+
+use chacha20poly1305::aead::{rand_core::RngCore, OsRng};
+use lazy_static::lazy_static;
+
+use crate::restic::{register_secret, Error, ErrorKind};
+
+// -------------------------------------------------------------------------------------------------
+
+/// OS keychain secret store
+mod keychain;
+
+pub use keychain::KeychainSecretStore;
+
+/// Encrypted file secret store
+mod encrypted_file;
+
+pub use encrypted_file::EncryptedFileSecretStore;
+
+// -------------------------------------------------------------------------------------------------
+
+/// A store for repository passwords and backend credentials.
+///
+/// Secrets are referenced by id from locations and only get resolved right before
+/// spawning restic, so that the frontend never has to keep them in presets.
+pub trait SecretStore: Send + Sync {
+    /// Display name of the store, e.g. for logging.
+    fn name(&self) -> &'static str;
+    /// Fetch the secret with the given id. Returns None when there is no such secret.
+    fn get(&self, id: &str) -> Result<Option<String>, Error>;
+    /// Create or update the secret with the given id.
+    fn set(&self, id: &str, secret: &str) -> Result<(), Error>;
+    /// Remove the secret with the given id. Removing missing secrets is not an error.
+    fn delete(&self, id: &str) -> Result<(), Error>;
+}
+
+// -------------------------------------------------------------------------------------------------
+
+lazy_static! {
+    /// The secret store all secret ids get resolved with.
+    static ref SECRET_STORE: RwLock<Option<Arc<dyn SecretStore>>> = RwLock::new(None);
+}
+
+/// Set the global secret store, as used by `Program` to resolve location secrets.
+pub fn set_secret_store(store: Arc<dyn SecretStore>) {
+    log::info!("Using the {} to store secrets", store.name());
+    match SECRET_STORE.write() {
+        Ok(mut secret_store) => *secret_store = Some(store),
+        Err(err) => log::error!("Failed to set secret store: {err}"),
+    }
+}
+
+/// Get the global secret store.
+pub fn secret_store() -> Result<Arc<dyn SecretStore>, Error> {
+    SECRET_STORE
+        .read()
+        .map_err(|err| Error::new(ErrorKind::SecretStore, err.to_string()))?
+        .clone()
+        .ok_or_else(|| Error::new(ErrorKind::SecretStore, "No secret store available"))
+}
+
+/// Resolve the secret with the given id from the global secret store.
+pub fn resolve_secret(id: &str) -> Result<String, Error> {
+    let secret = secret_store()?.get(id)?.ok_or_else(|| {
+        Error::new(
+            ErrorKind::NotFound,
+            format!("Can't resolve secret with id '{id}'"),
+        )
+    })?;
+    register_secret(&secret);
+    Ok(secret)
+}
+
+/// Create a new random secret id.
+pub fn new_secret_id() -> String {
+    let mut bytes = [0u8; 16];
+    OsRng.fill_bytes(&mut bytes);
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+/// Create the default secret store: the OS keychain when it's accessible, else an
+/// encrypted secrets file in the given directory (e.g. on headless Linux setups).
+pub fn default_secret_store(secrets_dir: &Path) -> Result<Arc<dyn SecretStore>, Error> {
+    if let Some(keychain) = KeychainSecretStore::new() {
+        Ok(Arc::new(keychain))
+    } else {
+        Ok(Arc::new(EncryptedFileSecretStore::new(secrets_dir)?))
+    }
+}
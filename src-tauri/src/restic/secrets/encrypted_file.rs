@@ -0,0 +1,323 @@
+use std::{
+    collections::BTreeMap,
+    env, fs,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use base64::Engine;
+use chacha20poly1305::{
+    aead::{rand_core::RngCore, Aead, AeadCore, KeyInit, OsRng, Payload},
+    ChaCha20Poly1305, Key, Nonce,
+};
+
+use crate::restic::{Error, ErrorKind, SecretStore};
+
+// -------------------------------------------------------------------------------------------------
+
+/// Env variable with an optional passphrase the secrets file key gets derived from.
+const SECRETS_PASSPHRASE_ENV: &str = "RESTIC_BROWSER_SECRETS_PASSPHRASE";
+
+/// Name of the encrypted secrets file.
+const SECRETS_FILE_NAME: &str = "secrets.json";
+/// Name of the generated key file, used when no passphrase is set.
+const SECRETS_KEY_FILE_NAME: &str = "secrets.key";
+
+/// Current secrets file format version.
+const SECRETS_FILE_VERSION: u32 = 1;
+
+// -------------------------------------------------------------------------------------------------
+
+/// Serialized layout of the secrets file.
+#[derive(serde::Serialize, serde::Deserialize, Debug, Default)]
+struct SecretsFile {
+    version: u32,
+    /// Base64 encoded salt for passphrase based key derivation.
+    salt: String,
+    secrets: BTreeMap<String, EncryptedSecret>,
+}
+
+/// A single ChaCha20-Poly1305 encrypted secret. The secret id is used as associated data,
+/// so encrypted secrets can't be swapped in the file.
+#[derive(serde::Serialize, serde::Deserialize, Debug)]
+struct EncryptedSecret {
+    nonce: String,
+    data: String,
+}
+
+// -------------------------------------------------------------------------------------------------
+
+/// Secret store fallback for setups without an accessible OS keychain: secrets are stored
+/// encrypted in a JSON file.
+///
+/// The file key is derived from the passphrase in `RESTIC_BROWSER_SECRETS_PASSPHRASE` via
+/// Argon2, when set. Else a random key gets generated and stored next to the secrets file,
+/// which is readable by the current user only. Without a passphrase secrets thus are only
+/// obfuscated, not encrypted: anyone who can read both files can decrypt them.
+pub struct EncryptedFileSecretStore {
+    path: PathBuf,
+    cipher: ChaCha20Poly1305,
+    lock: Mutex<()>,
+}
+
+impl EncryptedFileSecretStore {
+    /// Open or create an encrypted secrets file in the given directory.
+    pub fn new(secrets_dir: &Path) -> Result<Self, Error> {
+        let passphrase = env::var(SECRETS_PASSPHRASE_ENV).ok();
+        Self::open(secrets_dir, passphrase.as_deref())
+    }
+
+    // Open or create an encrypted secrets file with the given optional passphrase.
+    fn open(secrets_dir: &Path, passphrase: Option<&str>) -> Result<Self, Error> {
+        fs::create_dir_all(secrets_dir)?;
+        let path = secrets_dir.join(SECRETS_FILE_NAME);
+        let mut file = Self::read_file(&path)?;
+        if file.salt.is_empty() {
+            let mut salt = [0u8; 16];
+            OsRng.fill_bytes(&mut salt);
+            file.version = SECRETS_FILE_VERSION;
+            file.salt = Self::encode(&salt);
+            Self::write_file(&path, &file)?;
+        }
+        let key = match passphrase {
+            Some(passphrase) if !passphrase.is_empty() => {
+                Self::derive_key(passphrase, &Self::decode(&file.salt)?)?
+            }
+            _ => {
+                log::warn!(
+                    "No {SECRETS_PASSPHRASE_ENV} set: the secrets file key is stored unencrypted \
+                    next to the secrets file"
+                );
+                Self::read_or_create_key_file(&secrets_dir.join(SECRETS_KEY_FILE_NAME))?
+            }
+        };
+        Ok(Self {
+            path,
+            cipher: ChaCha20Poly1305::new(&key),
+            lock: Mutex::new(()),
+        })
+    }
+
+    fn read_file(path: &Path) -> Result<SecretsFile, Error> {
+        if !path.exists() {
+            return Ok(SecretsFile::default());
+        }
+        let file = serde_json::from_slice::<SecretsFile>(&fs::read(path)?)
+            .map_err(|err| Self::error(format!("Invalid secrets file: {err}")))?;
+        if file.version > SECRETS_FILE_VERSION {
+            return Err(Self::error(format!(
+                "Unsupported secrets file version {}",
+                file.version
+            )));
+        }
+        Ok(file)
+    }
+
+    fn write_file(path: &Path, file: &SecretsFile) -> Result<(), Error> {
+        // write to a temp file first, so a failed write can't corrupt existing secrets
+        let temp_path = path.with_extension("json.tmp");
+        Self::write_private(&temp_path, &serde_json::to_vec_pretty(file)?)?;
+        fs::rename(&temp_path, path)?;
+        Ok(())
+    }
+
+    fn read_or_create_key_file(path: &Path) -> Result<Key, Error> {
+        if path.exists() {
+            let key = fs::read(path)?;
+            if key.len() != 32 {
+                return Err(Self::error("Invalid secrets key file"));
+            }
+            Ok(*Key::from_slice(&key))
+        } else {
+            let key = ChaCha20Poly1305::generate_key(&mut OsRng);
+            Self::write_private(path, &key)?;
+            Ok(key)
+        }
+    }
+
+    fn derive_key(passphrase: &str, salt: &[u8]) -> Result<Key, Error> {
+        let mut key = Key::default();
+        argon2::Argon2::default()
+            .hash_password_into(passphrase.as_bytes(), salt, &mut key)
+            .map_err(|err| Self::error(format!("Failed to derive secrets key: {err}")))?;
+        Ok(key)
+    }
+
+    // Write a file which only is accessible by the current user.
+    fn write_private(path: &Path, contents: &[u8]) -> Result<(), Error> {
+        let mut options = fs::OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600);
+        }
+        std::io::Write::write_all(&mut options.open(path)?, contents)?;
+        Ok(())
+    }
+
+    fn encode(data: &[u8]) -> String {
+        base64::engine::general_purpose::STANDARD.encode(data)
+    }
+
+    fn decode(data: &str) -> Result<Vec<u8>, Error> {
+        base64::engine::general_purpose::STANDARD
+            .decode(data)
+            .map_err(|err| Self::error(format!("Invalid secrets file: {err}")))
+    }
+
+    fn error<S: Into<String>>(message: S) -> Error {
+        Error::new(ErrorKind::SecretStore, message)
+    }
+}
+
+impl SecretStore for EncryptedFileSecretStore {
+    fn name(&self) -> &'static str {
+        "encrypted secrets file"
+    }
+
+    fn get(&self, id: &str) -> Result<Option<String>, Error> {
+        let _lock = self
+            .lock
+            .lock()
+            .map_err(|err| Self::error(err.to_string()))?;
+        let file = Self::read_file(&self.path)?;
+        let Some(secret) = file.secrets.get(id) else {
+            return Ok(None);
+        };
+        let nonce = Self::decode(&secret.nonce)?;
+        if nonce.len() != 12 {
+            return Err(Self::error("Invalid secrets file: bad nonce"));
+        }
+        let payload = Payload {
+            msg: &Self::decode(&secret.data)?,
+            aad: id.as_bytes(),
+        };
+        let data = self
+            .cipher
+            .decrypt(Nonce::from_slice(&nonce), payload)
+            .map_err(|_| {
+                Self::error("Failed to decrypt secret: wrong secrets key or passphrase")
+            })?;
+        String::from_utf8(data)
+            .map(Some)
+            .map_err(|err| Self::error(err.to_string()))
+    }
+
+    fn set(&self, id: &str, secret: &str) -> Result<(), Error> {
+        let _lock = self
+            .lock
+            .lock()
+            .map_err(|err| Self::error(err.to_string()))?;
+        let mut file = Self::read_file(&self.path)?;
+        let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let payload = Payload {
+            msg: secret.as_bytes(),
+            aad: id.as_bytes(),
+        };
+        let data = self
+            .cipher
+            .encrypt(&nonce, payload)
+            .map_err(|_| Self::error("Failed to encrypt secret"))?;
+        file.secrets.insert(
+            id.to_string(),
+            EncryptedSecret {
+                nonce: Self::encode(&nonce),
+                data: Self::encode(&data),
+            },
+        );
+        Self::write_file(&self.path, &file)
+    }
+
+    fn delete(&self, id: &str) -> Result<(), Error> {
+        let _lock = self
+            .lock
+            .lock()
+            .map_err(|err| Self::error(err.to_string()))?;
+        let mut file = Self::read_file(&self.path)?;
+        if file.secrets.remove(id).is_some() {
+            Self::write_file(&self.path, &file)?;
+        }
+        Ok(())
+    }
+}
+
+// -------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Create a new, empty temp directory for a secrets file.
+    fn temp_dir() -> PathBuf {
+        env::temp_dir().join(format!(
+            "restic-browser-secrets-test-{}",
+            crate::restic::new_secret_id()
+        ))
+    }
+
+    #[test]
+    fn encrypt_and_decrypt_secrets() {
+        for passphrase in [Some("passphrase"), None] {
+            let dir = temp_dir();
+            let store = EncryptedFileSecretStore::open(&dir, passphrase).unwrap();
+            store.set("a", "secret a").unwrap();
+            store.set("b", "secret b").unwrap();
+            assert_eq!(store.get("a").unwrap().as_deref(), Some("secret a"));
+            assert_eq!(store.get("c").unwrap(), None);
+            // secrets are not stored in plain text
+            let contents = fs::read_to_string(dir.join(SECRETS_FILE_NAME)).unwrap();
+            assert!(!contents.contains("secret a"));
+            // reopening the file decrypts existing secrets
+            let store = EncryptedFileSecretStore::open(&dir, passphrase).unwrap();
+            assert_eq!(store.get("b").unwrap().as_deref(), Some("secret b"));
+            store.delete("b").unwrap();
+            assert_eq!(store.get("b").unwrap(), None);
+            fs::remove_dir_all(&dir).unwrap();
+        }
+    }
+
+    #[test]
+    fn reject_wrong_passphrase() {
+        let dir = temp_dir();
+        let store = EncryptedFileSecretStore::open(&dir, Some("passphrase")).unwrap();
+        store.set("a", "secret a").unwrap();
+        let store = EncryptedFileSecretStore::open(&dir, Some("wrong passphrase")).unwrap();
+        assert_eq!(store.get("a").unwrap_err().kind, ErrorKind::SecretStore);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn reject_swapped_secrets() {
+        let dir = temp_dir();
+        let store = EncryptedFileSecretStore::open(&dir, Some("passphrase")).unwrap();
+        store.set("a", "secret a").unwrap();
+        store.set("b", "secret b").unwrap();
+        // copy the encrypted secret of "a" to "b"
+        let path = dir.join(SECRETS_FILE_NAME);
+        let mut file = EncryptedFileSecretStore::read_file(&path).unwrap();
+        let secret_a = file.secrets.remove("a").unwrap();
+        file.secrets.insert("b".to_string(), secret_a);
+        EncryptedFileSecretStore::write_file(&path, &file).unwrap();
+        assert_eq!(store.get("b").unwrap_err().kind, ErrorKind::SecretStore);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn derive_deterministic_keys() {
+        let salt = [1u8; 16];
+        let key = EncryptedFileSecretStore::derive_key("passphrase", &salt).unwrap();
+        assert_eq!(
+            key,
+            EncryptedFileSecretStore::derive_key("passphrase", &salt).unwrap()
+        );
+        assert_ne!(
+            key,
+            EncryptedFileSecretStore::derive_key("other passphrase", &salt).unwrap()
+        );
+        assert_ne!(
+            key,
+            EncryptedFileSecretStore::derive_key("passphrase", &[2u8; 16]).unwrap()
+        );
+    }
+}
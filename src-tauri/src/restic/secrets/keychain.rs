@@ -0,0 +1,65 @@
+use crate::restic::{Error, ErrorKind, SecretStore};
+
+// -------------------------------------------------------------------------------------------------
+
+/// Keychain service name all secrets get stored under.
+const KEYCHAIN_SERVICE_NAME: &str = "restic-browser";
+
+// -------------------------------------------------------------------------------------------------
+
+/// Secret store which uses the OS keychain: the Secret Service (libsecret, e.g. GNOME Keyring
+/// or KWallet) on Linux, the Keychain on macOS and the Credential Manager on Windows.
+pub struct KeychainSecretStore {}
+
+impl KeychainSecretStore {
+    /// Create a new keychain store. Returns None when the keychain is not accessible,
+    /// e.g. when there's no Secret Service running on headless Linux setups.
+    pub fn new() -> Option<Self> {
+        // fetching a missing entry fails with `NoEntry` only when the keychain is usable
+        match Self::entry("probe").and_then(|entry| entry.get_password()) {
+            Ok(_) | Err(keyring::Error::NoEntry) => Some(Self {}),
+            Err(err) => {
+                log::warn!("OS keychain is not accessible: {err}");
+                None
+            }
+        }
+    }
+
+    fn entry(id: &str) -> keyring::Result<keyring::Entry> {
+        keyring::Entry::new(KEYCHAIN_SERVICE_NAME, id)
+    }
+
+    fn error(err: keyring::Error) -> Error {
+        Error::new(
+            ErrorKind::SecretStore,
+            format!("Failed to access OS keychain: {err}"),
+        )
+    }
+}
+
+impl SecretStore for KeychainSecretStore {
+    fn name(&self) -> &'static str {
+        "OS keychain"
+    }
+
+    fn get(&self, id: &str) -> Result<Option<String>, Error> {
+        match Self::entry(id).and_then(|entry| entry.get_password()) {
+            Ok(secret) => Ok(Some(secret)),
+            Err(keyring::Error::NoEntry) => Ok(None),
+            Err(err) => Err(Self::error(err)),
+        }
+    }
+
+    fn set(&self, id: &str, secret: &str) -> Result<(), Error> {
+        Self::entry(id)
+            .and_then(|entry| entry.set_password(secret))
+            .map_err(Self::error)
+    }
+
+    fn delete(&self, id: &str) -> Result<(), Error> {
+        match Self::entry(id).and_then(|entry| entry.delete_credential()) {
+            Ok(()) | Err(keyring::Error::NoEntry) => Ok(()),
+            Err(err) => Err(Self::error(err)),
+        }
+    }
+}
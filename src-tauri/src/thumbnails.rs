@@ -0,0 +1,352 @@
+use std::{
+    fs,
+    io::{Cursor, Read},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        mpsc, Arc, Mutex,
+    },
+    time::SystemTime,
+};
+
+use base64::Engine;
+use sha2::{Digest, Sha256};
+
+use crate::restic;
+
+// -------------------------------------------------------------------------------------------------
+
+/// Command group of all thumbnail dump processes.
+pub const THUMBNAIL_COMMAND_GROUP: &str = "thumbnails";
+
+/// Number of worker threads which concurrently dump and decode images.
+const WORKER_COUNT: usize = 4;
+
+/// Max size of image files that get thumbnails: larger files are skipped.
+const MAX_IMAGE_FILE_SIZE: i64 = 64 * 1024 * 1024;
+
+/// Max edge length of generated thumbnails in pixels.
+const MAX_THUMBNAIL_SIZE: u32 = 1024;
+
+/// Max total size of all cached thumbnail files. When exceeded, the least recently used
+/// thumbnails get removed until the cache shrinks to 3/4 of this size.
+const MAX_CACHE_SIZE: u64 = 256 * 1024 * 1024;
+
+// -------------------------------------------------------------------------------------------------
+
+/// Returns true if thumbnails can be generated for the given file.
+pub fn is_supported_image(file: &restic::File) -> bool {
+    let extension = Path::new(&file.name)
+        .extension()
+        .map(|ext| ext.to_string_lossy().to_ascii_lowercase())
+        .unwrap_or_default();
+    file.type_ == "file" && matches!(extension.as_str(), "png" | "jpg" | "jpeg" | "webp" | "gif")
+}
+
+// -------------------------------------------------------------------------------------------------
+
+/// A queued thumbnail request.
+struct Job {
+    generation: usize,
+    program: restic::Program,
+    location: restic::Location,
+    snapshot_id: String,
+    file: restic::File,
+    size: u32,
+    reply: tokio::sync::oneshot::Sender<Result<String, restic::Error>>,
+}
+
+// -------------------------------------------------------------------------------------------------
+
+/// Generates image thumbnails from snapshot files with a bounded worker pool and caches
+/// them on disk, keyed by repository, snapshot id, path and thumbnail size. The disk cache
+/// is limited to `MAX_CACHE_SIZE` and drops the least recently used thumbnails first.
+///
+/// Requests which got queued before the last `cancel` call are skipped and running dump
+/// processes are terminated, so the frontend can drop stale requests while scrolling.
+/// Requests which were already running when they got cancelled don't write cache files.
+pub struct ThumbnailGenerator {
+    cache_dir: PathBuf,
+    sender: Mutex<mpsc::Sender<Job>>,
+    generation: Arc<AtomicUsize>,
+}
+
+impl ThumbnailGenerator {
+    /// Create a new generator which caches thumbnails in the given directory.
+    pub fn new(cache_dir: PathBuf) -> Self {
+        if let Err(err) = fs::create_dir_all(&cache_dir) {
+            log::warn!("Failed to create thumbnail cache directory: {err}");
+        }
+        let cache_size = Arc::new(Mutex::new(Self::prune_cache(&cache_dir, MAX_CACHE_SIZE)));
+        let (sender, receiver) = mpsc::channel::<Job>();
+        let receiver = Arc::new(Mutex::new(receiver));
+        let generation = Arc::new(AtomicUsize::new(0));
+        for _ in 0..WORKER_COUNT {
+            let receiver = Arc::clone(&receiver);
+            let generation = Arc::clone(&generation);
+            let cache_dir = cache_dir.clone();
+            let cache_size = Arc::clone(&cache_size);
+            std::thread::spawn(move || loop {
+                let job = match receiver.lock() {
+                    Ok(receiver) => match receiver.recv() {
+                        Ok(job) => job,
+                        Err(_) => break, // generator got dropped
+                    },
+                    Err(_) => break,
+                };
+                let result = if job.generation != generation.load(Ordering::Acquire) {
+                    Err(restic::Error::cancelled())
+                } else {
+                    Self::generate(&cache_dir, &cache_size, &job, &generation)
+                };
+                let _ = job.reply.send(result);
+            });
+        }
+        Self {
+            cache_dir,
+            sender: Mutex::new(sender),
+            generation,
+        }
+    }
+
+    /// Get a thumbnail for the given image file as PNG data URL. Resolves when the
+    /// thumbnail got generated by a worker or fetched from the cache.
+    pub async fn thumbnail(
+        &self,
+        program: &restic::Program,
+        location: &restic::Location,
+        snapshot_id: &str,
+        file: &restic::File,
+        size: u32,
+    ) -> Result<String, restic::Error> {
+        if !is_supported_image(file) {
+            return Err(restic::Error::from(format!(
+                "Unsupported image file '{}'",
+                file.name
+            )));
+        }
+        let size = size.clamp(16, MAX_THUMBNAIL_SIZE);
+        // fast path: no need to queue cached thumbnails
+        let cache_file = Self::cache_file(&self.cache_dir, location, snapshot_id, &file.path, size);
+        if let Some(data) = Self::read_cache_file(&cache_file) {
+            return Ok(Self::data_url(&data));
+        }
+        let (reply, receiver) = tokio::sync::oneshot::channel();
+        let job = Job {
+            generation: self.generation.load(Ordering::Acquire),
+            program: program.clone(),
+            location: location.clone(),
+            snapshot_id: snapshot_id.to_string(),
+            file: file.clone(),
+            size,
+            reply,
+        };
+        self.sender
+            .lock()
+            .map_err(|err| err.to_string())?
+            .send(job)
+            .map_err(|err| err.to_string())?;
+        receiver
+            .await
+            .map_err(|err| restic::Error::from(err.to_string()))?
+    }
+
+    /// Skip all queued thumbnail requests and kill running dump processes.
+    pub fn cancel(&self) -> Result<(), restic::Error> {
+        self.generation.fetch_add(1, Ordering::AcqRel);
+        Ok(restic::terminate_all_commands_in_group(
+            THUMBNAIL_COMMAND_GROUP,
+        )?)
+    }
+
+    // Dump, decode, downsize and cache the image of the given job. Skips writing the cache
+    // file when the job got cancelled in the meantime.
+    fn generate(
+        cache_dir: &Path,
+        cache_size: &Mutex<u64>,
+        job: &Job,
+        generation: &AtomicUsize,
+    ) -> Result<String, restic::Error> {
+        let cache_file = Self::cache_file(
+            cache_dir,
+            &job.location,
+            &job.snapshot_id,
+            &job.file.path,
+            job.size,
+        );
+        if let Some(data) = Self::read_cache_file(&cache_file) {
+            return Ok(Self::data_url(&data));
+        }
+        if job.file.size > MAX_IMAGE_FILE_SIZE {
+            return Err(format!("Image file '{}' is too large", job.file.name).into());
+        }
+        log::debug!(
+            "Creating thumbnail for '{}' from snapshot '{}'...",
+            job.file.path,
+            job.snapshot_id
+        );
+        let mut image_data = Vec::with_capacity(job.file.size.max(0) as usize);
+        job.program
+            .run_streamed(
+                &job.location,
+                &["dump", &job.snapshot_id, &job.file.path],
+                restic::CommandGroup::Shared(THUMBNAIL_COMMAND_GROUP),
+            )?
+            .take(MAX_IMAGE_FILE_SIZE as u64)
+            .read_to_end(&mut image_data)?;
+        let thumbnail = image::load_from_memory(&image_data)
+            .map_err(|err| format!("Failed to decode image '{}': {err}", job.file.name))?
+            .thumbnail(job.size, job.size);
+        let mut thumbnail_data = Vec::new();
+        thumbnail
+            .write_to(
+                &mut Cursor::new(&mut thumbnail_data),
+                image::ImageFormat::Png,
+            )
+            .map_err(|err| format!("Failed to encode thumbnail: {err}"))?;
+        if job.generation != generation.load(Ordering::Acquire) {
+            return Err(restic::Error::cancelled());
+        }
+        Self::write_cache_file(cache_dir, cache_size, &cache_file, &thumbnail_data);
+        Ok(Self::data_url(&thumbnail_data))
+    }
+
+    // Read the given cache file and mark it as recently used.
+    fn read_cache_file(cache_file: &Path) -> Option<Vec<u8>> {
+        let data = fs::read(cache_file).ok()?;
+        let _ = fs::File::options()
+            .write(true)
+            .open(cache_file)
+            .and_then(|file| file.set_modified(SystemTime::now()));
+        Some(data)
+    }
+
+    // Write the given cache file and prune the cache when it got too large.
+    fn write_cache_file(cache_dir: &Path, cache_size: &Mutex<u64>, cache_file: &Path, data: &[u8]) {
+        if let Err(err) = fs::write(cache_file, data) {
+            log::warn!("Failed to write thumbnail cache file: {err}");
+            return;
+        }
+        let Ok(mut cache_size) = cache_size.lock() else {
+            return;
+        };
+        *cache_size += data.len() as u64;
+        if *cache_size > MAX_CACHE_SIZE {
+            *cache_size = Self::prune_cache(cache_dir, MAX_CACHE_SIZE / 4 * 3);
+        }
+    }
+
+    // Remove the least recently used cache files until the cache's total size is below the
+    // given max size. Returns the remaining cache size.
+    fn prune_cache(cache_dir: &Path, max_size: u64) -> u64 {
+        let Ok(entries) = fs::read_dir(cache_dir) else {
+            return 0;
+        };
+        let mut files = entries
+            .flatten()
+            .filter(|entry| entry.path().extension().is_some_and(|ext| ext == "png"))
+            .filter_map(|entry| {
+                let metadata = entry.metadata().ok()?;
+                let modified = metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH);
+                Some((modified, metadata.len(), entry.path()))
+            })
+            .collect::<Vec<_>>();
+        // keep most recently used files first
+        files.sort_by_key(|(modified, _, _)| std::cmp::Reverse(*modified));
+        let mut cache_size = 0;
+        for (_, file_size, path) in files {
+            if cache_size + file_size <= max_size {
+                cache_size += file_size;
+            } else if let Err(err) = fs::remove_file(&path) {
+                log::warn!("Failed to remove thumbnail cache file: {err}");
+            }
+        }
+        cache_size
+    }
+
+    // Thumbnail cache file path for the given repository's snapshot file and size.
+    fn cache_file(
+        cache_dir: &Path,
+        location: &restic::Location,
+        snapshot_id: &str,
+        path: &str,
+        size: u32,
+    ) -> PathBuf {
+        let mut hasher = Sha256::new();
+        hasher.update(location.prefix.as_bytes());
+        hasher.update([0]);
+        hasher.update(location.path.as_bytes());
+        hasher.update([0]);
+        hasher.update(snapshot_id.as_bytes());
+        hasher.update([0]);
+        hasher.update(path.as_bytes());
+        hasher.update(size.to_le_bytes());
+        cache_dir.join(format!("{:x}.png", hasher.finalize()))
+    }
+
+    fn data_url(png_data: &[u8]) -> String {
+        format!(
+            "data:image/png;base64,{}",
+            base64::engine::general_purpose::STANDARD.encode(png_data)
+        )
+    }
+}
+
+// -------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cache_files_are_keyed_by_repository() {
+        let cache_dir = Path::new("/cache");
+        let location = |path: &str| restic::Location {
+            path: path.to_string(),
+            ..Default::default()
+        };
+        let file = |location, snapshot_id, size| {
+            ThumbnailGenerator::cache_file(cache_dir, location, snapshot_id, "/a.png", size)
+        };
+        let (repo_a, repo_b) = (location("/repo-a"), location("/repo-b"));
+        assert_eq!(file(&repo_a, "abc", 128), file(&repo_a, "abc", 128));
+        assert_ne!(file(&repo_a, "abc", 128), file(&repo_b, "abc", 128));
+        assert_ne!(file(&repo_a, "abc", 128), file(&repo_a, "def", 128));
+        assert_ne!(file(&repo_a, "abc", 128), file(&repo_a, "abc", 256));
+    }
+
+    #[test]
+    fn prune_least_recently_used_cache_files() {
+        let cache_dir = std::env::temp_dir().join(format!(
+            "restic-browser-thumbnails-test-{}",
+            restic::new_secret_id()
+        ));
+        fs::create_dir_all(&cache_dir).unwrap();
+        let now = SystemTime::now();
+        for (index, name) in ["a", "b", "c", "d"].iter().enumerate() {
+            let path = cache_dir.join(format!("{name}.png"));
+            fs::write(&path, [0u8; 10]).unwrap();
+            let modified = now - std::time::Duration::from_secs(60 * (4 - index as u64));
+            let file = fs::File::options().write(true).open(&path).unwrap();
+            file.set_modified(modified).unwrap();
+        }
+        // reading a file marks it as recently used
+        assert!(ThumbnailGenerator::read_cache_file(&cache_dir.join("a.png")).is_some());
+
+        assert_eq!(ThumbnailGenerator::prune_cache(&cache_dir, 25), 20);
+        let mut remaining = fs::read_dir(&cache_dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().to_string())
+            .collect::<Vec<_>>();
+        remaining.sort();
+        assert_eq!(remaining, ["a.png", "d.png"]);
+
+        let cache_size = Mutex::new(20);
+        let cache_file = cache_dir.join("e.png");
+        ThumbnailGenerator::write_cache_file(&cache_dir, &cache_size, &cache_file, &[0u8; 10]);
+        assert!(cache_file.exists());
+        assert_eq!(*cache_size.lock().unwrap(), 30);
+
+        fs::remove_dir_all(&cache_dir).unwrap();
+    }
+}
@@ -1,7 +1,11 @@
 import { MobxLitElement } from "@adobe/lit-mobx";
 import { css, html } from "lit";
 import { customElement, state } from "lit/decorators.js";
+import * as mobx from "mobx";
 
+import { Notification } from "@vaadin/notification";
+
+import type { resticApp } from "./backend/app";
 import { appState } from "./states/app-state";
 
 import "./components/app-footer";
@@ -13,6 +17,7 @@ import "./components/error-message";
 
 import "@vaadin/vertical-layout";
 import "@vaadin/split-layout";
+import "@vaadin/notification";
 
 // -------------------------------------------------------------------------------------------------
 
@@ -22,6 +27,14 @@ import "@vaadin/split-layout";
 export class ResticBrowserApp extends MobxLitElement {
   @state()
   private _showLocationDialog: boolean = false;
+  // error kind of the last failed open attempt, which the location dialog should resolve
+  @state()
+  private _locationDialogErrorKind?: resticApp.ErrorKind = undefined;
+
+  // disposes the repoErrorKind reaction
+  private _repoErrorDisposer?: mobx.IReactionDisposer = undefined;
+  // disposes the requestedSnapshotError reaction
+  private _snapshotErrorDisposer?: mobx.IReactionDisposer = undefined;
 
   constructor() {
     super();
@@ -69,11 +82,42 @@ export class ResticBrowserApp extends MobxLitElement {
   connectedCallback(): void {
     super.connectedCallback();
     document.body.addEventListener("keydown", this._keyDownHandler);
+    // let the user fix wrong passwords and repository paths in the location dialog
+    this._repoErrorDisposer = mobx.reaction(
+      () => appState.repoErrorKind,
+      (errorKind) => {
+        if (errorKind === "wrongPassword" || errorKind === "repositoryNotFound") {
+          this._locationDialogErrorKind = errorKind;
+          this._showLocationDialog = true;
+        }
+      },
+    );
+    // tell the user when a requested snapshot is missing or ambiguous
+    this._snapshotErrorDisposer = mobx.reaction(
+      () => appState.requestedSnapshotError,
+      (error) => {
+        if (error) {
+          Notification.show(error, {
+            position: "middle",
+            theme: "error",
+            duration: 10000,
+          });
+        }
+      },
+    );
   }
 
   disconnectedCallback(): void {
     super.disconnectedCallback();
     document.body.removeEventListener("keydown", this._keyDownHandler);
+    if (this._repoErrorDisposer) {
+      this._repoErrorDisposer();
+      this._repoErrorDisposer = undefined;
+    }
+    if (this._snapshotErrorDisposer) {
+      this._snapshotErrorDisposer();
+      this._snapshotErrorDisposer = undefined;
+    }
   }
 
   render() {
@@ -81,12 +125,15 @@ export class ResticBrowserApp extends MobxLitElement {
       // repository location dialog
       return html`
         <restic-browser-location-dialog 
+          .errorKind=${this._locationDialogErrorKind}
           .onClose=${() => {
             this._showLocationDialog = false;
+            this._locationDialogErrorKind = undefined;
             appState.openRepository();
           }}
           .onCancel=${() => {
             this._showLocationDialog = false;
+            this._locationDialogErrorKind = undefined;
           }}>
         </restic-browser-location-dialog>
       `;
@@ -3,6 +3,156 @@ import { core } from "@tauri-apps/api";
 import type { restic } from "./restic";
 
 export namespace resticApp {
+  export type ErrorKind =
+    | "failed"
+    | "resticNotFound"
+    | "invalidLocation"
+    | "repositoryNotFound"
+    | "repositoryLocked"
+    | "wrongPassword"
+    | "passwordCommandFailed"
+    | "networkError"
+    | "cancelled"
+    | "notFound"
+    | "secretStore"
+    | "io";
+
+  export interface ResticError {
+    kind: ErrorKind;
+    message: string;
+    exitCode?: number;
+    stderr?: string;
+  }
+
+  export function isResticError(err: unknown): err is ResticError {
+    return typeof err === "object" && err !== null && "kind" in err && "message" in err;
+  }
+
+  export interface FilePreview {
+    // base64 encoded leading bytes, empty for text files
+    data: string;
+    text?: string;
+    encoding?: string;
+    mimeType: string;
+    isBinary: boolean;
+    truncated: boolean;
+  }
+
+  export interface FileMetadata {
+    size: number;
+    mode?: number;
+    mtime?: string;
+  }
+
+  export interface DiffLine {
+    tag: "equal" | "delete" | "insert";
+    oldIndex?: number;
+    newIndex?: number;
+    content: string;
+  }
+
+  export interface DiffHunk {
+    oldStart: number;
+    oldLines: number;
+    newStart: number;
+    newLines: number;
+    lines: DiffLine[];
+  }
+
+  export type ContentDiff =
+    | { kind: "identical" }
+    | {
+        kind: "text";
+        unifiedDiff: string;
+        hunks: DiffHunk[];
+        insertions: number;
+        deletions: number;
+      }
+    | {
+        kind: "binary";
+        firstDifference: number;
+        differingBytes: number;
+        oldSize: number;
+        newSize: number;
+      }
+    | { kind: "tooLarge"; oldSize: number; newSize: number }
+    | { kind: "added"; newSize: number }
+    | { kind: "deleted"; oldSize: number }
+    | { kind: "notAFile"; oldType?: string; newType?: string };
+
+  export interface LocalFileComparison {
+    localPath: string;
+    snapshot: FileMetadata;
+    local?: FileMetadata;
+    content?: ContentDiff;
+  }
+
+  export interface ProfileLocation {
+    name: string;
+    location: restic.Location;
+  }
+
+  export interface LocationPreset {
+    id: string;
+    name: string;
+    location: restic.Location;
+  }
+
+  export type ExportSecrets = "strip" | "keychainReferences";
+
+  export type DiagnosticCheck =
+    | "binaryFound"
+    | "backendReachable"
+    | "repositoryExists"
+    | "passwordCorrect"
+    | "repositoryVersion"
+    | "lockWritable";
+
+  export interface DiagnosticStep {
+    check: DiagnosticCheck;
+    status: "passed" | "warning" | "failed" | "skipped";
+    message: string;
+  }
+
+  export interface LocationDiagnostics {
+    success: boolean;
+    steps: DiagnosticStep[];
+    repositoryVersion?: number;
+    error?: ResticError;
+  }
+
+  export interface JobInfo {
+    id: number;
+    repository: string;
+    command: string;
+    commandGroup?: string;
+    priority: "interactive" | "normal" | "background";
+    status: "queued" | "running" | "parked";
+    queuedAt: number;
+    startedAt?: number;
+  }
+
+  export interface OperationRecord {
+    timestamp: string;
+    user: string;
+    repository: string;
+    command: string;
+    args: string[];
+    durationMs: number;
+    status: "succeeded" | "failed" | "cancelled";
+    exitCode?: number;
+    error?: string;
+    bytesTransferred?: number;
+  }
+
+  export function getLogPath(): Promise<string> {
+    return core.invoke<string>("get_log_path");
+  }
+
+  export function openLogFolder(): Promise<void> {
+    return core.invoke<void>("open_log_folder");
+  }
+
   export function supportedRepoLocationTypes(): Promise<restic.RepositoryLocationType[]> {
     return core.invoke<restic.RepositoryLocationType[]>("supported_repo_location_types");
   }
@@ -11,10 +161,84 @@ export namespace resticApp {
     return core.invoke<restic.Location>("default_repo_location");
   }
 
+  // name of the event which notifies about new pending open requests (deep links)
+  export const OPEN_REQUEST_EVENT = "open-request";
+
+  export interface ResolvedOpenRequest {
+    presetId?: string;
+    location?: restic.Location;
+    snapshot?: string;
+    path?: string;
+    hosts: string[];
+    tags: string[];
+  }
+
+  export function resolveOpenRequest(): Promise<ResolvedOpenRequest | null> {
+    return core.invoke<ResolvedOpenRequest | null>("resolve_open_request");
+  }
+
   export function openFileOrUrl(path: string): Promise<void> {
     return core.invoke<void>("open_file_or_url", { path });
   }
 
+  export function storeSecret(secret: string, id?: string): Promise<string> {
+    return core.invoke<string>("store_secret", { id, secret });
+  }
+
+  export function hasSecret(id: string): Promise<boolean> {
+    return core.invoke<boolean>("has_secret", { id });
+  }
+
+  export function deleteSecret(id: string): Promise<void> {
+    return core.invoke<void>("delete_secret", { id });
+  }
+
+  export function loadProfileLocations(file?: string): Promise<ProfileLocation[]> {
+    return core.invoke<ProfileLocation[]>("load_profile_locations", { file });
+  }
+
+  export function getLocationPresets(): Promise<LocationPreset[]> {
+    return core.invoke<LocationPreset[]>("get_location_presets");
+  }
+
+  export function addLocationPreset(
+    name: string,
+    location: restic.Location,
+  ): Promise<LocationPreset> {
+    return core.invoke<LocationPreset>("add_location_preset", { name, location });
+  }
+
+  export function updateLocationPreset(preset: LocationPreset): Promise<void> {
+    return core.invoke<void>("update_location_preset", { preset });
+  }
+
+  export function removeLocationPreset(id: string): Promise<void> {
+    return core.invoke<void>("remove_location_preset", { id });
+  }
+
+  export function reorderLocationPresets(ids: string[]): Promise<void> {
+    return core.invoke<void>("reorder_location_presets", { ids });
+  }
+
+  export function locationPresetsWithCommands(file: string): Promise<string[]> {
+    return core.invoke<string[]>("location_presets_with_commands", { file });
+  }
+
+  export function importLocationPresets(
+    file: string,
+    allowCommands: boolean,
+  ): Promise<LocationPreset[]> {
+    return core.invoke<LocationPreset[]>("import_location_presets", { file, allowCommands });
+  }
+
+  export function exportLocationPresets(
+    file: string,
+    secrets: ExportSecrets,
+    ids?: string[],
+  ): Promise<void> {
+    return core.invoke<void>("export_location_presets", { file, ids, secrets });
+  }
+
   export function verifyResticPath(): Promise<void> {
     return core.invoke<void>("verify_restic_path");
   }
@@ -23,6 +247,10 @@ export namespace resticApp {
     return core.invoke<void>("open_repository", { location });
   }
 
+  export function testLocation(location: restic.Location): Promise<LocationDiagnostics> {
+    return core.invoke<LocationDiagnostics>("test_location", { location });
+  }
+
   export function getSnapshots(): Promise<Array<restic.Snapshot>> {
     return core.invoke<Array<restic.Snapshot>>("get_snapshots", { location });
   }
@@ -39,6 +267,63 @@ export namespace resticApp {
     return core.invoke<string>("dump_file_to_temp", { snapshotId, file });
   }
 
+  export function fileStreamUrl(snapshotId: string, file: restic.File): string {
+    const url = core.convertFileSrc(snapshotId + file.path, "restic");
+    return file.type === "file" && file.size !== undefined ? `${url}?size=${file.size}` : url;
+  }
+
+  export function previewFile(
+    snapshotId: string,
+    file: restic.File,
+    maxBytes: number,
+  ): Promise<FilePreview> {
+    return core.invoke<FilePreview>("preview_file", { snapshotId, file, maxBytes });
+  }
+
+  export function getThumbnail(
+    snapshotId: string,
+    file: restic.File,
+    size: number,
+  ): Promise<string> {
+    return core.invoke<string>("get_thumbnail", { snapshotId, file, size });
+  }
+
+  export function cancelThumbnails(): Promise<void> {
+    return core.invoke<void>("cancel_thumbnails");
+  }
+
+  export function listJobs(): Promise<JobInfo[]> {
+    return core.invoke<JobInfo[]>("list_jobs");
+  }
+
+  export function getOperationHistory(limit?: number): Promise<OperationRecord[]> {
+    return core.invoke<OperationRecord[]>("get_operation_history", { limit });
+  }
+
+  export function exportOperationHistory(file: string): Promise<void> {
+    return core.invoke<void>("export_operation_history", { file });
+  }
+
+  export function compareWithLocal(
+    snapshotId: string,
+    file: restic.File,
+    localPath?: string,
+  ): Promise<LocalFileComparison> {
+    return core.invoke<LocalFileComparison>("compare_with_local", {
+      snapshotId,
+      file,
+      localPath,
+    });
+  }
+
+  export function diffFileContents(
+    snapshotA: string,
+    snapshotB: string,
+    path: string,
+  ): Promise<ContentDiff> {
+    return core.invoke<ContentDiff>("diff_file_contents", { snapshotA, snapshotB, path });
+  }
+
   export function restoreFile(snapshotId: string, file: restic.File): Promise<string> {
     return core.invoke<string>("restore_file", { snapshotId, file });
   }
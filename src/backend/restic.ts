@@ -30,21 +30,57 @@ export namespace restic {
   export class EnvValue {
     name: string;
     value: string;
+    secretId?: string;
 
     constructor(source: any = {}) {
       if ("string" === typeof source) source = JSON.parse(source);
       this.name = source["name"];
       this.value = source["value"];
+      this.secretId = source["secretId"] || undefined;
     }
   }
 
+  export class ExtendedOption {
+    name: string;
+    value: string;
+
+    constructor(source: any = {}) {
+      if ("string" === typeof source) source = JSON.parse(source);
+      this.name = source["name"];
+      this.value = source["value"];
+    }
+  }
+
+  export class CommandPolicy {
+    timeoutSecs: number;
+    retries: number;
+    retryDelayMs: number;
+
+    constructor(source: any = {}) {
+      if ("string" === typeof source) source = JSON.parse(source);
+      this.timeoutSecs = source["timeoutSecs"] ?? 300;
+      this.retries = source["retries"] ?? 2;
+      this.retryDelayMs = source["retryDelayMs"] ?? 1000;
+    }
+  }
+
+  export type PasswordStrategy = "environment" | "pipe" | "helper";
+
   export class Location {
     prefix: string;
     path: string;
     credentials: EnvValue[];
     allowEmptyPassword: boolean;
     password: string;
+    passwordSecretId?: string;
+    passwordStrategy: PasswordStrategy;
+    passwordCommand?: string;
     insecureTls: boolean;
+    options: ExtendedOption[];
+    extraArgs: string;
+    extraEnvs: EnvValue[];
+    commandPolicy: CommandPolicy;
+    nativeReader: boolean;
 
     constructor(source: any = {}) {
       if ("string" === typeof source) source = JSON.parse(source);
@@ -54,7 +90,15 @@ export namespace restic {
       this.allowEmptyPassword =
         source["allowEmptyPassword"] !== undefined ? source["allowEmptyPassword"] : false;
       this.password = source["password"] || "";
+      this.passwordSecretId = source["passwordSecretId"] || undefined;
+      this.passwordStrategy = source["passwordStrategy"] || "environment";
+      this.passwordCommand = source["passwordCommand"] || undefined;
       this.insecureTls = source["insecureTls"] !== undefined ? source["insecureTls"] : false;
+      this.options = this.convertValues(source["options"], ExtendedOption) || [];
+      this.extraArgs = source["extraArgs"] || "";
+      this.extraEnvs = this.convertValues(source["extraEnvs"], EnvValue) || [];
+      this.commandPolicy = new CommandPolicy(source["commandPolicy"]);
+      this.nativeReader = source["nativeReader"] !== undefined ? source["nativeReader"] : false;
     }
 
     convertValues(a: any, classs: any): any {
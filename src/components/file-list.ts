@@ -84,6 +84,19 @@ export class ResticBrowserFileList extends MobxLitElement {
         { fireImmediately: true },
       ),
     );
+    // navigate to paths requested via deep links or args
+    this._actionDisposers.push(
+      mobx.reaction(
+        () => appState.requestedPath,
+        (requestedPath) => {
+          if (requestedPath) {
+            this._setRootPath(requestedPath);
+            appState.clearRequestedPath();
+          }
+        },
+        { fireImmediately: true },
+      ),
+    );
   }
 
   disconnectedCallback(): void {
@@ -5,7 +5,8 @@ import { type CSSResultGroup, css, html, render, type TemplateResult } from "lit
 import { customElement, property, state } from "lit/decorators.js";
 import * as mobx from "mobx";
 
-import { appState } from "../states/app-state";
+import type { resticApp } from "../backend/app";
+import { appState, type PasswordStorage } from "../states/app-state";
 import { Location } from "../states/location";
 import type { LocationPreset } from "../states/location-preset";
 import type { ResticBrowserLocationProperties } from "./location-properties";
@@ -36,6 +37,11 @@ export class ResticBrowserLocationDialog extends MobxLitElement {
   @property()
   onCancel!: () => void;
 
+  // optional error of the last attempt to open the location, which the user should fix:
+  // wrong passwords get prompted again, when the location has no saved password.
+  @property()
+  errorKind?: resticApp.ErrorKind;
+
   // when true, show save preset dialog instead of main dialog
   @state()
   private _showSavePresetDialog: boolean = false;
@@ -114,6 +120,7 @@ export class ResticBrowserLocationDialog extends MobxLitElement {
     if (this._showPasswordDialog) {
       return html`
         <restic-browser-location-password-dialog 
+          .label=${this.errorKind === "wrongPassword" ? "Wrong password, please try again" : undefined}
           .onClose=${this._handlePasswordDialogClose} 
           .onCancel=${this._handlePasswordDialogCancel}
         >
@@ -222,6 +229,29 @@ export class ResticBrowserLocationDialog extends MobxLitElement {
     `;
   }
 
+  firstUpdated() {
+    // re-prompt wrong passwords or show why the last location could not be opened
+    if (
+      this.errorKind === "wrongPassword" &&
+      appState.repoLocation.path &&
+      !appState.repoLocation.hasPassword
+    ) {
+      this._handleShowPasswordDialog();
+    } else if (this.errorKind === "wrongPassword") {
+      Notification.show("Wrong repository password: please check the location's password", {
+        position: "middle",
+        theme: "error",
+        duration: 4000,
+      });
+    } else if (this.errorKind === "repositoryNotFound") {
+      Notification.show("Repository not found: please check the location's path", {
+        position: "middle",
+        theme: "error",
+        duration: 4000,
+      });
+    }
+  }
+
   private get _locationProperties(): ResticBrowserLocationProperties | undefined {
     if (this._dialogContentRoot) {
       return this._dialogContentRoot.querySelector(
@@ -245,7 +275,7 @@ export class ResticBrowserLocationDialog extends MobxLitElement {
     if (
       appState.repoLocation.path &&
       !appState.repoLocation.allowEmptyPassword &&
-      !appState.repoLocation.password
+      !appState.repoLocation.hasPassword
     ) {
       this._handleShowPasswordDialog();
       return;
@@ -274,7 +304,7 @@ export class ResticBrowserLocationDialog extends MobxLitElement {
     appState.setRepositoryLocation(preset.location);
     // ask for repo password?
     appState.setRepositoryPassword("");
-    if (appState.repoLocation.path && !appState.repoLocation.password) {
+    if (appState.repoLocation.path && !appState.repoLocation.hasPassword) {
       this._handleShowPasswordDialog();
       return;
     }
@@ -296,10 +326,20 @@ export class ResticBrowserLocationDialog extends MobxLitElement {
     this._showSavePresetDialog = true;
   }
 
-  private _handleSavePresetDialogClose(presetName: string, savePasswords: boolean): boolean {
+  private _handleSavePresetDialogClose(
+    presetName: string,
+    passwordStorage: PasswordStorage,
+  ): boolean {
     if (presetName) {
       // create new location preset from the properties
-      appState.addLocationPreset(this._newPresetLocation, presetName, savePasswords);
+      appState
+        .addLocationPreset(this._newPresetLocation, presetName, passwordStorage)
+        .catch((err) => {
+          Notification.show(`Failed to save passwords: '${err.message || err}'`, {
+            position: "bottom-center",
+            theme: "error",
+          });
+        });
       // close save preset dialog
       this._showSavePresetDialog = false;
       this._editingPreset = false;
@@ -338,6 +378,7 @@ export class ResticBrowserLocationDialog extends MobxLitElement {
     // reset repo password
     appState.setRepositoryPassword("");
     this._showPasswordDialog = false;
+    this.errorKind = undefined;
   }
 
   private _handleStartEditingPreset() {
@@ -6,6 +6,7 @@ import { type CSSResultGroup, css, html, nothing } from "lit";
 import { customElement, property } from "lit/decorators.js";
 import * as mobx from "mobx";
 
+import type { restic } from "../backend/restic";
 import { appState } from "../states/app-state";
 import { Location } from "../states/location";
 import { decodeTextData } from "../utils/text-encoding";
@@ -121,6 +122,7 @@ export class ResticBrowserLocationProperties extends MobxLitElement {
         pathLabel = "Bucket";
         break;
       case "gs":
+      case "swift":
         pathLabel = "Container";
         break;
     }
@@ -160,12 +162,15 @@ export class ResticBrowserLocationProperties extends MobxLitElement {
           return html`
               <vaadin-password-field 
                 label=${value.name}
-                required
+                .required=${!value.secretId}
+                helper-text=${value.secretId ? "Saved in the OS keychain" : ""}
                 .disabled=${!this.allowEditing}
                 value=${value.value}
                 @change=${mobx.action((event: CustomEvent) => {
                   // NB: don't trim passwords. Spaces are allowed here...
                   value.value = (event.target as HTMLInputElement).value;
+                  // entered values replace saved ones
+                  value.secretId = undefined;
                 })}
               ></vaadin-password-field>`;
         case CredentialDisplayType.Text:
@@ -209,12 +214,15 @@ export class ResticBrowserLocationProperties extends MobxLitElement {
         <vaadin-password-field
           style="width: 100%; margin-right: 4px;"
           label="Repository Password"
-          required
+          .required=${!this._location.passwordSecretId}
+          helper-text=${this._location.passwordSecretId ? "Saved in the OS keychain" : ""}
           .disabled=${!this.allowEditing}
           value=${this._location.password}
           @change=${mobx.action((event: CustomEvent) => {
             // NB: don't trim passwords. Spaces are allowed here...
             this._location.password = (event.target as HTMLInputElement).value;
+            // entered passwords replace saved ones
+            this._location.passwordSecretId = "";
           })}
         >
         </vaadin-password-field>
@@ -241,6 +249,26 @@ export class ResticBrowserLocationProperties extends MobxLitElement {
       </vaadin-horizontal-layout>
     `;
 
+    const passwordStrategy =
+      this._location.allowEmptyPassword === false
+        ? html`
+      <vaadin-select
+        label="Pass Password via"
+        helper-text="Environment variables can be read by other processes of the same user."
+        .items=${[
+          { label: "Environment variable", value: "environment" },
+          { label: "Pipe (Linux and macOS)", value: "pipe" },
+          { label: "One-shot password helper", value: "helper" },
+        ]}
+        .value=${this._location.passwordStrategy}
+        .disabled=${!this.allowEditing}
+        @change=${mobx.action((event: CustomEvent) => {
+          this._location.passwordStrategy = (event.target as HTMLInputElement)
+            .value as restic.PasswordStrategy;
+        })}
+      ></vaadin-select>`
+        : nothing;
+
     const insecureTsl =
       this._location.type !== "local"
         ? html`
@@ -258,14 +286,105 @@ export class ResticBrowserLocationProperties extends MobxLitElement {
         </vaadin-form-item>`
         : nothing;
 
+    const nativeReader =
+      this._location.type === "local"
+        ? html`
+      <vaadin-horizontal-layout>
+        <vaadin-form-item style="margin-top: 10.5px;">
+          <vaadin-checkbox 
+            id="checkbox" 
+            label="Read listings without restic (faster)"
+            .checked=${this._location.nativeReader}
+            .disabled=${!this.allowEditing}
+            @change=${mobx.action((event: CustomEvent) => {
+              this._location.nativeReader = (event.target as HTMLInputElement).checked;
+            })}
+          ></vaadin-checkbox>
+        </vaadin-form-item>`
+        : nothing;
+
+    const extendedOptions = html`
+      ${this._location.options.map(
+        (option, index) => html`
+          <vaadin-horizontal-layout style="width: 24rem">
+            <vaadin-text-field
+              style="width: 50%; margin-right: 4px;"
+              label=${index === 0 ? "Extended Options" : ""}
+              placeholder="e.g. s3.storage-class"
+              .disabled=${!this.allowEditing}
+              value=${option.name}
+              @change=${mobx.action((event: CustomEvent) => {
+                option.name = (event.target as HTMLInputElement).value.trim();
+              })}
+            ></vaadin-text-field>
+            <vaadin-text-field
+              style="width: 50%; margin-right: 4px;"
+              placeholder="value"
+              .disabled=${!this.allowEditing}
+              value=${option.value}
+              @change=${mobx.action((event: CustomEvent) => {
+                option.value = (event.target as HTMLInputElement).value.trim();
+              })}
+            ></vaadin-text-field>
+            ${
+              this.allowEditing
+                ? html`<vaadin-button theme="tertiary" style="width: 4rem; margin-top: auto;"
+                    @click=${mobx.action(() => {
+                      this._location.options = this._location.options.filter((v) => v !== option);
+                    })}>Remove</vaadin-button>`
+                : nothing
+            }
+          </vaadin-horizontal-layout>`,
+      )}
+      ${
+        this.allowEditing
+          ? html`<vaadin-button theme="tertiary" style="align-self: flex-start;"
+              @click=${mobx.action(() => {
+                this._location.options = [...this._location.options, { name: "", value: "" }];
+              })}>Add Extended Option</vaadin-button>`
+          : nothing
+      }`;
+
+    const extraArgs = html`
+      <vaadin-text-field
+        label="Extra Arguments"
+        placeholder="e.g. --cacert /path/to/ca.pem --limit-download 1024"
+        .disabled=${!this.allowEditing}
+        value=${this._location.extraArgs}
+        @change=${mobx.action((event: CustomEvent) => {
+          this._location.extraArgs = (event.target as HTMLInputElement).value.trim();
+        })}
+      ></vaadin-text-field>`;
+
+    const commandTimeout = html`
+      <vaadin-text-field
+        label="Command Timeout (seconds)"
+        helper-text="Restic commands which take longer get killed, except restores. 0 disables the timeout."
+        allowed-char-pattern="[0-9]"
+        .disabled=${!this.allowEditing}
+        value=${String(this._location.commandPolicy.timeoutSecs)}
+        @change=${mobx.action((event: CustomEvent) => {
+          const timeoutSecs = Number.parseInt((event.target as HTMLInputElement).value, 10);
+          this._location.commandPolicy = {
+            ...this._location.commandPolicy,
+            timeoutSecs: Number.isNaN(timeoutSecs) ? 0 : timeoutSecs,
+          };
+        })}
+      ></vaadin-text-field>`;
+
     return html`
       <vaadin-vertical-layout id="layout">
         ${locationType}
         ${locationPath}
         ${credentials}
         ${password} 
+        ${passwordStrategy}
         ${allowEmptyPassword}
         ${insecureTsl}
+        ${nativeReader}
+        ${extendedOptions}
+        ${extraArgs}
+        ${commandTimeout}
       </vaadin-vertical-layout>
     `;
   }
@@ -354,6 +473,7 @@ export class ResticBrowserLocationProperties extends MobxLitElement {
                   // restic's `readText` impl supports BOM headers and also trims, so we should too
                   const textContent = decodeTextData(fileContent);
                   this._location.password = textContent.trim();
+                  this._location.passwordSecretId = "";
                 })(fileContent);
               })
               .catch((err) => {
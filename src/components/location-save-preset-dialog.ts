@@ -2,9 +2,12 @@ import { dialogFooterRenderer, dialogRenderer } from "@vaadin/dialog/lit.js";
 import { html, LitElement } from "lit";
 import { customElement, property } from "lit/decorators.js";
 
+import type { PasswordStorage } from "../states/app-state";
+
 import "@vaadin/dialog";
 import "@vaadin/horizontal-layout";
 import "@vaadin/button";
+import "@vaadin/select";
 
 // -------------------------------------------------------------------------------------------------
 
@@ -16,14 +19,14 @@ import "@vaadin/button";
 export class ResticBrowserLocationSavePresetDialog extends LitElement {
   // optional custom label for the password field: by default "Password".
   @property()
-  onClose!: (name: string, savePasswords: boolean) => boolean;
+  onClose!: (name: string, passwordStorage: PasswordStorage) => boolean;
 
   // called when the dialog's 'Cancel' button was invoked or the dialog got cancelled.
   @property()
   onCancel!: () => void;
 
   private _name: string = "";
-  private _savePasswords: boolean = false;
+  private _passwordStorage: PasswordStorage = "keychain";
 
   private _handledClose: boolean = false;
 
@@ -48,14 +51,19 @@ export class ResticBrowserLocationSavePresetDialog extends LitElement {
             this._name = (event.target as HTMLInputElement).value;
           }}
         ></vaadin-text-field>
-        <vaadin-checkbox 
-          id="checkbox" 
-          label="Save Password (not recommended)"
-          .checked=${this._savePasswords}
+        <vaadin-select
+          label="Passwords and Credentials"
+          style="width: 100%;"
+          .items=${[
+            { label: "Save in OS keychain", value: "keychain" },
+            { label: "Don't save password", value: "none" },
+            { label: "Save in preset file (not recommended)", value: "preset" },
+          ]}
+          .value=${this._passwordStorage}
           @change=${(event: CustomEvent) => {
-            this._savePasswords = (event.target as HTMLInputElement).checked;
+            this._passwordStorage = (event.target as HTMLInputElement).value as PasswordStorage;
           }}
-          ></vaadin-checkbox>
+        ></vaadin-select>
       </vaadin-vertical-layout>
     `;
 
@@ -89,7 +97,7 @@ export class ResticBrowserLocationSavePresetDialog extends LitElement {
   }
 
   private _handleDialogClose() {
-    if (this.onClose(this._name, this._savePasswords)) {
+    if (this.onClose(this._name, this._passwordStorage)) {
       this._handledClose = true;
     }
   }
@@ -1,14 +1,20 @@
-import { BaseDirectory, exists, readFile, writeFile } from "@tauri-apps/plugin-fs";
+import { event } from "@tauri-apps/api";
+import { ask } from "@tauri-apps/plugin-dialog";
 import * as mobx from "mobx";
 
 import { resticApp } from "../backend/app";
 import { restic } from "../backend/restic";
-import { decodeTextData, encodeTextData } from "../utils/text-encoding";
+import { findRequestedSnapshot } from "../utils/snapshot-request";
 import type { Location } from "./location";
 import { LocationPreset } from "./location-preset";
 
 // -------------------------------------------------------------------------------------------------
 
+// How passwords and credentials of new location presets are saved.
+export type PasswordStorage = "none" | "keychain" | "preset";
+
+// -------------------------------------------------------------------------------------------------
+
 /*!
  * Global application state and controller
 !*/
@@ -31,6 +37,9 @@ class AppState {
   // human readable error string, if any, set after opening the location
   @mobx.observable
   repoError: string = "";
+  // classification of the repoError, if any
+  @mobx.observable
+  repoErrorKind: resticApp.ErrorKind | "" = "";
 
   // snapshots
   @mobx.observable
@@ -38,6 +47,18 @@ class AppState {
   @mobx.observable
   selectedSnapshotID: string = "";
 
+  // snapshot id, short id or "latest" and snapshot path, as requested via deep links or args
+  @mobx.observable
+  requestedSnapshotID: string = "";
+  @mobx.observable
+  requestedPath: string = "";
+  // why the last requested snapshot could not be selected, if it could not
+  @mobx.observable
+  requestedSnapshotError: string = "";
+  // host and comma separated tag list filters for the requested "latest" snapshot
+  private _requestedHosts: string[] = [];
+  private _requestedTags: string[] = [];
+
   // loading status
   @mobx.observable
   isLoadingSnapshots: number = 0;
@@ -65,14 +86,25 @@ class AppState {
       // fetch supported location types from backend (this is fatal)
       this.supportedLocationTypes = await resticApp.supportedRepoLocationTypes();
 
-      // fetch default location from env or arguments, and open this location
+      // fetch pending open request from deep links or arguments, if any
+      let openRequest: resticApp.ResolvedOpenRequest | null = null;
       try {
-        this.repoLocation.setFromResticLocation(await resticApp.defaultRepoLocation());
-        if (this.repoLocation.path) {
-          this.openRepository();
-        }
+        openRequest = await resticApp.resolveOpenRequest();
       } catch (err: any) {
-        console.error("Failed to fetch default location: '%s'", err.message || String(err));
+        console.error("Failed to resolve open request: '%s'", err.message || String(err));
+      }
+
+      // fetch default location from env or arguments, and open this location
+      // unless the open request is going to open another location
+      if (!openRequest?.location) {
+        try {
+          this.repoLocation.setFromResticLocation(await resticApp.defaultRepoLocation());
+          if (this.repoLocation.path && !openRequest) {
+            this.openRepository();
+          }
+        } catch (err: any) {
+          console.error("Failed to fetch default location: '%s'", err.message || String(err));
+        }
       }
 
       // auto-load location presets from config dir
@@ -83,6 +115,24 @@ class AppState {
       }
       // auto-save location presets to config dir on changes
       this._autoSavePresets();
+
+      // apply open request, now that presets are loaded
+      if (openRequest) {
+        this._applyOpenRequest(openRequest);
+      }
+      // handle open requests from deep links or other app instances
+      await event.listen(resticApp.OPEN_REQUEST_EVENT, () => {
+        resticApp
+          .resolveOpenRequest()
+          .then((request) => {
+            if (request) {
+              this._applyOpenRequest(request);
+            }
+          })
+          .catch((err) => {
+            console.error("Failed to resolve open request: '%s'", err.message || String(err));
+          });
+      });
     })().catch((err) => {
       console.error("Failed to initialize appState: '%s'", err.message || String(err));
     });
@@ -98,14 +148,69 @@ class AppState {
     this.selectedLocationPreset = locationPreset;
   }
 
-  // add a new location preset from the given location with the given name
-  @mobx.action
-  addLocationPreset(location: Location, displayName: string, savePasswords: boolean) {
+  // add a new location preset from the given location with the given name.
+  // passwords and credentials are dropped, saved in the backend's secret store (OS keychain)
+  // or saved as plain text in the preset, depending on \param passwordStorage.
+  async addLocationPreset(
+    location: Location,
+    displayName: string,
+    passwordStorage: PasswordStorage,
+  ): Promise<void> {
     const newPreset = new LocationPreset();
     newPreset.name = displayName;
-    newPreset.location.setFromOtherLocation(location, savePasswords);
-    this.locationPresets.push(newPreset);
-    this.selectedLocationPreset = newPreset;
+    newPreset.location.setFromOtherLocation(location, passwordStorage !== "none");
+    if (passwordStorage === "keychain") {
+      await this._moveSecretsToSecretStore(newPreset.location);
+    }
+    mobx.runInAction(() => {
+      this.locationPresets.push(newPreset);
+      this.selectedLocationPreset = newPreset;
+    });
+    // save new preset in the backend, which assigns its id
+    resticApp
+      .addLocationPreset(newPreset.name, newPreset.toJSON().location)
+      .then((preset) =>
+        mobx.runInAction(() => {
+          newPreset.id = preset.id;
+        }),
+      )
+      .catch((err) => {
+        console.error("Failed to save location preset: '%s'", err.message || String(err));
+      });
+  }
+
+  // import location presets from the given JSON or TOML file. Password commands, extra args
+  // and envs of imported presets may run arbitrary programs, so they need to be confirmed.
+  async importLocationPresets(file: string) {
+    const presetsWithCommands = await resticApp.locationPresetsWithCommands(file);
+    const allowCommands =
+      presetsWithCommands.length > 0 &&
+      (await ask(
+        "The following presets run password commands or set extra arguments or environment " +
+          `variables, which may run arbitrary programs:\n\n${presetsWithCommands.join("\n")}` +
+          "\n\nOnly keep them if you trust the presets file. Else they get removed.",
+        {
+          title: "Import Location Presets",
+          kind: "warning",
+          okLabel: "Keep",
+          cancelLabel: "Remove",
+        },
+      ));
+    const presets = await resticApp.importLocationPresets(file, allowCommands);
+    mobx.runInAction(() =>
+      this.locationPresets.push(
+        ...presets.map((presetObject) => {
+          const newPreset = new LocationPreset();
+          newPreset.fromJSON(presetObject);
+          return newPreset;
+        }),
+      ),
+    );
+  }
+
+  // export all location presets to the given JSON or TOML file
+  exportLocationPresets(file: string, secrets: resticApp.ExportSecrets): Promise<void> {
+    return resticApp.exportLocationPresets(file, secrets);
   }
 
   // remove given location preset
@@ -113,7 +218,23 @@ class AppState {
   removeLocationPreset(index: number) {
     if (index !== 0) {
       const deletingSelected = this.selectedLocationPreset === this.locationPresets[index];
-      this.locationPresets.splice(index, 1);
+      const [removedPreset] = this.locationPresets.splice(index, 1);
+      if (removedPreset.id) {
+        resticApp.removeLocationPreset(removedPreset.id).catch((err) => {
+          console.error("Failed to remove location preset: '%s'", err.message || String(err));
+        });
+      }
+      // delete saved secrets which are no longer referenced by any preset
+      const usedSecretIds = new Set(
+        this.locationPresets.flatMap((preset) => AppState._secretIds(preset.location)),
+      );
+      for (const secretId of AppState._secretIds(removedPreset.location)) {
+        if (!usedSecretIds.has(secretId)) {
+          resticApp.deleteSecret(secretId).catch((err) => {
+            console.error("Failed to delete saved secret: '%s'", err.message || String(err));
+          });
+        }
+      }
       if (deletingSelected) {
         this.selectedLocationPreset = this.locationPresets[0];
       }
@@ -138,21 +259,26 @@ class AppState {
   @mobx.action
   openRepository(): void {
     const location = new restic.Location(this.repoLocation);
-    if (!location.allowEmptyPassword && !location.password && this.repoPassword) {
+    if (!location.allowEmptyPassword && !this.repoLocation.hasPassword && this.repoPassword) {
       location.password = this.repoPassword;
     }
     ++this.isLoadingSnapshots;
     this.selectedSnapshotID = "";
     this.snapShots = [];
     this.repoError = "";
+    this.repoErrorKind = "";
     resticApp
       .openRepository(location)
       .then(() => resticApp.getSnapshots())
       .then(
         mobx.action((result) => {
           this.repoError = "";
+          this.repoErrorKind = "";
           this.snapShots = result;
-          if (!result.find((s) => s.short_id === this.selectedSnapshotID)) {
+          if (
+            !this._selectRequestedSnapshot() &&
+            !result.find((s) => s.short_id === this.selectedSnapshotID)
+          ) {
             if (result.length) {
               // select most recent
               this.selectedSnapshotID = result[result.length - 1].id;
@@ -167,6 +293,7 @@ class AppState {
       .catch(
         mobx.action((err) => {
           this.repoError = err.message || String(err);
+          this.repoErrorKind = resticApp.isResticError(err) ? err.kind : "failed";
           this.snapShots = [];
           this.selectedSnapshotID = "";
           --this.isLoadingSnapshots;
@@ -184,6 +311,12 @@ class AppState {
     this.selectedSnapshotID = id;
   }
 
+  // mark the requested path as handled (called by the file list after navigating to it)
+  @mobx.action
+  clearRequestedPath(): void {
+    this.requestedPath = "";
+  }
+
   // fetch files at \param rootPath in the selected snapshot
   @mobx.action
   fetchFiles(rootPath: string): Promise<restic.File[]> {
@@ -343,46 +476,138 @@ class AppState {
     });
   }
 
-  // load presets from config file
-  private async _autoLoadPresets() {
-    if (await exists("presets.json", { baseDir: BaseDirectory.AppConfig })) {
-      const fileContent = await readFile("presets.json", {
-        baseDir: BaseDirectory.AppConfig,
-      });
-      const textContent = decodeTextData(fileContent);
-      const presetsObject = JSON.parse(textContent);
-      if (!Array.isArray(presetsObject)) {
-        throw "Content is not an array";
+  // select the requested preset or location, snapshot and path from a resolved open request
+  @mobx.action
+  private _applyOpenRequest(request: resticApp.ResolvedOpenRequest): void {
+    this.requestedSnapshotID = request.snapshot || "";
+    this.requestedSnapshotError = "";
+    this.requestedPath = request.path || "";
+    this._requestedHosts = request.hosts || [];
+    this._requestedTags = request.tags || [];
+    if (request.location) {
+      const preset = this.locationPresets.find((p) => p.id && p.id === request.presetId);
+      if (preset) {
+        this.selectedLocationPreset = preset;
+      } else {
+        this.selectedLocationPreset = this.locationPresets[0];
+        this.repoLocation.setFromResticLocation(request.location);
       }
-      // NB: keep first entry: it is used as new location template
-      mobx.runInAction(() =>
-        this.locationPresets.push(
-          ...presetsObject.map((presetObject) => {
-            const newPreset = new LocationPreset();
-            newPreset.fromJSON(presetObject);
-            return newPreset;
-          }),
-        ),
-      );
+    } else if (this.snapShots.length && this.isLoadingSnapshots === 0) {
+      // repository is already open: only select the requested snapshot
+      this._selectRequestedSnapshot();
+      return;
     }
+    if (this.repoLocation.path) {
+      this.openRepository();
+    }
+  }
+
+  // select and reset the requested snapshot: "latest" or a (short) id prefix.
+  // selects nothing and sets requestedSnapshotError when no single snapshot matches, instead
+  // of falling back to some other snapshot.
+  // returns false when no snapshot was requested.
+  @mobx.action
+  private _selectRequestedSnapshot(): boolean {
+    const requestedID = this.requestedSnapshotID;
+    if (!requestedID) {
+      return false;
+    }
+    this.requestedSnapshotID = "";
+    const { snapshot, error } = findRequestedSnapshot(
+      this.snapShots,
+      requestedID,
+      this._requestedHosts,
+      this._requestedTags,
+    );
+    if (!snapshot) {
+      console.warn(error);
+      // the requested path belongs to the missing snapshot
+      this.requestedPath = "";
+    }
+    this.requestedSnapshotError = error ?? "";
+    this.selectedSnapshotID = snapshot?.id ?? "";
+    return true;
+  }
+
+  // store the given location's password and credentials in the backend's secret store
+  // and replace them with secret ids
+  private async _moveSecretsToSecretStore(location: Location): Promise<void> {
+    const password = location.password
+      ? await resticApp.storeSecret(location.password)
+      : location.passwordSecretId;
+    const credentials = await Promise.all(
+      location.credentials.map(async (credential) =>
+        credential.value
+          ? {
+              name: credential.name,
+              value: "",
+              secretId: await resticApp.storeSecret(credential.value),
+            }
+          : credential,
+      ),
+    );
+    mobx.runInAction(() => {
+      location.password = "";
+      location.passwordSecretId = password;
+      location.credentials = credentials;
+    });
+  }
+
+  // all secret store ids the given location refers to
+  private static _secretIds(location: Location): string[] {
+    return [
+      location.passwordSecretId,
+      ...location.credentials.map((credential) => credential.secretId || ""),
+      ...location.extraEnvs.map((env) => env.secretId || ""),
+    ].filter((id) => id);
+  }
+
+  // load presets from the backend's presets store
+  private async _autoLoadPresets() {
+    const presets = await resticApp.getLocationPresets();
+    // NB: keep first entry: it is used as new location template
+    mobx.runInAction(() =>
+      this.locationPresets.push(
+        ...presets.map((presetObject) => {
+          const newPreset = new LocationPreset();
+          newPreset.fromJSON(presetObject);
+          return newPreset;
+        }),
+      ),
+    );
   }
 
-  // save presets to config file
+  // save changed presets to the backend's presets store
   private _autoSavePresets() {
-    // auto-save location presets to config dir
+    // skip first entry: it is used as new location template
+    const savedPresets = new Map(
+      this.locationPresets.slice(1).map((preset) => [preset.id, JSON.stringify(preset)]),
+    );
     mobx.reaction(
-      // skip first entry: it is used as new location template
-      () => JSON.stringify(this.locationPresets.slice(1)),
+      () => this.locationPresets.slice(1).map((preset) => JSON.stringify(preset)),
       (contents) => {
-        const fileContent = encodeTextData(contents);
-        writeFile("presets.json", fileContent, {
-          baseDir: BaseDirectory.AppConfig,
-        }).catch((err) => {
-          console.error("Failed to save location presets: '%s'", err.message || String(err));
-        });
+        for (const content of contents) {
+          const preset = JSON.parse(content) as resticApp.LocationPreset;
+          // NB: presets without id are still getting added
+          if (preset.id && savedPresets.get(preset.id) !== content) {
+            savedPresets.set(preset.id, content);
+            resticApp.updateLocationPreset(preset).catch((err) => {
+              console.error("Failed to save location preset: '%s'", err.message || String(err));
+            });
+          }
+        }
       },
       { delay: 500 },
     );
+    mobx.reaction(
+      () => this.locationPresets.slice(1).map((preset) => preset.id),
+      (ids) => {
+        resticApp.reorderLocationPresets(ids.filter((id) => id)).catch((err) => {
+          console.error("Failed to reorder location presets: '%s'", err.message || String(err));
+        });
+      },
+      { delay: 500, equals: mobx.comparer.structural },
+    );
   }
 }
 
@@ -12,6 +12,10 @@ import { Location } from "./location";
  */
 
 export class LocationPreset {
+  // backend preset id, empty until the preset got saved
+  @mobx.observable
+  id: string = "";
+
   @mobx.observable
   name: string = "New Location";
 
@@ -26,6 +30,7 @@ export class LocationPreset {
   fromJSON(json: any) {
     const name = (json["name"] as string) || "Untitled Preset";
     const location = new restic.Location(json["location"]);
+    this.id = (json["id"] as string) || "";
     this.name = name;
     this.location.setFromResticLocation(location);
   }
@@ -33,6 +38,7 @@ export class LocationPreset {
   // convert to JSON
   toJSON(): any {
     return {
+      id: this.id,
       name: this.name,
       location: new restic.Location(this.location),
     };
@@ -19,8 +19,9 @@ export class Location {
   @mobx.observable
   path: string = "";
 
+  // credentials with a `secretId` are resolved from the backend's secret store
   @mobx.observable
-  credentials: { name: string; value: string }[] = [];
+  credentials: { name: string; value: string; secretId?: string }[] = [];
 
   @mobx.observable
   allowEmptyPassword: boolean = false;
@@ -28,9 +29,44 @@ export class Location {
   @mobx.observable
   password: string = "";
 
+  // id of the password in the backend's secret store: used instead of `password` when set
+  @mobx.observable
+  passwordSecretId: string = "";
+
+  // how the password gets passed to restic: see restic.Location.passwordStrategy
+  @mobx.observable
+  passwordStrategy: restic.PasswordStrategy = "environment";
+
+  // optional command which prints the password, run by restic for each command
+  @mobx.observable
+  passwordCommand: string = "";
+
   @mobx.observable
   insecureTls: boolean = false;
 
+  // extended backend options, passed as `-o name=value` to restic
+  @mobx.observable
+  options: { name: string; value: string }[] = [];
+
+  // extra restic global flags and env variables
+  @mobx.observable
+  extraArgs: string = "";
+
+  @mobx.observable
+  extraEnvs: { name: string; value: string; secretId?: string }[] = [];
+
+  // timeout and retry policy for restic commands
+  @mobx.observable
+  commandPolicy: { timeoutSecs: number; retries: number; retryDelayMs: number } = {
+    timeoutSecs: 300,
+    retries: 2,
+    retryDelayMs: 1000,
+  };
+
+  // list snapshots and files of local repositories without running restic
+  @mobx.observable
+  nativeReader: boolean = false;
+
   constructor() {
     mobx.makeObservable(this);
 
@@ -44,6 +80,11 @@ export class Location {
     );
   }
 
+  // true when the location has a password, a saved password or a password command
+  get hasPassword(): boolean {
+    return Boolean(this.password || this.passwordSecretId || this.passwordCommand);
+  }
+
   // get repository path with possibly cloaked password string in REST or SFTP paths.
   // should only used for display purposes, as this malforms the path.
   get clokedPath(): string {
@@ -82,7 +123,15 @@ export class Location {
     this.credentials = [];
     this.allowEmptyPassword = false;
     this.password = "";
+    this.passwordSecretId = "";
+    this.passwordStrategy = "environment";
+    this.passwordCommand = "";
     this.insecureTls = false;
+    this.options = [];
+    this.extraArgs = "";
+    this.extraEnvs = [];
+    this.commandPolicy = { timeoutSecs: 300, retries: 2, retryDelayMs: 1000 };
+    this.nativeReader = false;
   }
 
   // set location properties from some other Location
@@ -91,17 +140,32 @@ export class Location {
     this.type = other.type;
     this.prefix = other.prefix;
     this.path = other.path;
-    this.credentials = Array.from(other.credentials);
+    this.credentials = other.credentials.map((credential) => ({ ...credential }));
     this.allowEmptyPassword = other.allowEmptyPassword;
     this.password = copyPasswords ? other.password : "";
+    this.passwordSecretId = copyPasswords ? other.passwordSecretId : "";
+    this.passwordStrategy = other.passwordStrategy;
+    this.passwordCommand = other.passwordCommand;
     this.insecureTls = other.insecureTls;
+    // skip unfinished options, as added in the location dialog
+    this.options = other.options
+      .filter((option) => option.name !== "")
+      .map((option) => ({ ...option }));
+    this.extraArgs = other.extraArgs;
+    this.extraEnvs = other.extraEnvs.map((env) => ({ ...env }));
+    this.commandPolicy = { ...other.commandPolicy };
+    this.nativeReader = other.nativeReader;
   }
 
   // set location properties from a restic.Location
   @mobx.action
   setFromResticLocation(location: restic.Location): void {
-    // find matching location type
-    const locationInfo = appState.supportedLocationTypes.find((v) => v.prefix === location.prefix);
+    // find matching location type: S3-compatible endpoints share the s3 prefix with Amazon S3
+    const isCustomEndpoint = location.prefix === "s3" && /^https?:\/\//.test(location.path);
+    const locationInfo =
+      appState.supportedLocationTypes.find(
+        (v) => v.prefix === location.prefix && (v.type === "s3compatible") === isCustomEndpoint,
+      ) || appState.supportedLocationTypes.find((v) => v.prefix === location.prefix);
     if (!locationInfo) {
       throw Error(`Unexpected/unsupported location prefix: '${location.prefix}'`);
     }
@@ -110,7 +174,15 @@ export class Location {
     this.path = location.path;
     this.allowEmptyPassword = location.allowEmptyPassword;
     this.password = location.password;
+    this.passwordSecretId = location.passwordSecretId || "";
+    this.passwordStrategy = location.passwordStrategy;
+    this.passwordCommand = location.passwordCommand || "";
     this.insecureTls = location.insecureTls;
+    this.options = location.options.map((option) => ({ ...option }));
+    this.extraArgs = location.extraArgs;
+    this.extraEnvs = location.extraEnvs.map((env) => ({ ...env }));
+    this.commandPolicy = { ...location.commandPolicy };
+    this.nativeReader = location.nativeReader;
     this._setPrefixFromType();
     this._setCredentialsFromType();
     // set all required credentials as well, if they are valid
@@ -119,6 +191,7 @@ export class Location {
       const locationValue = this.credentials.find((v) => v.name === credential);
       if (defaultValue && locationValue) {
         locationValue.value = defaultValue.value;
+        locationValue.secretId = defaultValue.secretId;
       }
     }
   }
@@ -0,0 +1,52 @@
+import assert from "node:assert/strict";
+import { test } from "node:test";
+
+import type { restic } from "../backend/restic";
+import { findRequestedSnapshot } from "./snapshot-request.ts";
+
+// -------------------------------------------------------------------------------------------------
+
+function snapshot(id: string, hostname: string, tags: string[] = []): restic.Snapshot {
+  return { id, short_id: id.slice(0, 8), time: "", paths: [], tags, hostname, username: "" };
+}
+
+const snapshots = [
+  snapshot("abc12345aaaa", "nas", ["daily"]),
+  snapshot("abc12345bbbb", "laptop", ["daily", "home"]),
+  snapshot("def67890cccc", "nas", ["weekly"]),
+];
+
+test("select snapshots by unique id prefix", () => {
+  assert.equal(findRequestedSnapshot(snapshots, "def").snapshot?.id, "def67890cccc");
+  assert.equal(findRequestedSnapshot(snapshots, "abc12345b").snapshot?.id, "abc12345bbbb");
+  assert.equal(findRequestedSnapshot(snapshots, "abc12345bbbb").snapshot?.id, "abc12345bbbb");
+});
+
+test("select nothing for ambiguous id prefixes", () => {
+  const result = findRequestedSnapshot(snapshots, "abc12345");
+  assert.equal(result.snapshot, undefined);
+  assert.match(result.error || "", /ambiguous/);
+});
+
+test("select nothing for unknown ids", () => {
+  const result = findRequestedSnapshot(snapshots, "fff");
+  assert.equal(result.snapshot, undefined);
+  assert.match(result.error || "", /Can't find/);
+});
+
+test("select the latest snapshot matching hosts and tags", () => {
+  assert.equal(findRequestedSnapshot(snapshots, "latest").snapshot?.id, "def67890cccc");
+  assert.equal(
+    findRequestedSnapshot(snapshots, "latest", ["laptop", "pc"]).snapshot?.id,
+    "abc12345bbbb",
+  );
+  assert.equal(
+    findRequestedSnapshot(snapshots, "latest", [], ["weekly", "daily,home"]).snapshot?.id,
+    "def67890cccc",
+  );
+  assert.equal(
+    findRequestedSnapshot(snapshots, "latest", ["nas"], ["daily"]).snapshot?.id,
+    "abc12345aaaa",
+  );
+  assert.equal(findRequestedSnapshot(snapshots, "latest", ["pc"]).snapshot, undefined);
+});
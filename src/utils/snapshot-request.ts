@@ -0,0 +1,47 @@
+import type { restic } from "../backend/restic";
+
+// -------------------------------------------------------------------------------------------------
+
+// Result of resolving a requested snapshot: the matching snapshot or why none matched.
+export type SnapshotRequestResult =
+  | { snapshot: restic.Snapshot; error?: undefined }
+  | { snapshot?: undefined; error: string };
+
+// Find the requested snapshot: "latest" or a (short) id prefix. Like in restic, host and
+// comma separated tag list filters only apply to "latest", and id prefixes must be unique.
+// Returns an error instead of falling back to some other snapshot when no single snapshot
+// matches. \param snapshots are expected to be sorted by time, oldest first.
+export function findRequestedSnapshot(
+  snapshots: restic.Snapshot[],
+  requestedID: string,
+  hosts: string[] = [],
+  tags: string[] = [],
+): SnapshotRequestResult {
+  if (requestedID === "latest") {
+    const tagLists = tags.map((tags) => tags.split(",").filter((tag) => tag));
+    const snapshot = snapshots
+      .filter((s) => !hosts.length || hosts.includes(s.hostname))
+      .filter(
+        (s) =>
+          !tagLists.length ||
+          tagLists.some((tags) => tags.every((tag) => (s.tags || []).includes(tag))),
+      )
+      .pop();
+    if (!snapshot) {
+      return { error: "Can't find a snapshot matching the requested hosts and tags" };
+    }
+    return { snapshot };
+  }
+  const matches = snapshots.filter((s) => s.id.startsWith(requestedID));
+  if (!matches.length) {
+    return { error: `Can't find requested snapshot '${requestedID}'` };
+  }
+  if (matches.length > 1) {
+    return {
+      error:
+        `Requested snapshot '${requestedID}' is ambiguous: it matches ` +
+        `${matches.length} snapshots`,
+    };
+  }
+  return { snapshot: matches[0] };
+}